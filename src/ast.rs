@@ -2,14 +2,80 @@
 
 /// AST for source code.
 pub mod src {
+    use crate::span::Span;
+
     /// A module.
     #[derive(Debug)]
     pub struct Module {
         /// The module's name.
         pub name: String,
 
+        /// The module's import list.
+        pub imports: Vec<Import>,
+
         /// The module's declaration list.
         pub decls: Vec<Decl>,
+
+        /// The module's initialisation statement sequence.
+        pub body: Vec<Stmt>,
+
+        /// The module's pragmas, in source order. A pragma applies from where
+        /// it is until a later pragma changes it.
+        pub pragmas: Vec<Pragma>,
+    }
+
+    /// A compiler pragma, `(*$ text *)`.
+    #[derive(Debug, PartialEq)]
+    pub struct Pragma {
+        /// The pragma's text, without the surrounding white space.
+        pub text: String,
+
+        /// Where the pragma is.
+        pub span: Span,
+    }
+
+    impl Pragma {
+        /// The setting the pragma makes, or `None` if it isn't known.
+        pub fn setting(&self) -> Option<Setting> {
+            match self.text.as_str() {
+                "ASSERT+" => Some(Setting::Assert(true)),
+                "ASSERT-" => Some(Setting::Assert(false)),
+                _ => None,
+            }
+        }
+    }
+
+    /// A setting a pragma makes.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Setting {
+        /// Whether `ASSERT` is checked, set by `(*$ASSERT+*)` and
+        /// `(*$ASSERT-*)`.
+        Assert(bool),
+    }
+
+    /// Whether an `ASSERT` at `span` is checked, as set by the last
+    /// `ASSERT` pragma before it. It is if there's none.
+    pub fn asserts_checked(pragmas: &[Pragma], span: Span) -> bool {
+        pragmas
+            .iter()
+            .take_while(|pragma| pragma.span.offset < span.offset)
+            .filter_map(Pragma::setting)
+            .map(|Setting::Assert(checked)| checked)
+            .last()
+            .unwrap_or(true)
+    }
+
+    /// An entry in a module's import list.
+    #[derive(Debug)]
+    pub struct Import {
+        /// The name the imported module is referred to by in the importer.
+        pub alias: String,
+
+        /// The imported module's name.
+        pub name: String,
+
+        /// Where the import is named in the import list.
+        pub span: Span,
     }
 
     /// All possible declarations.
@@ -17,6 +83,38 @@ pub mod src {
     pub enum Decl {
         /// A procedure declaration.
         Proc(Proc),
+
+        /// A variable declaration.
+        Var(VarDecl),
+    }
+
+    impl Decl {
+        /// Where the declaration's name is.
+        pub fn span(&self) -> Span {
+            match self {
+                Decl::Proc(proc) => proc.span,
+                Decl::Var(var) => var.span,
+            }
+        }
+    }
+
+    /// A variable declaration.
+    #[derive(Debug)]
+    pub struct VarDecl {
+        /// The variable's name.
+        pub name: String,
+
+        /// Where the variable is named in its declaration.
+        pub span: Span,
+
+        /// Whether the variable is exported.
+        pub export: bool,
+
+        /// The variable's type identifier, or its elements' if it's an array.
+        pub tid: String,
+
+        /// The variable's length, if it's an array.
+        pub len: Option<i64>,
     }
 
     /// A procedure declaration.
@@ -25,14 +123,214 @@ pub mod src {
         /// The procedure's name.
         pub name: String,
 
-        /// The line the procedure is defined on.
-        pub line: usize,
+        /// Where the procedure is named in its heading.
+        pub span: Span,
 
         /// Whether the procedure is exported.
         pub export: bool,
 
+        /// The name the procedure is exported as, if not its own name.
+        pub export_name: Option<String>,
+
+        /// The host function implementing the procedure, if it's external.
+        pub external: Option<External>,
+
+        /// The procedure's formal parameters.
+        pub params: Vec<Param>,
+
         /// Return type identifier.
         pub tid_return: Option<String>,
+
+        /// The procedure's local variables.
+        pub locals: Vec<VarDecl>,
+
+        /// The procedure's statement sequence.
+        pub body: Vec<Stmt>,
+    }
+
+    /// The host function implementing an external procedure.
+    #[derive(Debug, PartialEq)]
+    pub struct External {
+        /// The name of the host module.
+        pub module: String,
+
+        /// The function's name in the host module.
+        pub name: String,
+    }
+
+    /// A formal parameter.
+    #[derive(Debug)]
+    pub struct Param {
+        /// The parameter's name.
+        pub name: String,
+
+        /// The parameter's type identifier.
+        pub tid: String,
+
+        /// Where the parameter is named.
+        pub span: Span,
+    }
+
+    /// All possible statements.
+    #[derive(Debug)]
+    pub enum Stmt {
+        /// An assignment to a variable.
+        Assign(Assign),
+
+        /// A procedure call.
+        Call(Call),
+
+        /// A return statement with an optional result.
+        Return(Return),
+    }
+
+    impl Stmt {
+        /// Where the statement starts.
+        pub fn span(&self) -> Span {
+            match self {
+                Stmt::Assign(assign) => assign.var.span,
+                Stmt::Call(call) => call.span,
+                Stmt::Return(stmt_return) => stmt_return.span,
+            }
+        }
+    }
+
+    /// An assignment statement.
+    #[derive(Debug)]
+    pub struct Assign {
+        /// The variable being assigned to.
+        pub var: Var,
+
+        /// The value being assigned.
+        pub expr: Expr,
+    }
+
+    /// A return statement.
+    #[derive(Debug)]
+    pub struct Return {
+        /// The value being returned, if any.
+        pub expr: Option<Expr>,
+
+        /// Where the `RETURN` keyword is.
+        pub span: Span,
+    }
+
+    /// All possible expressions.
+    #[derive(Debug)]
+    pub enum Expr {
+        /// A function procedure call.
+        Call(Call),
+
+        /// A character literal.
+        Char(Char),
+
+        /// An integer literal.
+        Integer(Integer),
+
+        /// A real literal.
+        Real(Real),
+
+        /// A string literal.
+        Str(Str),
+
+        /// A variable.
+        Var(Var),
+    }
+
+    impl Expr {
+        /// Where the expression starts.
+        pub fn span(&self) -> Span {
+            match self {
+                Expr::Call(call) => call.span,
+                Expr::Char(c) => c.span,
+                Expr::Integer(integer) => integer.span,
+                Expr::Real(real) => real.span,
+                Expr::Str(s) => s.span,
+                Expr::Var(var) => var.span,
+            }
+        }
+    }
+
+    /// A reference to a variable.
+    #[derive(Debug)]
+    pub struct Var {
+        /// The variable's name.
+        pub name: QualIdent,
+
+        /// Where the variable is referenced.
+        pub span: Span,
+    }
+
+    /// An integer literal.
+    #[derive(Debug)]
+    pub struct Integer {
+        /// The literal's value.
+        pub value: i64,
+
+        /// Where the literal is.
+        pub span: Span,
+    }
+
+    /// A real literal.
+    #[derive(Debug)]
+    pub struct Real {
+        /// The literal's value.
+        pub value: f64,
+
+        /// Where the literal is.
+        pub span: Span,
+    }
+
+    /// A string literal.
+    #[derive(Debug)]
+    pub struct Str {
+        /// The literal's characters.
+        pub value: String,
+
+        /// Where the literal is.
+        pub span: Span,
+    }
+
+    /// A character literal.
+    #[derive(Debug)]
+    pub struct Char {
+        /// The character's code.
+        pub value: u8,
+
+        /// Where the literal is.
+        pub span: Span,
+    }
+
+    /// A procedure call.
+    #[derive(Debug)]
+    pub struct Call {
+        /// The procedure being called.
+        pub proc: QualIdent,
+
+        /// The actual parameters.
+        pub args: Vec<Expr>,
+
+        /// Where the called procedure is named.
+        pub span: Span,
+    }
+
+    /// An identifier optionally qualified by a module name, e.g. `M.P`.
+    #[derive(Debug, PartialEq)]
+    pub struct QualIdent {
+        /// The module qualifier, if any.
+        pub module: Option<String>,
+
+        /// The identifier.
+        pub name: String,
+    }
+
+    impl std::fmt::Display for QualIdent {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            if let Some(module) = &self.module {
+                write!(f, "{module}.")?;
+            }
+            write!(f, "{}", self.name)
+        }
     }
 
     pub mod builder {
@@ -40,16 +338,23 @@ pub mod src {
 
         use super::*;
 
+        #[derive(Default)]
         pub struct BuilderModule {
             pub name: String,
+            pub imports: Vec<Import>,
             pub decls: Vec<Decl>,
+            pub body: Vec<Stmt>,
+            pub pragmas: Vec<Pragma>,
         }
 
         impl BuilderModule {
             pub fn new() -> Self {
                 Self {
                     name: String::new(),
+                    imports: Vec::new(),
                     decls: Vec::new(),
+                    body: Vec::new(),
+                    pragmas: Vec::new(),
                 }
             }
 
@@ -58,38 +363,77 @@ pub mod src {
                 self
             }
 
+            pub fn add_import(&mut self, alias: &str, name: &str, span: Span) -> &mut Self {
+                self.imports.push(Import {
+                    alias: alias.to_owned(),
+                    name: name.to_owned(),
+                    span,
+                });
+                self
+            }
+
             pub fn add_decl(&mut self, decl: Decl) -> &mut Self {
                 self.decls.push(decl);
                 self
             }
 
+            pub fn add_stmt(&mut self, stmt: Stmt) -> &mut Self {
+                self.body.push(stmt);
+                self
+            }
+
+            pub fn add_pragma(&mut self, pragma: Pragma) -> &mut Self {
+                self.pragmas.push(pragma);
+                self
+            }
+
             pub fn build(&mut self) -> Module {
-                let name = mem::replace(&mut self.name, String::new());
-                let decls = mem::replace(&mut self.decls, Vec::new());
-                Module { name, decls }
+                let name = mem::take(&mut self.name);
+                let imports = mem::take(&mut self.imports);
+                let decls = mem::take(&mut self.decls);
+                let body = mem::take(&mut self.body);
+                let pragmas = mem::take(&mut self.pragmas);
+                Module {
+                    name,
+                    imports,
+                    decls,
+                    body,
+                    pragmas,
+                }
             }
         }
 
+        #[derive(Default)]
         pub struct BuilderProc {
             name: String,
-            line: usize,
+            span: Span,
             export: bool,
+            export_name: Option<String>,
+            external: Option<External>,
+            params: Vec<Param>,
             tid_return: Option<String>,
+            locals: Vec<VarDecl>,
+            body: Vec<Stmt>,
         }
 
         impl BuilderProc {
             pub fn new() -> Self {
                 Self {
                     name: String::new(),
-                    line: 0,
+                    span: Span::default(),
                     export: false,
+                    export_name: None,
+                    external: None,
+                    params: Vec::new(),
                     tid_return: None,
+                    locals: Vec::new(),
+                    body: Vec::new(),
                 }
             }
 
-            pub fn set_name(&mut self, name: &str, line: usize) -> &mut Self {
+            pub fn set_name(&mut self, name: &str, span: Span) -> &mut Self {
                 self.name = name.to_owned();
-                self.line = line;
+                self.span = span;
                 self
             }
 
@@ -98,21 +442,63 @@ pub mod src {
                 self
             }
 
+            pub fn set_export_name(&mut self, export_name: &str) -> &mut Self {
+                self.export_name = Some(export_name.to_owned());
+                self
+            }
+
+            pub fn set_external(&mut self, module: &str, name: &str) -> &mut Self {
+                self.external = Some(External {
+                    module: module.to_owned(),
+                    name: name.to_owned(),
+                });
+                self
+            }
+
+            pub fn add_param(&mut self, name: &str, tid: &str, span: Span) -> &mut Self {
+                self.params.push(Param {
+                    name: name.to_owned(),
+                    tid: tid.to_owned(),
+                    span,
+                });
+                self
+            }
+
             pub fn set_tid_return(&mut self, tid_return: &str) -> &mut Self {
                 self.tid_return = Some(tid_return.to_owned());
                 self
             }
 
+            pub fn add_local(&mut self, local: VarDecl) -> &mut Self {
+                self.locals.push(local);
+                self
+            }
+
+            pub fn add_stmt(&mut self, stmt: Stmt) -> &mut Self {
+                self.body.push(stmt);
+                self
+            }
+
             pub fn build(&mut self) -> Proc {
-                let name = mem::replace(&mut self.name, String::new());
-                let line = mem::replace(&mut self.line, 0);
+                let name = mem::take(&mut self.name);
+                let span = mem::take(&mut self.span);
                 let export = mem::replace(&mut self.export, false);
-                let tid_return = mem::replace(&mut self.tid_return, None);
+                let export_name = self.export_name.take();
+                let external = self.external.take();
+                let params = mem::take(&mut self.params);
+                let tid_return = self.tid_return.take();
+                let locals = mem::take(&mut self.locals);
+                let body = mem::take(&mut self.body);
                 Proc {
                     name,
-                    line,
+                    span,
                     export,
+                    export_name,
+                    external,
+                    params,
                     tid_return,
+                    locals,
+                    body,
                 }
             }
 
@@ -122,22 +508,125 @@ pub mod src {
                 Decl::Proc(decl_proc)
             }
         }
+
+        /// Creates a call to a procedure, qualified by `module` if given.
+        #[cfg(test)]
+        pub fn call(module: Option<&str>, name: &str, args: Vec<Expr>, line: usize) -> Call {
+            let proc = QualIdent {
+                module: module.map(str::to_owned),
+                name: name.to_owned(),
+            };
+            let span = Span::at_line(line);
+            Call { proc, args, span }
+        }
+
+        /// Creates an unqualified variable expression.
+        #[cfg(test)]
+        pub fn var(name: &str, line: usize) -> Expr {
+            let name = QualIdent {
+                module: None,
+                name: name.to_owned(),
+            };
+            let span = Span::at_line(line);
+            Expr::Var(Var { name, span })
+        }
+
+        /// Creates a variable declaration.
+        #[cfg(test)]
+        pub fn var_decl(name: &str, tid: &str, export: bool, line: usize) -> Decl {
+            Decl::Var(local(name, tid, export, line))
+        }
+
+        /// Creates a variable declaration for a procedure's local variable.
+        #[cfg(test)]
+        pub fn local(name: &str, tid: &str, export: bool, line: usize) -> VarDecl {
+            VarDecl {
+                name: name.to_owned(),
+                span: Span::at_line(line),
+                export,
+                tid: tid.to_owned(),
+                len: None,
+            }
+        }
+
+        /// Creates a return statement.
+        #[cfg(test)]
+        pub fn ret(expr: Option<Expr>, line: usize) -> Stmt {
+            let span = Span::at_line(line);
+            Stmt::Return(Return { expr, span })
+        }
+
+        /// Creates an assignment to an unqualified variable.
+        #[cfg(test)]
+        pub fn assign(name: &str, expr: Expr, line: usize) -> Stmt {
+            let Expr::Var(var) = var(name, line) else {
+                unreachable!();
+            };
+            Stmt::Assign(Assign { var, expr })
+        }
+
+        /// Creates an integer literal expression.
+        #[cfg(test)]
+        pub fn integer(value: i64, line: usize) -> Expr {
+            let span = Span::at_line(line);
+            Expr::Integer(Integer { value, span })
+        }
     }
 }
 
 /// WebAssembly text format AST.
 pub mod wat {
+    /// The size of a page of memory, in bytes.
+    pub const PAGE_SIZE: u32 = 65536;
+
     /// A WAT module.
     #[derive(Debug)]
     pub struct Module {
         /// The module's name.
         pub name: String,
 
+        /// The module's imports.
+        pub imports: Vec<Import>,
+
+        /// The module's tables.
+        pub tables: Vec<Table>,
+
+        /// The module's memories.
+        pub memories: Vec<Memory>,
+
+        /// The module's globals.
+        pub globals: Vec<Global>,
+
         /// The module's functions.
         pub funcs: Vec<Func>,
 
         /// The module's exports.
         pub exports: Vec<Export>,
+
+        /// The function run when the module is instantiated, if any.
+        pub start: Option<String>,
+
+        /// The module's data segments.
+        pub data: Vec<Data>,
+    }
+
+    /// Represents a function import S-expression.
+    #[derive(Debug, PartialEq)]
+    pub struct Import {
+        /// The name of the module the function is imported from.
+        pub module: String,
+
+        /// The function's name in the module it's imported from.
+        pub name: String,
+
+        /// The identifier the function is referred to by in this module.
+        pub id: String,
+
+        /// The function's parameter types.
+        pub params: Vec<Type>,
+
+        /// The function's result.
+        pub result: Option<Type>,
     }
 
     /// A WAT function.
@@ -146,39 +635,288 @@ pub mod wat {
         /// The function's name.
         pub name: String,
 
+        /// The function's parameters.
+        pub params: Vec<Local>,
+
         /// The function's result.
         pub result: Option<Type>,
+
+        /// The function's locals, other than its parameters.
+        pub locals: Vec<Local>,
+
+        /// The function's instructions.
+        pub body: Vec<Instr>,
     }
 
-    /// Represents an export S-expression.
+    /// A named function parameter or local.
     #[derive(Debug, PartialEq)]
+    pub struct Local {
+        /// The local's name.
+        pub name: String,
+
+        /// The local's type.
+        pub t: Type,
+    }
+
+    /// A table of function references.
+    #[derive(Debug, PartialEq)]
+    pub struct Table {
+        /// The table's name.
+        pub name: String,
+
+        /// The table's initial number of elements.
+        pub min: u32,
+
+        /// The table's maximum number of elements, if limited.
+        pub max: Option<u32>,
+    }
+
+    /// A linear memory.
+    #[derive(Debug, PartialEq)]
+    pub struct Memory {
+        /// The memory's name.
+        pub name: String,
+
+        /// The memory's initial number of pages.
+        pub min: u32,
+
+        /// The memory's maximum number of pages, if limited.
+        pub max: Option<u32>,
+    }
+
+    /// A global variable.
+    #[derive(Debug, PartialEq)]
+    pub struct Global {
+        /// The global's name.
+        pub name: String,
+
+        /// The global's type.
+        pub t: Type,
+
+        /// Whether the global can be assigned to.
+        pub mutable: bool,
+
+        /// The constant instruction giving the global's initial value.
+        pub init: Instr,
+    }
+
+    /// Represents an export S-expression.
+    #[derive(Clone, Debug, PartialEq)]
     pub struct Export {
         /// The export's name.
         pub name: String,
+
+        /// What's being exported.
+        pub desc: ExportDesc,
+    }
+
+    /// A data segment, initialising part of the module's memory. The compiler
+    /// doesn't define data yet.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Data {
+        /// The address of the first byte initialised.
+        pub offset: u32,
+
+        /// The initial bytes.
+        pub bytes: Vec<u8>,
+    }
+
+    /// The kinds of things a module can export, each with its identifier.
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum ExportDesc {
+        /// A function.
+        Func(String),
+
+        /// A table. The compiler doesn't define tables, so this is only
+        /// for modules built by hand, which the linker and encoder handle.
+        Table(String),
+
+        /// A memory.
+        Memory(String),
+
+        /// A global.
+        Global(String),
     }
 
     /// WAT types.
-    #[derive(Debug, PartialEq)]
+    #[derive(Clone, Debug, PartialEq)]
     pub enum Type {
+        /// The `f64` type.
+        F64,
+
         /// The `i32` type.
         I32,
     }
 
+    /// WAT instructions. Blocks have no parameters or results.
+    #[derive(Debug, PartialEq)]
+    pub enum Instr {
+        /// Branches to the given enclosing block if the popped `i32` isn't
+        /// zero, where 0 is the innermost block.
+        BrIf(u32),
+
+        /// Calls the function with the given identifier.
+        Call(String),
+
+        /// Discards the value on top of the stack.
+        Drop,
+
+        /// Pops an `f64` value and pushes its absolute value.
+        F64Abs,
+
+        /// Pops two `f64` values and pushes their sum.
+        F64Add,
+
+        /// Pushes an `f64` constant.
+        F64Const(f64),
+
+        /// Pops an `i32` value and pushes it as an `f64`, as a signed
+        /// integer.
+        F64ConvertI32S,
+
+        /// Pops two `f64` values and pushes the first divided by the second.
+        F64Div,
+
+        /// Pops two `f64` values and pushes 1 if they're equal, or else 0.
+        F64Eq,
+
+        /// Pops an `f64` value and pushes the largest integer not greater
+        /// than it.
+        F64Floor,
+
+        /// Pops two `f64` values and pushes 1 if the first is greater than
+        /// the second, or else 0.
+        F64Gt,
+
+        /// Pops two `f64` values and pushes 1 if the first is less than the
+        /// second, or else 0.
+        F64Lt,
+
+        /// Pops two `f64` values and pushes their product.
+        F64Mul,
+
+        /// Pops two `f64` values and pushes 1 if they aren't equal, or else
+        /// 0. A NaN isn't equal to anything, itself included.
+        F64Ne,
+
+        /// Pops an `f64` value and pushes the nearest integer to it, ties
+        /// going to the even integer.
+        F64Nearest,
+
+        /// Pops an `f64` value and pushes it negated.
+        F64Neg,
+
+        /// Pops an `f64` value and pushes its square root.
+        F64Sqrt,
+
+        /// Pops two `f64` values and pushes the first minus the second.
+        F64Sub,
+
+        /// Pushes the value of the global with the given name.
+        GlobalGet(String),
+
+        /// Pops a value into the global with the given name.
+        GlobalSet(String),
+
+        /// Pops two `i32` values and pushes their sum.
+        I32Add,
+
+        /// Pops two `i32` values and pushes their bitwise and.
+        I32And,
+
+        /// Pushes an `i32` constant.
+        I32Const(i32),
+
+        /// Pops two `i32` values and pushes the first divided by the second,
+        /// as unsigned integers.
+        I32DivU,
+
+        /// Pops two `i32` values and pushes 1 if they're equal, or else 0.
+        I32Eq,
+
+        /// Pops an `i32` value and pushes 1 if it's zero, or else 0.
+        I32Eqz,
+
+        /// Pops an address and pushes the 4 bytes at the address plus the
+        /// given offset.
+        I32Load(u32),
+
+        /// Pops an address and pushes the byte at the address plus the given
+        /// offset, zero extended.
+        I32Load8U(u32),
+
+        /// Pops two `i32` values and pushes 1 if the first is less than the
+        /// second, as signed integers, or else 0.
+        I32LtS,
+
+        /// Pops two `i32` values and pushes 1 if the first is less than the
+        /// second, as unsigned integers, or else 0.
+        I32LtU,
+
+        /// Pops two `i32` values and pushes their product.
+        I32Mul,
+
+        /// Pops two `i32` values and pushes the remainder of the first
+        /// divided by the second, as unsigned integers.
+        I32RemU,
+
+        /// Pops a value and an address, and stores the value's 4 bytes at the
+        /// address plus the given offset.
+        I32Store(u32),
+
+        /// Pops a value and an address, and stores the value's low byte at
+        /// the address plus the given offset.
+        I32Store8(u32),
+
+        /// Pops two `i32` values and pushes the first minus the second.
+        I32Sub,
+
+        /// Pops an `f64` value and pushes it truncated towards zero as an
+        /// `i32`, trapping if it's NaN or the result doesn't fit.
+        I32TruncF64S,
+
+        /// Runs the instructions if the popped `i32` isn't zero.
+        If(Vec<Instr>),
+
+        /// Pushes the value of the local with the given name.
+        LocalGet(String),
+
+        /// Pops a value into the local with the given name.
+        LocalSet(String),
+
+        /// Runs the instructions, where a branch to the loop runs them again.
+        Loop(Vec<Instr>),
+
+        /// Returns from the current function.
+        Return,
+
+        /// Traps. The trap code isn't part of the WebAssembly instruction, but
+        /// is kept for the interpreter and emitted in text as a comment.
+        Unreachable(i32),
+    }
+
     pub mod builder {
         use std::mem;
 
         use super::*;
 
+        #[derive(Default)]
         pub struct BuilderFunc {
             name: String,
+            params: Vec<Local>,
             result: Option<Type>,
+            locals: Vec<Local>,
+            body: Vec<Instr>,
         }
 
         impl BuilderFunc {
             pub fn new() -> Self {
                 Self {
                     name: String::new(),
+                    params: Vec::new(),
                     result: None,
+                    locals: Vec::new(),
+                    body: Vec::new(),
                 }
             }
 
@@ -187,15 +925,41 @@ pub mod wat {
                 self
             }
 
+            pub fn add_param(&mut self, name: &str, t: Type) -> &mut Self {
+                let name = name.to_owned();
+                self.params.push(Local { name, t });
+                self
+            }
+
             pub fn set_result(&mut self, result: Option<Type>) -> &mut Self {
                 self.result = result;
                 self
             }
 
+            pub fn add_local(&mut self, name: &str, t: Type) -> &mut Self {
+                let name = name.to_owned();
+                self.locals.push(Local { name, t });
+                self
+            }
+
+            pub fn set_body(&mut self, body: Vec<Instr>) -> &mut Self {
+                self.body = body;
+                self
+            }
+
             pub fn build(&mut self) -> Func {
-                let name = mem::replace(&mut self.name, String::new());
-                let result = mem::replace(&mut self.result, None);
-                Func { name, result }
+                let name = mem::take(&mut self.name);
+                let params = mem::take(&mut self.params);
+                let result = self.result.take();
+                let locals = mem::take(&mut self.locals);
+                let body = mem::take(&mut self.body);
+                Func {
+                    name,
+                    params,
+                    result,
+                    locals,
+                    body,
+                }
             }
         }
     }
@@ -2,91 +2,1264 @@
 
 use crate::ast::{src, wat};
 use crate::error::*;
+use crate::library::{self, START_EXPORT};
+use crate::linker::DATA_ID;
+use crate::span::Span;
+use crate::suggest::did_you_mean;
+use crate::symbols::Symbols;
 use crate::table::Table;
 use crate::types::*;
 
 // Result type for parsing functions.
 pub type ResultCompile<T> = Result<T, Error>;
 
-/// Translates a Titania AST to a WAT AST.
-pub fn compile(module: &src::Module) -> ResultCompile<wat::Module> {
-    let mut table_type = create_default_type_table();
+/// The identifier of the function holding a module's initialisation body.
+pub const INIT_ID: &str = "_init";
+
+/// The identifier and export name of a module's memory.
+pub const MEMORY_ID: &str = "memory";
+
+/// Where a module's data, its strings and arrays, start in memory, after
+/// the first kilobyte, which the library modules use as scratch space.
+pub const DATA_BASE: u32 = 1024;
+
+/// The most characters an array can have. Its zeros are part of its module's
+/// data, so a module can't be too big.
+const MAX_ARRAY_LEN: i64 = 65536;
+
+/// The identifier of the function computing `ABS`, defined in modules that
+/// use it.
+const ABS_ID: &str = "_abs";
+
+/// The trap code of a failed `ASSERT` without one, as in Wirth's compiler.
+pub const ASSERT_TRAP_CODE: i32 = 7;
+
+/// Options controlling compilation.
+#[derive(Default)]
+pub struct Options {
+    /// Whether a module's initialisation body is exported as `_init` rather
+    /// than run as the start function.
+    pub export_init: bool,
+
+    /// Whether the module defines a memory, exported as `memory`.
+    pub export_memory: bool,
+
+    /// Whether identifiers can have Unicode letters and digits. This is
+    /// used when scanning the source, before compiling.
+    pub unicode_identifiers: bool,
+}
+
+/// Translates a Titania AST to a WAT AST and the symbols the module exports.
+/// Imported modules are resolved against `symbols`, which is keyed by module
+/// name. Compilation continues after an error, so that every error in the
+/// module is reported.
+pub fn compile(
+    module: &src::Module,
+    symbols: &Table<Symbols>,
+    options: &Options,
+) -> Result<(wat::Module, Symbols), Diagnostics> {
+    let mut diagnostics = Diagnostics::new();
+    let table_type = create_default_type_table();
     let mut table_proc = Table::new();
+    let mut table_module = Table::new();
+    let mut table_global = Table::new();
 
     let name = module.name.clone();
+    let mut imports: Vec<wat::Import> = Vec::new();
+    let mut memories = Vec::new();
+    let mut globals = Vec::new();
     let mut funcs = Vec::new();
-    let mut exports = Vec::new();
+    let mut exports: Vec<wat::Export> = Vec::new();
+    let mut symbols_export = Symbols::new(&name);
+
+    for import in module.imports.iter() {
+        let symbols_import = match compile_import(&mut table_module, symbols, import) {
+            Ok(symbols_import) => symbols_import,
+            Err(e) => {
+                diagnostics.push(e);
+                continue;
+            }
+        };
+        // A module imported under a second alias shares the first's imports.
+        if imports.iter().any(|i| i.module == symbols_import.module) {
+            continue;
+        }
+        for item in symbols_import.procs.items.iter() {
+            let params = item.value.params().iter().map(to_type_wat);
+            let result = item.value.t_return().map(to_type_wat).transpose()?;
+            imports.push(wat::Import {
+                module: symbols_import.module.clone(),
+                name: item.name.clone(),
+                id: import_id(&symbols_import.module, &item.name),
+                params: params.collect::<ResultCompile<_>>()?,
+                result,
+            });
+        }
+    }
+
+    // The `_init` export is only added after the declarations, and the
+    // `_start` and `memory` exports of a program using WASI only when
+    // it's linked, so their names are reserved up front to catch a
+    // declaration exported under them.
+    let export_init = options.export_init && !module.body.is_empty();
+    let mut reserved = Vec::new();
+    if export_init {
+        reserved.push(INIT_ID);
+    }
+    let wasi = module.imports.iter().any(|i| library::uses_wasi(&i.name));
+    if wasi && !module.body.is_empty() && !options.export_init {
+        reserved.push(START_EXPORT);
+    }
+    if wasi {
+        reserved.push(MEMORY_ID);
+    }
+    if options.export_memory {
+        memories.push(wat::Memory {
+            name: MEMORY_ID.to_owned(),
+            min: 1,
+            max: None,
+        });
+        exports.push(wat::Export {
+            name: MEMORY_ID.to_owned(),
+            desc: wat::ExportDesc::Memory(MEMORY_ID.to_owned()),
+        });
+    }
+
+    // String literals are laid out before compiling, each stored once with a
+    // 0X after it, so that an expression can refer to one by its offset.
+    // Arrays follow, laid out as they're declared. Each is after its length,
+    // see `reserve`.
+    let mut strings = Table::new();
+    let mut arrays = Table::new();
+    let mut data = Vec::new();
+    let bodies = module.decls.iter().filter_map(|decl| match decl {
+        src::Decl::Proc(decl_proc) => Some(&decl_proc.body),
+        src::Decl::Var(_) => None,
+    });
+    for stmts in bodies.chain([&module.body]) {
+        layout_strings(stmts, &mut strings, &mut data);
+    }
 
     for decl in module.decls.iter() {
-        let (func, export) = compile_decl(&mut table_type, &mut table_proc, &decl)?;
+        // A redefinition is still compiled, to report the errors in it, but is
+        // then dropped, along with the name it declared, so that later code is
+        // checked against the definition that's kept.
+        let redefinition = check_redefinition(&table_proc, &table_module, &table_global, decl);
+        let redefined = redefinition.is_err();
+        if let Err(e) = redefinition {
+            diagnostics.push(e);
+        }
+
+        let procs = table_proc.items.len();
+        let globals_declared = table_global.items.len();
+        let shared = Shared {
+            pragmas: &module.pragmas,
+            strings: &strings,
+            arrays: &arrays,
+        };
+        let result = compile_decl(
+            &table_type,
+            &mut table_proc,
+            &table_module,
+            &mut table_global,
+            shared,
+            decl,
+            &mut diagnostics,
+        );
+        if redefined {
+            table_proc.items.truncate(procs);
+            table_global.items.truncate(globals_declared);
+        }
+        let (definition, export) = match result {
+            Ok(_) if redefined => continue,
+            Ok(compiled) => compiled,
+            Err(e) => {
+                diagnostics.push(e);
+                continue;
+            }
+        };
+
         if let Some(export) = export {
+            if exports.iter().any(|other| other.name == export.name)
+                || reserved.contains(&export.name.as_str())
+            {
+                let tag = ErrorTag::ExportRedefinition(export.name);
+                diagnostics.push(Error::new(tag, decl.span()));
+                continue;
+            }
+            if let wat::ExportDesc::Func(id) = &export.desc
+                && let Some(t_proc) = table_proc.lookup(id)
+            {
+                symbols_export.procs.push(id, t_proc.clone());
+            }
             exports.push(export);
         }
-        funcs.push(func);
+        match definition {
+            Definition::Func(func) => funcs.push(func),
+            Definition::Array { name, len } => arrays.push(&name, reserve(&mut data, len)),
+            Definition::Global(global) => globals.push(global),
+            Definition::Import(import) => imports.push(import),
+        }
+    }
+
+    let mut start = None;
+    if !module.body.is_empty() {
+        let scope = Scope {
+            procs: &table_proc,
+            modules: &table_module,
+            globals: &table_global,
+            locals: &Table::new(),
+            strings: &strings,
+            arrays: &arrays,
+            pragmas: &module.pragmas,
+            proc: None,
+            t_return: None,
+        };
+        let mut body = Vec::new();
+        for stmt in module.body.iter() {
+            if let Err(e) = compile_stmt(&scope, stmt, &mut body) {
+                diagnostics.push(e);
+            }
+        }
+        funcs.push(
+            wat::builder::BuilderFunc::new()
+                .set_name(INIT_ID)
+                .set_body(body)
+                .build(),
+        );
+        if export_init {
+            exports.push(wat::Export {
+                name: INIT_ID.to_owned(),
+                desc: wat::ExportDesc::Func(INIT_ID.to_owned()),
+            });
+        } else {
+            start = Some(INIT_ID.to_owned());
+        }
+    }
+
+    if funcs.iter().any(|func| calls(&func.body, ABS_ID)) {
+        funcs.push(func_abs());
+    }
+
+    // The strings are addressed from a global the linker moves them with.
+    let mut segments = Vec::new();
+    if !data.is_empty() {
+        let pages = (DATA_BASE + data.len() as u32).div_ceil(wat::PAGE_SIZE);
+        match memories.first_mut() {
+            Some(memory) => memory.min = memory.min.max(pages),
+            None => memories.push(wat::Memory {
+                name: MEMORY_ID.to_owned(),
+                min: pages,
+                max: None,
+            }),
+        }
+        globals.push(wat::Global {
+            name: DATA_ID.to_owned(),
+            t: wat::Type::I32,
+            mutable: false,
+            init: wat::Instr::I32Const(DATA_BASE as i32),
+        });
+        segments.push(wat::Data {
+            offset: DATA_BASE,
+            bytes: data,
+        });
     }
 
-    Ok(wat::Module {
+    let module = wat::Module {
         name,
+        imports,
+        tables: Vec::new(),
+        memories,
+        globals,
         funcs,
         exports,
-    })
+        start,
+        data: segments,
+    };
+
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+    Ok((module, symbols_export))
+}
+
+/// Checks declarations, statements and expressions one at a time, keeping the
+/// names declared in tables that persist between checks, e.g. for a REPL.
+/// Statements and expressions are checked as if in a module body.
+#[derive(Default)]
+pub struct Checker {
+    table_proc: Table<TypeProc>,
+    table_global: Table<Type>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks declarations, adding their names if they're all correct.
+    pub fn decls(&mut self, decls: &[src::Decl]) -> Result<(), Diagnostics> {
+        let mut diagnostics = Diagnostics::new();
+        let table_type = create_default_type_table();
+        let table_module = Table::new();
+        let procs = self.table_proc.items.len();
+        let globals = self.table_global.items.len();
+
+        for decl in decls.iter() {
+            let result =
+                check_redefinition(&self.table_proc, &table_module, &self.table_global, decl)
+                    .and_then(|_| {
+                        compile_decl(
+                            &table_type,
+                            &mut self.table_proc,
+                            &table_module,
+                            &mut self.table_global,
+                            Shared {
+                                pragmas: &[],
+                                strings: &Table::new(),
+                                arrays: &Table::new(),
+                            },
+                            decl,
+                            &mut diagnostics,
+                        )
+                    });
+            if let Err(e) = result {
+                diagnostics.push(e);
+            }
+        }
+
+        if !diagnostics.is_empty() {
+            self.table_proc.items.truncate(procs);
+            self.table_global.items.truncate(globals);
+            return Err(diagnostics);
+        }
+        Ok(())
+    }
+
+    /// Checks a statement sequence.
+    pub fn stmts(&self, stmts: &[src::Stmt]) -> Result<(), Diagnostics> {
+        let mut diagnostics = Diagnostics::new();
+        self.with_scope(|scope| {
+            for stmt in stmts.iter() {
+                if let Err(e) = compile_stmt(scope, stmt, &mut Vec::new()) {
+                    diagnostics.push(e);
+                }
+            }
+        });
+
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        Ok(())
+    }
+
+    /// Checks an expression, returning its type.
+    pub fn expr(&self, expr: &src::Expr) -> Result<Type, Diagnostics> {
+        self.with_scope(|scope| compile_expr(scope, expr, &mut Vec::new()))
+            .map_err(|e| {
+                let mut diagnostics = Diagnostics::new();
+                diagnostics.push(e);
+                diagnostics
+            })
+    }
+
+    /// Calls a function with the scope of a module body.
+    fn with_scope<T>(&self, f: impl FnOnce(&Scope) -> T) -> T {
+        let scope = Scope {
+            procs: &self.table_proc,
+            modules: &Table::new(),
+            globals: &self.table_global,
+            locals: &Table::new(),
+            strings: &Table::new(),
+            arrays: &Table::new(),
+            pragmas: &[],
+            proc: None,
+            t_return: None,
+        };
+        f(&scope)
+    }
+}
+
+/// Brings an imported module's symbols into scope under the import's alias.
+fn compile_import<'a>(
+    table_module: &mut Table<&'a Symbols>,
+    symbols: &'a Table<Symbols>,
+    import: &src::Import,
+) -> ResultCompile<&'a Symbols> {
+    if table_module.lookup(&import.alias).is_some() {
+        return Error::name_redefinition(&import.alias, import.span);
+    }
+
+    let Some(symbols_import) = symbols.lookup(&import.name) else {
+        let tag = ErrorTag::UndefinedModule(import.name.clone());
+        return Err(Error::new(tag, import.span));
+    };
+
+    table_module.push(&import.alias, symbols_import);
+    Ok(symbols_import)
+}
+
+/// A translated declaration.
+#[derive(Debug, PartialEq)]
+enum Definition {
+    /// A function defined in the module.
+    Func(wat::Func),
+
+    /// A module level array variable, stored in the module's data.
+    Array { name: String, len: u32 },
+
+    /// A module level variable.
+    Global(wat::Global),
+
+    /// A function imported from the host.
+    Import(wat::Import),
+}
+
+/// What a module's procedures share besides names.
+#[derive(Clone, Copy)]
+struct Shared<'a> {
+    /// The module's pragmas.
+    pragmas: &'a [src::Pragma],
+
+    /// The offsets of the module's string literals from its data.
+    strings: &'a Table<u32>,
+
+    /// The offsets of the module's arrays from its data.
+    arrays: &'a Table<u32>,
+}
+
+/// The names visible to a statement sequence.
+struct Scope<'a> {
+    /// The module's procedures.
+    procs: &'a Table<TypeProc>,
+
+    /// The imported modules, by alias.
+    modules: &'a Table<&'a Symbols>,
+
+    /// The module's variables.
+    globals: &'a Table<Type>,
+
+    /// The current procedure's parameters and local variables.
+    locals: &'a Table<Type>,
+
+    /// The offsets of the module's string literals from its data.
+    strings: &'a Table<u32>,
+
+    /// The offsets of the module's arrays from its data.
+    arrays: &'a Table<u32>,
+
+    /// The module's pragmas.
+    pragmas: &'a [src::Pragma],
+
+    /// The current procedure's name, or `None` in the module body.
+    proc: Option<&'a str>,
+
+    /// The current procedure's return type, if it's a function procedure.
+    t_return: Option<&'a Type>,
+}
+
+/// Where a variable is stored.
+enum Storage {
+    Local,
+    Global,
+}
+
+/// Make sure a declaration's name isn't being re-defined.
+fn check_redefinition(
+    table_proc: &Table<TypeProc>,
+    table_module: &Table<&Symbols>,
+    table_global: &Table<Type>,
+    decl: &src::Decl,
+) -> ResultCompile<()> {
+    let name = match decl {
+        src::Decl::Proc(decl_proc) => &decl_proc.name,
+        src::Decl::Var(decl_var) => &decl_var.name,
+    };
+    if table_proc.lookup(name).is_some()
+        || table_module.lookup(name).is_some()
+        || table_global.lookup(name).is_some()
+    {
+        return Error::name_redefinition(name, decl.span());
+    }
+    Ok(())
 }
 
 fn compile_decl(
     table_type: &Table<Type>,
     table_proc: &mut Table<TypeProc>,
+    table_module: &Table<&Symbols>,
+    table_global: &mut Table<Type>,
+    shared: Shared,
     decl: &src::Decl,
-) -> ResultCompile<(wat::Func, Option<wat::Export>)> {
+    diagnostics: &mut Diagnostics,
+) -> ResultCompile<(Definition, Option<wat::Export>)> {
     match decl {
-        src::Decl::Proc(decl_proc) => compile_proc(table_type, table_proc, decl_proc),
+        src::Decl::Proc(decl_proc) => compile_proc(
+            table_type,
+            table_proc,
+            table_module,
+            table_global,
+            shared,
+            decl_proc,
+            diagnostics,
+        ),
+        src::Decl::Var(decl_var) => compile_var(table_type, table_global, decl_var),
     }
 }
 
-fn compile_proc(
+fn compile_var(
     table_type: &Table<Type>,
-    table_proc: &mut Table<TypeProc>,
-    proc: &src::Proc,
-) -> ResultCompile<(wat::Func, Option<wat::Export>)> {
-    // Make sure the proc name isn't being re-defined.
-    if let Some(_) = table_proc.lookup(&proc.name) {
-        return Error::name_redefinition(&proc.name, proc.line);
+    table_global: &mut Table<Type>,
+    var: &src::VarDecl,
+) -> ResultCompile<(Definition, Option<wat::Export>)> {
+    let t = lookup_type(table_type, &var.tid, var.span)?;
+    if let Some(len) = var.len {
+        if var.export {
+            let tag = ErrorTag::ArrayVariable(var.name.clone());
+            return Err(Error::new(tag, var.span));
+        }
+        if *t.tag() != TypeTag::Char {
+            let tag = ErrorTag::TypeMismatch {
+                expected: Type::new_char().to_string(),
+                got: t.to_string(),
+            };
+            return Err(Error::new(tag, var.span));
+        }
+        if !(1..=MAX_ARRAY_LEN).contains(&len) {
+            let tag = ErrorTag::IntegerOutOfRange(len.to_string());
+            return Err(Error::new(tag, var.span));
+        }
+        let len = len as u32;
+        table_global.push(&var.name, Type::new_fixed_char_array(len));
+        let name = var.name.clone();
+        return Ok((Definition::Array { name, len }, None));
     }
 
-    let mut builder = wat::builder::BuilderFunc::new();
-    builder.set_name(&proc.name);
+    let t_wat = to_type_wat(&t)?;
+    let global = wat::Global {
+        name: var.name.clone(),
+        init: zero(&t_wat),
+        t: t_wat,
+        mutable: true,
+    };
+    table_global.push(&var.name, t);
+
+    let export = if var.export {
+        Some(wat::Export {
+            name: var.name.clone(),
+            desc: wat::ExportDesc::Global(var.name.clone()),
+        })
+    } else {
+        None
+    };
+
+    Ok((Definition::Global(global), export))
+}
 
+fn compile_proc(
+    table_type: &Table<Type>,
+    table_proc: &mut Table<TypeProc>,
+    table_module: &Table<&Symbols>,
+    table_global: &Table<Type>,
+    shared: Shared,
+    proc: &src::Proc,
+    diagnostics: &mut Diagnostics,
+) -> ResultCompile<(Definition, Option<wat::Export>)> {
+    let params = proc
+        .params
+        .iter()
+        .map(|param| lookup_type(table_type, &param.tid, param.span))
+        .collect::<ResultCompile<Vec<_>>>()?;
     let t_return = proc
         .tid_return
         .as_ref()
-        .map(|tid| lookup_type(&table_type, &tid))
+        .map(|tid| lookup_type(table_type, tid, proc.span))
         .transpose()?;
     let t_return_wat = t_return.as_ref().map(to_type_wat).transpose()?;
+    table_proc.push(&proc.name, TypeProc::new(params.clone(), t_return.clone()));
+
+    let export = match (proc.export, &proc.export_name) {
+        (true, export_name) => Some(wat::Export {
+            name: export_name.as_ref().unwrap_or(&proc.name).clone(),
+            desc: wat::ExportDesc::Func(proc.name.clone()),
+        }),
+        (false, None) => None,
+        (false, Some(_)) => {
+            let tag = ErrorTag::UnexportedExportName(proc.name.clone());
+            return Err(Error::new(tag, proc.span));
+        }
+    };
+
+    if let Some(external) = &proc.external {
+        let import = wat::Import {
+            module: external.module.clone(),
+            name: external.name.clone(),
+            id: proc.name.clone(),
+            params: params
+                .iter()
+                .map(to_type_wat)
+                .collect::<ResultCompile<_>>()?,
+            result: t_return_wat,
+        };
+        return Ok((Definition::Import(import), export));
+    }
+
+    let mut builder = wat::builder::BuilderFunc::new();
+    builder.set_name(&proc.name);
+
+    let mut table_local = Table::new();
+    for (param, t) in proc.params.iter().zip(params) {
+        if table_local.lookup(&param.name).is_some() {
+            return Error::name_redefinition(&param.name, param.span);
+        }
+        builder.add_param(&param.name, to_type_wat(&t)?);
+        table_local.push(&param.name, t);
+    }
+
     builder.set_result(t_return_wat);
-    table_proc.push(&proc.name, TypeProc::new(t_return));
 
-    let func = builder.build();
-    let export = if proc.export {
-        Some(wat::Export {
-            name: proc.name.clone(),
-        })
-    } else {
-        None
+    for local in proc.locals.iter() {
+        if table_local.lookup(&local.name).is_some() {
+            return Error::name_redefinition(&local.name, local.span);
+        }
+        if local.export {
+            let tag = ErrorTag::ExportedLocal(local.name.clone());
+            return Err(Error::new(tag, local.span));
+        }
+        if local.len.is_some() {
+            let tag = ErrorTag::ArrayVariable(local.name.clone());
+            return Err(Error::new(tag, local.span));
+        }
+        let t = lookup_type(table_type, &local.tid, local.span)?;
+        builder.add_local(&local.name, to_type_wat(&t)?);
+        table_local.push(&local.name, t);
+    }
+
+    let scope = Scope {
+        procs: table_proc,
+        modules: table_module,
+        globals: table_global,
+        locals: &table_local,
+        strings: shared.strings,
+        arrays: shared.arrays,
+        pragmas: shared.pragmas,
+        proc: Some(&proc.name),
+        t_return: t_return.as_ref(),
+    };
+    let mut body = Vec::new();
+    for stmt in proc.body.iter() {
+        if let Err(e) = compile_stmt(&scope, stmt, &mut body) {
+            diagnostics.push(e);
+        }
+    }
+    builder.set_body(body);
+
+    if t_return.is_some() && !returns(&scope, &proc.body) {
+        let tag = ErrorTag::MissingReturn(proc.name.clone());
+        diagnostics.push(Error::new(tag, proc.span));
+    }
+
+    Ok((Definition::Func(builder.build()), export))
+}
+
+/// Translates a statement, appending its instructions to `body`.
+fn compile_stmt(scope: &Scope, stmt: &src::Stmt, body: &mut Vec<wat::Instr>) -> ResultCompile<()> {
+    match stmt {
+        src::Stmt::Assign(assign) => {
+            let t = compile_expr(scope, &assign.expr, body)?;
+            let (storage, t_var) = lookup_var(scope, &assign.var)?;
+            if let TypeTag::FixedCharArray(_) = t_var.tag() {
+                let tag = ErrorTag::ArrayVariable(assign.var.name.to_string());
+                return Err(Error::new(tag, assign.var.span));
+            }
+            check_type(&t_var, &t, &assign.expr)?;
+            let name = assign.var.name.name.clone();
+            body.push(match storage {
+                Storage::Local => wat::Instr::LocalSet(name),
+                Storage::Global => wat::Instr::GlobalSet(name),
+            });
+        }
+        src::Stmt::Call(call) => {
+            if compile_call(scope, call, body)?.is_some() {
+                body.push(wat::Instr::Drop);
+            }
+        }
+        src::Stmt::Return(stmt_return) => {
+            match (&stmt_return.expr, scope.t_return) {
+                (Some(expr), Some(t_return)) => {
+                    let t = compile_expr(scope, expr, body)?;
+                    check_type(t_return, &t, expr)?;
+                }
+                (Some(_), None) => {
+                    let tag = ErrorTag::UnexpectedReturnValue;
+                    return Err(Error::new(tag, stmt_return.span));
+                }
+                (None, Some(_)) => {
+                    let name = scope.proc.unwrap_or_default().to_owned();
+                    let tag = ErrorTag::MissingReturnValue(name);
+                    return Err(Error::new(tag, stmt_return.span));
+                }
+                (None, None) => (),
+            }
+            body.push(wat::Instr::Return);
+        }
+    }
+    Ok(())
+}
+
+/// Determines if a statement sequence always ends in a `RETURN` or `HALT`,
+/// so a function procedure can't fall off its end.
+fn returns(scope: &Scope, stmts: &[src::Stmt]) -> bool {
+    stmts
+        .iter()
+        .any(|stmt| terminates(stmt, |name| scope.procs.lookup(name).is_some()))
+}
+
+/// Determines if a statement never goes on to the next: a `RETURN`, or a call
+/// of the predeclared `HALT`. `declared` tells if the module declares a
+/// procedure with a name, which is called instead of a predeclared one.
+pub fn terminates(stmt: &src::Stmt, declared: impl Fn(&str) -> bool) -> bool {
+    match stmt {
+        src::Stmt::Assign(_) => false,
+        src::Stmt::Call(call) => {
+            call.proc.module.is_none() && call.proc.name == "HALT" && !declared("HALT")
+        }
+        src::Stmt::Return(_) => true,
+    }
+}
+
+/// Stores the string literals in a statement sequence that aren't already
+/// stored, each followed by a 0X, recording their offsets.
+fn layout_strings(stmts: &[src::Stmt], strings: &mut Table<u32>, data: &mut Vec<u8>) {
+    for stmt in stmts.iter() {
+        match stmt {
+            src::Stmt::Assign(assign) => layout_expr(&assign.expr, strings, data),
+            src::Stmt::Call(call) => {
+                for arg in call.args.iter() {
+                    layout_expr(arg, strings, data);
+                }
+            }
+            src::Stmt::Return(stmt_return) => {
+                if let Some(expr) = &stmt_return.expr {
+                    layout_expr(expr, strings, data);
+                }
+            }
+        }
+    }
+}
+
+/// Stores the string literals in an expression, as `layout_strings` does.
+fn layout_expr(expr: &src::Expr, strings: &mut Table<u32>, data: &mut Vec<u8>) {
+    match expr {
+        src::Expr::Call(call) => {
+            for arg in call.args.iter() {
+                layout_expr(arg, strings, data);
+            }
+        }
+        src::Expr::Str(s) if strings.lookup(&s.value).is_none() => {
+            let bytes = s.value.as_bytes();
+            let offset = reserve(data, bytes.len() as u32 + 1);
+            data[offset as usize..][..bytes.len()].copy_from_slice(bytes);
+            strings.push(&s.value, offset);
+        }
+        _ => (),
+    }
+}
+
+/// Reserves `len` zeros in a module's data, after a word holding `len`, so
+/// that a procedure given their address knows how many there are, and
+/// returns their offset. Each is aligned, for the word.
+fn reserve(data: &mut Vec<u8>, len: u32) -> u32 {
+    data.resize(data.len().next_multiple_of(4), 0);
+    data.extend(len.to_le_bytes());
+    let offset = data.len() as u32;
+    data.resize(data.len() + len as usize, 0);
+    offset
+}
+
+/// The instructions pushing the address of an offset in a module's data.
+fn data_address(offset: u32) -> [wat::Instr; 3] {
+    [
+        wat::Instr::GlobalGet(DATA_ID.to_owned()),
+        wat::Instr::I32Const(offset as i32),
+        wat::Instr::I32Add,
+    ]
+}
+
+/// Translates an expression, appending its instructions to `body`, and
+/// returns the expression's type.
+fn compile_expr(
+    scope: &Scope,
+    expr: &src::Expr,
+    body: &mut Vec<wat::Instr>,
+) -> ResultCompile<Type> {
+    match expr {
+        src::Expr::Call(call) => {
+            let Some(t_return) = compile_call(scope, call, body)? else {
+                let tag = ErrorTag::ExpectedFunctionProcedure(call.proc.to_string());
+                return Err(Error::new(tag, call.span));
+            };
+            Ok(t_return)
+        }
+        src::Expr::Char(c) => {
+            body.push(wat::Instr::I32Const(i32::from(c.value)));
+            Ok(Type::new_char())
+        }
+        src::Expr::Integer(integer) => {
+            // INTEGER is 32 bits.
+            let Ok(value) = i32::try_from(integer.value) else {
+                let tag = ErrorTag::IntegerOutOfRange(integer.value.to_string());
+                return Err(Error::new(tag, integer.span));
+            };
+            body.push(wat::Instr::I32Const(value));
+            Ok(Type::new_int())
+        }
+        src::Expr::Real(real) => {
+            body.push(wat::Instr::F64Const(real.value));
+            Ok(Type::new_real())
+        }
+        src::Expr::Str(s) => {
+            // A string is passed as its address. The checker has no data,
+            // and discards the code.
+            let offset = scope.strings.lookup(&s.value).copied().unwrap_or(0);
+            body.extend(data_address(offset));
+            Ok(Type::new_char_array())
+        }
+        src::Expr::Var(var) => {
+            let (storage, t) = lookup_var(scope, var)?;
+            let name = var.name.name.clone();
+            if let TypeTag::FixedCharArray(_) = t.tag() {
+                let offset = scope.arrays.lookup(&name).copied().unwrap_or(0);
+                body.extend(data_address(offset));
+                return Ok(t);
+            }
+            body.push(match storage {
+                Storage::Local => wat::Instr::LocalGet(name),
+                Storage::Global => wat::Instr::GlobalGet(name),
+            });
+            Ok(t)
+        }
+    }
+}
+
+/// Translates a procedure call, appending its instructions to `body`, and
+/// returns the type of its result, if it's a function procedure. Predeclared
+/// procedures are used unless the module declares one with the same name.
+fn compile_call(
+    scope: &Scope,
+    call: &src::Call,
+    body: &mut Vec<wat::Instr>,
+) -> ResultCompile<Option<Type>> {
+    if call.proc.module.is_none()
+        && scope.procs.lookup(&call.proc.name).is_none()
+        && let Some(&predeclared) = create_predeclared_table().lookup(&call.proc.name)
+    {
+        return compile_predeclared(scope, predeclared, call, body);
+    }
+
+    let (id, t_proc) = lookup_proc(scope, call)?;
+
+    if call.args.len() != t_proc.params().len() {
+        let tag = ErrorTag::ArgumentCount {
+            name: call.proc.to_string(),
+            expected: t_proc.params().len(),
+            got: call.args.len(),
+        };
+        return Err(Error::new(tag, call.span));
+    }
+
+    for (arg, t_param) in call.args.iter().zip(t_proc.params()) {
+        let t = compile_expr(scope, arg, body)?;
+        check_type(t_param, &t, arg)?;
+    }
+    body.push(wat::Instr::Call(id));
+
+    Ok(t_proc.t_return().cloned())
+}
+
+/// Translates a call of a predeclared procedure, appending its instructions
+/// to `body`, and returns the type of its result, if any.
+fn compile_predeclared(
+    scope: &Scope,
+    predeclared: Predeclared,
+    call: &src::Call,
+    body: &mut Vec<wat::Instr>,
+) -> ResultCompile<Option<Type>> {
+    let name = call.proc.name.as_str();
+    let (min, max) = predeclared.arity();
+    let got = call.args.len();
+    if got < min || got > max {
+        let name = name.to_owned();
+        let tag = if min == max {
+            ErrorTag::ArgumentCount {
+                name,
+                expected: min,
+                got,
+            }
+        } else {
+            ErrorTag::ArgumentCountRange {
+                name,
+                min,
+                max,
+                got,
+            }
+        };
+        return Err(Error::new(tag, call.span));
+    }
+
+    let args = &call.args;
+    let compile_arg = |expected: &Type, arg: &src::Expr, body: &mut Vec<wat::Instr>| {
+        let t = compile_expr(scope, arg, body)?;
+        check_type(expected, &t, arg)
+    };
+
+    match predeclared {
+        Predeclared::Abs => {
+            let t = compile_expr(scope, &args[0], body)?;
+            if t == Type::new_real() {
+                body.push(wat::Instr::F64Abs);
+                return Ok(Some(t));
+            }
+            check_type(&Type::new_int(), &t, &args[0])?;
+            body.push(wat::Instr::Call(ABS_ID.to_owned()));
+            Ok(Some(t))
+        }
+        Predeclared::Assert => {
+            // An unchecked ASSERT is still checked for errors, but has no
+            // code.
+            let checked = src::asserts_checked(scope.pragmas, call.span);
+            let mut unchecked = Vec::new();
+            let body = if checked { body } else { &mut unchecked };
+            compile_arg(&Type::new_bool(), &args[0], body)?;
+            let code = match args.get(1) {
+                Some(arg) => trap_code(name, arg)?,
+                None => ASSERT_TRAP_CODE,
+            };
+            if checked {
+                body.push(wat::Instr::I32Eqz);
+                body.push(wat::Instr::If(vec![wat::Instr::Unreachable(code)]));
+            }
+            Ok(None)
+        }
+        Predeclared::Dec | Predeclared::Inc => {
+            let src::Expr::Var(var) = &args[0] else {
+                let tag = ErrorTag::ExpectedVariableArgument(name.to_owned());
+                return Err(Error::new(tag, args[0].span()));
+            };
+            let (storage, t) = lookup_var(scope, var)?;
+            check_type(&Type::new_int(), &t, &args[0])?;
+
+            let id = var.name.name.clone();
+            body.push(match storage {
+                Storage::Local => wat::Instr::LocalGet(id.clone()),
+                Storage::Global => wat::Instr::GlobalGet(id.clone()),
+            });
+            match args.get(1) {
+                Some(step) => compile_arg(&Type::new_int(), step, body)?,
+                None => body.push(wat::Instr::I32Const(1)),
+            }
+            body.push(match predeclared {
+                Predeclared::Inc => wat::Instr::I32Add,
+                _ => wat::Instr::I32Sub,
+            });
+            body.push(match storage {
+                Storage::Local => wat::Instr::LocalSet(id),
+                Storage::Global => wat::Instr::GlobalSet(id),
+            });
+            Ok(None)
+        }
+        Predeclared::Floor => {
+            compile_arg(&Type::new_real(), &args[0], body)?;
+            body.push(wat::Instr::F64Floor);
+            body.push(wat::Instr::I32TruncF64S);
+            Ok(Some(Type::new_int()))
+        }
+        Predeclared::Flt => {
+            compile_arg(&Type::new_int(), &args[0], body)?;
+            body.push(wat::Instr::F64ConvertI32S);
+            Ok(Some(Type::new_real()))
+        }
+        Predeclared::Halt => {
+            let code = trap_code(name, &args[0])?;
+            body.push(wat::Instr::Unreachable(code));
+            Ok(None)
+        }
+        Predeclared::Max | Predeclared::Min | Predeclared::Size => {
+            let src::Expr::Var(var) = &args[0] else {
+                let tag = ErrorTag::ExpectedType(name.to_owned());
+                return Err(Error::new(tag, args[0].span()));
+            };
+            let table_type = create_default_type_table();
+            let t = match &var.name.module {
+                None => table_type.lookup(&var.name.name),
+                Some(_) => None,
+            };
+            let Some(t) = t else {
+                let tag = ErrorTag::ExpectedType(name.to_owned());
+                return Err(Error::new(tag, var.span));
+            };
+
+            body.push(predeclared.eval_type(t.tag()));
+            match predeclared {
+                Predeclared::Size => Ok(Some(Type::new_int())),
+                _ => Ok(Some(t.clone())),
+            }
+        }
+        Predeclared::Odd => {
+            compile_arg(&Type::new_int(), &args[0], body)?;
+            body.push(wat::Instr::I32Const(1));
+            body.push(wat::Instr::I32And);
+            Ok(Some(Type::new_bool()))
+        }
+    }
+}
+
+/// The value of a trap code argument, which must be an integer literal.
+fn trap_code(name: &str, arg: &src::Expr) -> ResultCompile<i32> {
+    let src::Expr::Integer(integer) = arg else {
+        let tag = ErrorTag::ExpectedTrapCode(name.to_owned());
+        return Err(Error::new(tag, arg.span()));
+    };
+    i32::try_from(integer.value).map_err(|_| {
+        let tag = ErrorTag::IntegerOutOfRange(integer.value.to_string());
+        Error::new(tag, integer.span)
+    })
+}
+
+/// The function computing `ABS`, which needs its argument twice.
+fn func_abs() -> wat::Func {
+    use wat::Instr::*;
+
+    wat::builder::BuilderFunc::new()
+        .set_name(ABS_ID)
+        .add_param("x", wat::Type::I32)
+        .set_result(Some(wat::Type::I32))
+        .set_body(vec![
+            LocalGet("x".to_owned()),
+            I32Const(0),
+            I32LtS,
+            If(vec![I32Const(0), LocalGet("x".to_owned()), I32Sub, Return]),
+            LocalGet("x".to_owned()),
+        ])
+        .build()
+}
+
+/// Determines if instructions call the function with the given identifier.
+fn calls(instrs: &[wat::Instr], id: &str) -> bool {
+    instrs.iter().any(|instr| match instr {
+        wat::Instr::Call(callee) => callee == id,
+        wat::Instr::If(body) | wat::Instr::Loop(body) => calls(body, id),
+        _ => false,
+    })
+}
+
+/// Make sure an expression has the type expected. An array of characters
+/// can be passed for an open array of them.
+fn check_type(expected: &Type, got: &Type, expr: &src::Expr) -> ResultCompile<()> {
+    if got == expected
+        || matches!(
+            (expected.tag(), got.tag()),
+            (TypeTag::CharArray, TypeTag::FixedCharArray(_))
+        )
+    {
+        return Ok(());
+    }
+    let tag = ErrorTag::TypeMismatch {
+        expected: expected.to_string(),
+        got: got.to_string(),
+    };
+    Err(Error::new(tag, expr.span()))
+}
+
+/// Lookup where a variable is stored and its type.
+fn lookup_var(scope: &Scope, var: &src::Var) -> ResultCompile<(Storage, Type)> {
+    let name = &var.name.name;
+    let (tag, help) = match &var.name.module {
+        None => {
+            if let Some(t) = scope.locals.lookup(name) {
+                return Ok((Storage::Local, t.clone()));
+            }
+            if let Some(t) = scope.globals.lookup(name) {
+                return Ok((Storage::Global, t.clone()));
+            }
+            if scope.procs.lookup(name).is_some() || scope.modules.lookup(name).is_some() {
+                (ErrorTag::ExpectedVariable(name.clone()), None)
+            } else {
+                let names = scope.locals.names().chain(scope.globals.names());
+                (
+                    ErrorTag::UndefinedName(name.clone()),
+                    did_you_mean(name, names),
+                )
+            }
+        }
+        // Modules only export procedures.
+        Some(alias) => match scope.modules.lookup(alias) {
+            Some(symbols) => (
+                ErrorTag::NotExported {
+                    module: symbols.module.clone(),
+                    name: name.clone(),
+                },
+                None,
+            ),
+            None => (
+                ErrorTag::UndefinedModule(alias.clone()),
+                did_you_mean(alias, scope.modules.names()),
+            ),
+        },
+    };
+    Err(Error::new(tag, var.span).with_help(help))
+}
+
+/// Lookup the WAT identifier and type of the procedure a call refers to.
+fn lookup_proc(scope: &Scope, call: &src::Call) -> ResultCompile<(String, TypeProc)> {
+    let name = &call.proc.name;
+
+    let Some(alias) = &call.proc.module else {
+        let Some(t_proc) = scope.procs.lookup(name) else {
+            if scope.locals.lookup(name).is_some()
+                || scope.globals.lookup(name).is_some()
+                || scope.modules.lookup(name).is_some()
+            {
+                let tag = ErrorTag::ExpectedProcedure(name.clone());
+                return Err(Error::new(tag, call.span));
+            }
+            let tag = ErrorTag::UndefinedName(name.clone());
+            let predeclared = create_predeclared_table();
+            let help = did_you_mean(name, scope.procs.names().chain(predeclared.names()));
+            return Err(Error::new(tag, call.span).with_help(help));
+        };
+        return Ok((name.clone(), t_proc.clone()));
+    };
+
+    let Some(symbols) = scope.modules.lookup(alias) else {
+        let tag = ErrorTag::UndefinedModule(alias.clone());
+        let help = did_you_mean(alias, scope.modules.names());
+        return Err(Error::new(tag, call.span).with_help(help));
+    };
+
+    let Some(t_proc) = symbols.procs.lookup(name) else {
+        let tag = ErrorTag::NotExported {
+            module: symbols.module.clone(),
+            name: name.clone(),
+        };
+        let help = did_you_mean(name, symbols.procs.names());
+        return Err(Error::new(tag, call.span).with_help(help));
     };
 
-    Ok((func, export))
+    Ok((import_id(&symbols.module, name), t_proc.clone()))
+}
+
+/// The WAT identifier of a procedure imported from another module.
+fn import_id(module: &str, name: &str) -> String {
+    format!("{module}.{name}")
 }
 
 /// Creates a type table with built-in types.
-fn create_default_type_table() -> Table<Type> {
+pub fn create_default_type_table() -> Table<Type> {
     let mut t = Table::new();
+    t.push("BOOLEAN", Type::new_bool());
+    t.push("CHAR", Type::new_char());
     t.push("INTEGER", Type::new_int());
+    t.push("REAL", Type::new_real());
+    t
+}
+
+/// Oberon's predeclared procedures, which are checked and translated
+/// specially rather than called.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Predeclared {
+    /// `ABS(x)`, the absolute value of an integer or real.
+    Abs,
+
+    /// `ASSERT(b)` or `ASSERT(b, n)`, which traps, with the code `n` or
+    /// `ASSERT_TRAP_CODE`, if `b` is false.
+    Assert,
+
+    /// `DEC(v)` or `DEC(v, n)`, which decreases an integer variable by 1 or
+    /// `n`.
+    Dec,
+
+    /// `FLOOR(x)`, the largest integer not greater than a real, which
+    /// traps if it doesn't fit in an `INTEGER`.
+    Floor,
+
+    /// `FLT(x)`, an integer as a real.
+    Flt,
+
+    /// `HALT(n)`, which traps with the code `n`.
+    Halt,
+
+    /// `INC(v)` or `INC(v, n)`, which increases an integer variable by 1 or
+    /// `n`.
+    Inc,
+
+    /// `MAX(T)`, the largest value of a basic type.
+    Max,
+
+    /// `MIN(T)`, the smallest value of a basic type.
+    Min,
+
+    /// `ODD(x)`, whether an integer is odd.
+    Odd,
+
+    /// `SIZE(T)`, the number of bytes a value of a type takes in memory.
+    Size,
+}
+
+impl Predeclared {
+    /// The least and most arguments the procedure takes.
+    pub fn arity(self) -> (usize, usize) {
+        match self {
+            Predeclared::Assert | Predeclared::Dec | Predeclared::Inc => (1, 2),
+            _ => (1, 1),
+        }
+    }
+
+    /// The value of `MAX`, `MIN` or `SIZE` of a type, as the constant
+    /// instruction representing it in the compiled code.
+    pub fn eval_type(self, t: &TypeTag) -> wat::Instr {
+        use wat::Instr::{F64Const, I32Const};
+
+        match (self, t) {
+            (Predeclared::Max, TypeTag::Bool) => I32Const(1),
+            (Predeclared::Max, TypeTag::Char) => I32Const(u8::MAX.into()),
+            (Predeclared::Max, TypeTag::Int) => I32Const(i32::MAX),
+            (Predeclared::Max, TypeTag::Real) => F64Const(f64::MAX),
+            (Predeclared::Min, TypeTag::Int) => I32Const(i32::MIN),
+            (Predeclared::Min, TypeTag::Real) => F64Const(f64::MIN),
+            (Predeclared::Min, _) => I32Const(0),
+            (Predeclared::Size, TypeTag::Bool | TypeTag::Char) => I32Const(1),
+            (Predeclared::Size, TypeTag::Int) => I32Const(4),
+            (Predeclared::Size, TypeTag::Real) => I32Const(8),
+            _ => unreachable!("only MAX, MIN and SIZE take a type"),
+        }
+    }
+}
+
+/// Creates a table of the predeclared procedures.
+pub fn create_predeclared_table() -> Table<Predeclared> {
+    let mut t = Table::new();
+    t.push("ABS", Predeclared::Abs);
+    t.push("ASSERT", Predeclared::Assert);
+    t.push("DEC", Predeclared::Dec);
+    t.push("FLOOR", Predeclared::Floor);
+    t.push("FLT", Predeclared::Flt);
+    t.push("HALT", Predeclared::Halt);
+    t.push("INC", Predeclared::Inc);
+    t.push("MAX", Predeclared::Max);
+    t.push("MIN", Predeclared::Min);
+    t.push("ODD", Predeclared::Odd);
+    t.push("SIZE", Predeclared::Size);
     t
 }
 
-/// Lookup type associated with an given identifier.
-fn lookup_type(table_type: &Table<Type>, tid: &str) -> ResultCompile<Type> {
+/// Lookup type associated with an given identifier, used in the declaration
+/// at `span`.
+fn lookup_type(table_type: &Table<Type>, tid: &str, span: Span) -> ResultCompile<Type> {
     let Some(t) = table_type.lookup(tid) else {
-        unimplemented!();
+        let tag = ErrorTag::UndefinedName(tid.to_owned());
+        let help = did_you_mean(tid, table_type.names());
+        return Err(Error::new(tag, span).with_help(help));
     };
     Ok(t.clone())
 }
@@ -94,7 +1267,22 @@ fn lookup_type(table_type: &Table<Type>, tid: &str) -> ResultCompile<Type> {
 /// Convert a type to a WAT type.
 fn to_type_wat(t: &Type) -> ResultCompile<wat::Type> {
     match t.tag() {
-        TypeTag::Int => Ok(wat::Type::I32),
+        // An array of characters is passed as its address.
+        TypeTag::Bool
+        | TypeTag::Char
+        | TypeTag::CharArray
+        | TypeTag::FixedCharArray(_)
+        | TypeTag::Int => Ok(wat::Type::I32),
+        TypeTag::Real => Ok(wat::Type::F64),
+    }
+}
+
+/// The constant instruction for a WAT type's zero, which variables start
+/// with.
+fn zero(t: &wat::Type) -> wat::Instr {
+    match t {
+        wat::Type::F64 => wat::Instr::F64Const(0.0),
+        wat::Type::I32 => wat::Instr::I32Const(0),
     }
 }
 
@@ -102,6 +1290,7 @@ fn to_type_wat(t: &Type) -> ResultCompile<wat::Type> {
 mod tests {
     use crate::ast::src::builder::*;
     use crate::ast::wat::builder::*;
+    use crate::span::Span;
     use crate::table::Table;
 
     use super::*;
@@ -112,7 +1301,7 @@ mod tests {
     fn test_module_empty() -> ResultTest {
         let module_name = "M";
         let module = BuilderModule::new().set_name(module_name).build();
-        let module = compile(&module)?;
+        let (module, _) = compile(&module, &Table::new(), &Options::default())?;
         assert_eq!(module.name, module_name);
         Ok(())
     }
@@ -123,10 +1312,14 @@ mod tests {
         let proc_name = "P";
         let module = BuilderModule::new()
             .set_name(module_name)
-            .add_decl(BuilderProc::new().set_name(proc_name, 1).build_decl())
-            .build();
-        let module = compile(&module)?;
-        assert_eq!(module.name, module_name);
+            .add_decl(
+                BuilderProc::new()
+                    .set_name(proc_name, Span::at_line(1))
+                    .build_decl(),
+            )
+            .build();
+        let (module, _) = compile(&module, &Table::new(), &Options::default())?;
+        assert_eq!(module.name, module_name);
         assert_eq!(module.funcs.len(), 1);
         Ok(())
     }
@@ -137,12 +1330,12 @@ mod tests {
             .set_name("M")
             .add_decl(
                 BuilderProc::new()
-                    .set_name("P", 1)
+                    .set_name("P", Span::at_line(1))
                     .set_export(true)
                     .build_decl(),
             )
             .build();
-        let module = compile(&module)?;
+        let (module, _) = compile(&module, &Table::new(), &Options::default())?;
         assert_eq!(module.exports[0].name, "P");
         Ok(())
     }
@@ -152,27 +1345,307 @@ mod tests {
         let mut builder_proc = BuilderProc::new();
         let module = BuilderModule::new()
             .set_name("M")
-            .add_decl(builder_proc.set_name("P", 2).build_decl())
-            .add_decl(builder_proc.set_name("P", 3).build_decl())
+            .add_decl(builder_proc.set_name("P", Span::at_line(2)).build_decl())
+            .add_decl(builder_proc.set_name("P", Span::at_line(3)).build_decl())
             .build();
-        let compile_result = compile(&module);
-        match compile_result {
+        let compile_result = compile(&module, &Table::new(), &Options::default());
+        match compile_result.map_err(Error::from) {
             Err(Error {
                 tag: ErrorTag::NameRedefinition(name),
-                line,
-            }) if name == "P" && line == 3 => Ok(()),
+                span: Span { line: 3, .. },
+                ..
+            }) if name == "P" => Ok(()),
             _ => panic!("Expected name redefinition error."),
         }
     }
 
+    #[test]
+    fn test_compile_module_var() -> ResultTest {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(var_decl("x", "INTEGER", true, 2))
+            .add_decl(var_decl("y", "INTEGER", false, 2))
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(3))
+                    .add_param("a", "INTEGER", Span::at_line(3))
+                    .add_stmt(assign("y", var("x", 4), 4))
+                    .add_stmt(assign("a", var("y", 5), 5))
+                    .build_decl(),
+            )
+            .build();
+        let (module, symbols) = compile(&module, &Table::new(), &Options::default())?;
+        assert_eq!(
+            module
+                .globals
+                .iter()
+                .map(|g| g.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["x", "y"]
+        );
+        assert_eq!(
+            module.exports,
+            vec![wat::Export {
+                name: "x".to_owned(),
+                desc: wat::ExportDesc::Global("x".to_owned()),
+            }]
+        );
+        assert_eq!(
+            module.funcs[0].body,
+            vec![
+                wat::Instr::GlobalGet("x".to_owned()),
+                wat::Instr::GlobalSet("y".to_owned()),
+                wat::Instr::GlobalGet("y".to_owned()),
+                wat::Instr::LocalSet("a".to_owned()),
+            ]
+        );
+        assert!(symbols.procs.items.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_assign_to_proc() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(1))
+                    .add_stmt(assign("P", integer(1, 2), 2))
+                    .build_decl(),
+            )
+            .build();
+        assert_eq!(
+            error_tag(compile(&module, &Table::new(), &Options::default())),
+            Some(ErrorTag::ExpectedVariable("P".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_compile_call_variable() {
+        let source = "MODULE M;
+VAR x: INTEGER;
+PROCEDURE P*(a: INTEGER);
+BEGIN
+  a; x
+END;
+END M.";
+        let diagnostics = compile_source(source).unwrap_err();
+        let tags: Vec<_> = diagnostics.errors().iter().map(|e| e.tag.clone()).collect();
+        assert_eq!(
+            tags,
+            vec![
+                ErrorTag::ExpectedProcedure("a".to_owned()),
+                ErrorTag::ExpectedProcedure("x".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_export_name() -> ResultTest {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("Init", Span::at_line(1))
+                    .set_export(true)
+                    .set_export_name("init")
+                    .build_decl(),
+            )
+            .build();
+        let (module, symbols) = compile(&module, &Table::new(), &Options::default())?;
+        assert_eq!(
+            module.exports,
+            vec![wat::Export {
+                name: "init".to_owned(),
+                desc: wat::ExportDesc::Func("Init".to_owned()),
+            }]
+        );
+        assert!(symbols.procs.lookup("Init").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_export_name_clash() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(var_decl("x", "INTEGER", true, 1))
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(2))
+                    .set_export(true)
+                    .set_export_name("x")
+                    .build_decl(),
+            )
+            .build();
+        match compile(&module, &Table::new(), &Options::default()).map_err(Error::from) {
+            Err(Error {
+                tag: ErrorTag::ExportRedefinition(name),
+                span: Span { line: 2, .. },
+                ..
+            }) if name == "x" => (),
+            _ => panic!("Expected export redefinition error."),
+        }
+    }
+
+    #[test]
+    fn test_compile_export_name_unexported() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(1))
+                    .set_export_name("p")
+                    .build_decl(),
+            )
+            .build();
+        assert_eq!(
+            error_tag(compile(&module, &Table::new(), &Options::default())),
+            Some(ErrorTag::UnexportedExportName("P".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_compile_export_memory() -> ResultTest {
+        let module = BuilderModule::new().set_name("M").build();
+        let options = Options {
+            export_memory: true,
+            ..Options::default()
+        };
+        let (module, _) = compile(&module, &Table::new(), &options)?;
+        assert_eq!(module.memories.len(), 1);
+        assert_eq!(
+            module.exports[0].desc,
+            wat::ExportDesc::Memory(MEMORY_ID.to_owned())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_export_name_clash_synthetic() {
+        let module = |name| {
+            BuilderModule::new()
+                .set_name("M")
+                .add_decl(
+                    BuilderProc::new()
+                        .set_name("P", Span::at_line(1))
+                        .set_export(true)
+                        .set_export_name(name)
+                        .build_decl(),
+                )
+                .add_stmt(src::Stmt::Call(call(None, "P", vec![], 2)))
+                .build()
+        };
+        let options = Options {
+            export_init: true,
+            export_memory: true,
+            ..Options::default()
+        };
+        for name in [INIT_ID, MEMORY_ID] {
+            assert_eq!(
+                error_tag(compile(&module(name), &Table::new(), &options)),
+                Some(ErrorTag::ExportRedefinition(name.to_owned()))
+            );
+        }
+        // Without the options, the names are free to use.
+        for name in [INIT_ID, MEMORY_ID] {
+            assert!(compile(&module(name), &Table::new(), &Options::default()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_compile_collects_errors() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(1))
+                    .build_decl(),
+            )
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(2))
+                    .add_stmt(src::Stmt::Call(call(None, "Q", vec![], 3)))
+                    .add_stmt(src::Stmt::Call(call(None, "R", vec![], 4)))
+                    .build_decl(),
+            )
+            .add_stmt(src::Stmt::Call(call(None, "S", vec![], 6)))
+            .build();
+        let Err(diagnostics) = compile(&module, &Table::new(), &Options::default()) else {
+            panic!("Expected errors.");
+        };
+        let errors: Vec<_> = diagnostics
+            .errors()
+            .iter()
+            .map(|e| (e.tag.clone(), e.span.line))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (ErrorTag::NameRedefinition("P".to_owned()), 2),
+                (ErrorTag::UndefinedName("Q".to_owned()), 3),
+                (ErrorTag::UndefinedName("R".to_owned()), 4),
+                (ErrorTag::UndefinedName("S".to_owned()), 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_redefinition_dropped() {
+        let source = "MODULE M;
+VAR x: INTEGER;
+PROCEDURE P(a: INTEGER);
+END;
+PROCEDURE P;
+END;
+PROCEDURE x(): CHAR;
+BEGIN
+  RETURN 41X
+END;
+BEGIN
+  P(1); x := 2
+END M.";
+        let Err(diagnostics) = compile_source(source) else {
+            panic!("Expected errors.");
+        };
+        let errors: Vec<_> = diagnostics
+            .errors()
+            .iter()
+            .map(|e| (e.tag.clone(), e.span.line))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (ErrorTag::NameRedefinition("P".to_owned()), 5),
+                (ErrorTag::NameRedefinition("x".to_owned()), 7),
+            ]
+        );
+    }
+
     #[test]
     fn test_compile_proc() -> ResultTest {
-        let mut table_type = create_default_type_table();
+        let table_type = create_default_type_table();
         let mut table_proc = Table::new();
         let proc_name = "P";
-        let t_proc = TypeProc::new(None);
-        let proc = BuilderProc::new().set_name(proc_name, 1).build_decl();
-        let (func, _) = compile_decl(&mut table_type, &mut table_proc, &proc)?;
+        let t_proc = TypeProc::new(Vec::new(), None);
+        let proc = BuilderProc::new()
+            .set_name(proc_name, Span::at_line(1))
+            .build_decl();
+        let (Definition::Func(func), _) = compile_decl(
+            &table_type,
+            &mut table_proc,
+            &Table::new(),
+            &mut Table::new(),
+            Shared {
+                pragmas: &[],
+                strings: &Table::new(),
+                arrays: &Table::new(),
+            },
+            &proc,
+            &mut Diagnostics::new(),
+        )?
+        else {
+            panic!("Expected a function.");
+        };
         assert_eq!(func.name, proc_name);
         assert_eq!(table_proc.lookup(proc_name), Some(&t_proc));
         Ok(())
@@ -182,22 +1655,1024 @@ mod tests {
     fn test_compile_proc_with_result_i32() -> ResultTest {
         let proc_name = "P";
         let proc = BuilderProc::new()
-            .set_name(proc_name, 1)
+            .set_name(proc_name, Span::at_line(1))
             .set_tid_return("INTEGER")
+            .add_stmt(ret(Some(integer(0, 2)), 2))
             .build();
         let func = BuilderFunc::new()
             .set_name(proc_name)
             .set_result(Some(wat::Type::I32))
+            .set_body(vec![wat::Instr::I32Const(0), wat::Instr::Return])
             .build();
-        let t_proc = TypeProc::new(Some(Type::new_int()));
+        let t_proc = TypeProc::new(Vec::new(), Some(Type::new_int()));
 
-        let mut table_type = create_default_type_table();
+        let table_type = create_default_type_table();
         let mut table_proc = Table::new();
-        let (func_compiled, _) = compile_proc(&mut table_type, &mut table_proc, &proc)?;
+        let mut diagnostics = Diagnostics::new();
+        let (func_compiled, _) = compile_proc(
+            &table_type,
+            &mut table_proc,
+            &Table::new(),
+            &Table::new(),
+            Shared {
+                pragmas: &[],
+                strings: &Table::new(),
+                arrays: &Table::new(),
+            },
+            &proc,
+            &mut diagnostics,
+        )?;
 
-        assert_eq!(func, func_compiled);
+        assert!(diagnostics.is_empty());
+        assert_eq!(Definition::Func(func), func_compiled);
         assert_eq!(table_proc.lookup(proc_name), Some(&t_proc));
 
         Ok(())
     }
+
+    #[test]
+    fn test_compile_proc_params() -> ResultTest {
+        let proc = BuilderProc::new()
+            .set_name("P", Span::at_line(1))
+            .add_param("x", "INTEGER", Span::at_line(1))
+            .set_tid_return("INTEGER")
+            .add_stmt(ret(Some(var("x", 2)), 2))
+            .build();
+        let func = BuilderFunc::new()
+            .set_name("P")
+            .add_param("x", wat::Type::I32)
+            .set_result(Some(wat::Type::I32))
+            .set_body(vec![
+                wat::Instr::LocalGet("x".to_owned()),
+                wat::Instr::Return,
+            ])
+            .build();
+
+        let table_type = create_default_type_table();
+        let mut table_proc = Table::new();
+        let (func_compiled, _) = compile_proc(
+            &table_type,
+            &mut table_proc,
+            &Table::new(),
+            &Table::new(),
+            Shared {
+                pragmas: &[],
+                strings: &Table::new(),
+                arrays: &Table::new(),
+            },
+            &proc,
+            &mut Diagnostics::new(),
+        )?;
+
+        assert_eq!(Definition::Func(func), func_compiled);
+        assert_eq!(
+            table_proc.lookup("P"),
+            Some(&TypeProc::new(vec![Type::new_int()], Some(Type::new_int())))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_proc_locals() -> ResultTest {
+        let proc = BuilderProc::new()
+            .set_name("P", Span::at_line(1))
+            .add_local(local("x", "INTEGER", false, 2))
+            .set_tid_return("INTEGER")
+            .add_stmt(assign("x", integer(1, 3), 3))
+            .add_stmt(ret(Some(var("x", 4)), 4))
+            .build();
+        let func = BuilderFunc::new()
+            .set_name("P")
+            .set_result(Some(wat::Type::I32))
+            .add_local("x", wat::Type::I32)
+            .set_body(vec![
+                wat::Instr::I32Const(1),
+                wat::Instr::LocalSet("x".to_owned()),
+                wat::Instr::LocalGet("x".to_owned()),
+                wat::Instr::Return,
+            ])
+            .build();
+
+        let (func_compiled, _) = compile_proc(
+            &create_default_type_table(),
+            &mut Table::new(),
+            &Table::new(),
+            &Table::new(),
+            Shared {
+                pragmas: &[],
+                strings: &Table::new(),
+                arrays: &Table::new(),
+            },
+            &proc,
+            &mut Diagnostics::new(),
+        )?;
+        assert_eq!(Definition::Func(func), func_compiled);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_proc_exported_local() {
+        let proc = BuilderProc::new()
+            .set_name("P", Span::at_line(1))
+            .add_local(local("x", "INTEGER", true, 2))
+            .build();
+        let result = compile_proc(
+            &create_default_type_table(),
+            &mut Table::new(),
+            &Table::new(),
+            &Table::new(),
+            Shared {
+                pragmas: &[],
+                strings: &Table::new(),
+                arrays: &Table::new(),
+            },
+            &proc,
+            &mut Diagnostics::new(),
+        );
+        assert_eq!(
+            error_tag(result),
+            Some(ErrorTag::ExportedLocal("x".to_owned()))
+        );
+    }
+
+    /// Compiles a procedure on its own, returning its first error.
+    fn compile_proc_error(proc: &src::Proc) -> Option<ErrorTag> {
+        let mut diagnostics = Diagnostics::new();
+        let result = compile_proc(
+            &create_default_type_table(),
+            &mut Table::new(),
+            &Table::new(),
+            &Table::new(),
+            Shared {
+                pragmas: &[],
+                strings: &Table::new(),
+                arrays: &Table::new(),
+            },
+            proc,
+            &mut diagnostics,
+        );
+        match result {
+            Err(e) => Some(e.tag),
+            Ok(_) => diagnostics.errors().first().map(|e| e.tag.clone()),
+        }
+    }
+
+    #[test]
+    fn test_compile_proc_missing_return() {
+        let proc = BuilderProc::new()
+            .set_name("P", Span::at_line(1))
+            .add_param("x", "INTEGER", Span::at_line(1))
+            .set_tid_return("INTEGER")
+            .add_stmt(assign("x", integer(1, 2), 2))
+            .build();
+        assert_eq!(
+            compile_proc_error(&proc),
+            Some(ErrorTag::MissingReturn("P".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_compile_halt_returns() -> ResultTest {
+        let source = "MODULE M;
+PROCEDURE P*(): INTEGER;
+BEGIN
+  HALT(1)
+END;
+END M.";
+        compile_source(source)?;
+        let source = "MODULE M;
+PROCEDURE HALT(n: INTEGER);
+END;
+PROCEDURE P*(): INTEGER;
+BEGIN
+  HALT(1)
+END;
+END M.";
+        assert_eq!(
+            error_tag(compile_source(source)),
+            Some(ErrorTag::MissingReturn("P".to_owned()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_proc_missing_return_value() {
+        let proc = BuilderProc::new()
+            .set_name("P", Span::at_line(1))
+            .set_tid_return("INTEGER")
+            .add_stmt(ret(None, 2))
+            .build();
+        assert_eq!(
+            compile_proc_error(&proc),
+            Some(ErrorTag::MissingReturnValue("P".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_compile_proc_unexpected_return_value() {
+        let proc = BuilderProc::new()
+            .set_name("P", Span::at_line(1))
+            .add_stmt(ret(Some(integer(1, 2)), 2))
+            .build();
+        assert_eq!(
+            compile_proc_error(&proc),
+            Some(ErrorTag::UnexpectedReturnValue)
+        );
+    }
+
+    #[test]
+    fn test_compile_proc_return_early() {
+        let proc = BuilderProc::new()
+            .set_name("P", Span::at_line(1))
+            .add_stmt(ret(None, 2))
+            .build();
+        assert_eq!(compile_proc_error(&proc), None);
+    }
+
+    #[test]
+    fn test_compile_proc_param_redefinition() {
+        let proc = BuilderProc::new()
+            .set_name("P", Span::at_line(1))
+            .add_param("x", "INTEGER", Span::at_line(1))
+            .add_param("x", "INTEGER", Span::at_line(2))
+            .build();
+        let result = compile_proc(
+            &create_default_type_table(),
+            &mut Table::new(),
+            &Table::new(),
+            &Table::new(),
+            Shared {
+                pragmas: &[],
+                strings: &Table::new(),
+                arrays: &Table::new(),
+            },
+            &proc,
+            &mut Diagnostics::new(),
+        );
+        assert_eq!(
+            error_tag(result),
+            Some(ErrorTag::NameRedefinition("x".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_compile_proc_external() -> ResultTest {
+        let proc = BuilderProc::new()
+            .set_name("Log", Span::at_line(1))
+            .set_external("env", "log")
+            .add_param("x", "INTEGER", Span::at_line(1))
+            .build();
+        let import = wat::Import {
+            module: "env".to_owned(),
+            name: "log".to_owned(),
+            id: "Log".to_owned(),
+            params: vec![wat::Type::I32],
+            result: None,
+        };
+
+        let table_type = create_default_type_table();
+        let mut table_proc = Table::new();
+        let (import_compiled, _) = compile_proc(
+            &table_type,
+            &mut table_proc,
+            &Table::new(),
+            &Table::new(),
+            Shared {
+                pragmas: &[],
+                strings: &Table::new(),
+                arrays: &Table::new(),
+            },
+            &proc,
+            &mut Diagnostics::new(),
+        )?;
+
+        assert_eq!(Definition::Import(import), import_compiled);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_call_args() -> ResultTest {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("Log", Span::at_line(1))
+                    .set_external("env", "log")
+                    .add_param("x", "INTEGER", Span::at_line(1))
+                    .build_decl(),
+            )
+            .add_stmt(src::Stmt::Call(call(None, "Log", vec![integer(7, 2)], 2)))
+            .build();
+        let (module, _) = compile(&module, &Table::new(), &Options::default())?;
+
+        use wat::Instr::*;
+        assert_eq!(module.imports.len(), 1);
+        assert_eq!(
+            module.funcs[0].body,
+            vec![I32Const(7), Call("Log".to_owned())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_call_argument_count() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(1))
+                    .add_param("x", "INTEGER", Span::at_line(1))
+                    .build_decl(),
+            )
+            .add_stmt(src::Stmt::Call(call(None, "P", vec![], 2)))
+            .build();
+        assert_eq!(
+            error_tag(compile(&module, &Table::new(), &Options::default())),
+            Some(ErrorTag::ArgumentCount {
+                name: "P".to_owned(),
+                expected: 1,
+                got: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_compile_proc_body() -> ResultTest {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(1))
+                    .set_tid_return("INTEGER")
+                    .add_stmt(ret(Some(integer(42, 2)), 2))
+                    .build_decl(),
+            )
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("Q", Span::at_line(3))
+                    .add_stmt(src::Stmt::Call(call(None, "P", vec![], 4)))
+                    .build_decl(),
+            )
+            .build();
+        let (module, _) = compile(&module, &Table::new(), &Options::default())?;
+
+        use wat::Instr::*;
+        assert_eq!(module.funcs[0].body, vec![I32Const(42), Return]);
+        assert_eq!(module.funcs[1].body, vec![Call("P".to_owned()), Drop]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_call_undefined() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(1))
+                    .add_stmt(src::Stmt::Call(call(None, "Q", vec![], 2)))
+                    .build_decl(),
+            )
+            .build();
+        assert_eq!(
+            error_tag(compile(&module, &Table::new(), &Options::default())),
+            Some(ErrorTag::UndefinedName("Q".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_compile_undefined_help() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(var_decl("count", "INTEGER", false, 1))
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("Add", Span::at_line(2))
+                    .add_stmt(assign("Count", integer(1, 3), 3))
+                    .build_decl(),
+            )
+            .add_stmt(src::Stmt::Call(call(None, "add", vec![], 5)))
+            .build();
+        let Err(diagnostics) = compile(&module, &Table::new(), &Options::default()) else {
+            panic!("Expected errors.");
+        };
+        let help: Vec<_> = diagnostics
+            .errors()
+            .iter()
+            .map(|e| e.help.as_deref())
+            .collect();
+        assert_eq!(
+            help,
+            vec![Some("did you mean `count`?"), Some("did you mean `Add`?")]
+        );
+    }
+
+    #[test]
+    fn test_compile_integer_out_of_range() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(1))
+                    .set_tid_return("INTEGER")
+                    .add_stmt(ret(Some(integer(2147483648, 2)), 2))
+                    .build_decl(),
+            )
+            .build();
+        assert_eq!(
+            error_tag(compile(&module, &Table::new(), &Options::default())),
+            Some(ErrorTag::IntegerOutOfRange("2147483648".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_compile_char() -> ResultTest {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(var_decl("c", "CHAR", false, 1))
+            .add_stmt(assign(
+                "c",
+                src::Expr::Char(src::Char {
+                    value: b'A',
+                    span: Span::at_line(3),
+                }),
+                3,
+            ))
+            .build();
+        let (module, _) = compile(&module, &Table::new(), &Options::default())?;
+        assert_eq!(
+            module.funcs[0].body,
+            vec![
+                wat::Instr::I32Const(65),
+                wat::Instr::GlobalSet("c".to_owned())
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_type_mismatch() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(var_decl("c", "CHAR", false, 1))
+            .add_stmt(assign("c", integer(65, 3), 3))
+            .build();
+        assert_eq!(
+            error_tag(compile(&module, &Table::new(), &Options::default())),
+            Some(ErrorTag::TypeMismatch {
+                expected: "CHAR".to_owned(),
+                got: "INTEGER".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn test_compile_export_symbols() -> ResultTest {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(1))
+                    .build_decl(),
+            )
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("Q", Span::at_line(2))
+                    .set_export(true)
+                    .set_tid_return("INTEGER")
+                    .add_stmt(ret(Some(integer(0, 3)), 3))
+                    .build_decl(),
+            )
+            .build();
+        let (_, symbols) = compile(&module, &Table::new(), &Options::default())?;
+        assert_eq!(symbols.module, "M");
+        assert!(symbols.procs.lookup("P").is_none());
+        assert_eq!(
+            symbols.procs.lookup("Q"),
+            Some(&TypeProc::new(Vec::new(), Some(Type::new_int())))
+        );
+        Ok(())
+    }
+
+    fn maths_symbols() -> Table<Symbols> {
+        let mut symbols = Symbols::new("Maths");
+        symbols
+            .procs
+            .push("Sqrt", TypeProc::new(Vec::new(), Some(Type::new_int())));
+        let mut table = Table::new();
+        table.push("Maths", symbols);
+        table
+    }
+
+    #[test]
+    fn test_compile_qualified_call() -> ResultTest {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_import("A", "Maths", Span::at_line(1))
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(2))
+                    .add_stmt(src::Stmt::Call(call(Some("A"), "Sqrt", vec![], 3)))
+                    .build_decl(),
+            )
+            .build();
+        let (module, _) = compile(&module, &maths_symbols(), &Options::default())?;
+
+        assert_eq!(
+            module.imports,
+            vec![wat::Import {
+                module: "Maths".to_owned(),
+                name: "Sqrt".to_owned(),
+                id: "Maths.Sqrt".to_owned(),
+                params: Vec::new(),
+                result: Some(wat::Type::I32),
+            }]
+        );
+        use wat::Instr::*;
+        assert_eq!(
+            module.funcs[0].body,
+            vec![Call("Maths.Sqrt".to_owned()), Drop]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_import_twice() -> ResultTest {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_import("A", "Maths", Span::at_line(1))
+            .add_import("B", "Maths", Span::at_line(1))
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(2))
+                    .add_stmt(src::Stmt::Call(call(Some("A"), "Sqrt", vec![], 3)))
+                    .add_stmt(src::Stmt::Call(call(Some("B"), "Sqrt", vec![], 4)))
+                    .build_decl(),
+            )
+            .build();
+        let (module, _) = compile(&module, &maths_symbols(), &Options::default())?;
+        assert_eq!(module.imports.len(), 1);
+        use wat::Instr::*;
+        assert_eq!(
+            module.funcs[0].body,
+            vec![
+                Call("Maths.Sqrt".to_owned()),
+                Drop,
+                Call("Maths.Sqrt".to_owned()),
+                Drop
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_qualified_call_not_exported() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_import("Maths", "Maths", Span::at_line(1))
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(2))
+                    .add_stmt(src::Stmt::Call(call(Some("Maths"), "Helper", vec![], 3)))
+                    .build_decl(),
+            )
+            .build();
+        assert_eq!(
+            error_tag(compile(&module, &maths_symbols(), &Options::default())),
+            Some(ErrorTag::NotExported {
+                module: "Maths".to_owned(),
+                name: "Helper".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_compile_import_undefined_module() {
+        let module = BuilderModule::new()
+            .set_name("M")
+            .add_import("Out", "Out", Span::at_line(1))
+            .build();
+        assert_eq!(
+            error_tag(compile(&module, &maths_symbols(), &Options::default())),
+            Some(ErrorTag::UndefinedModule("Out".to_owned()))
+        );
+    }
+
+    fn module_with_body() -> src::Module {
+        BuilderModule::new()
+            .set_name("M")
+            .add_decl(
+                BuilderProc::new()
+                    .set_name("P", Span::at_line(1))
+                    .build_decl(),
+            )
+            .add_stmt(src::Stmt::Call(call(None, "P", vec![], 2)))
+            .build()
+    }
+
+    #[test]
+    fn test_compile_module_body_start() -> ResultTest {
+        let (module, _) = compile(&module_with_body(), &Table::new(), &Options::default())?;
+        assert_eq!(module.funcs[1].name, INIT_ID);
+        assert_eq!(module.funcs[1].body, vec![wat::Instr::Call("P".to_owned())]);
+        assert_eq!(module.start, Some(INIT_ID.to_owned()));
+        assert!(module.exports.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_module_body_export_init() -> ResultTest {
+        let options = Options {
+            export_init: true,
+            ..Options::default()
+        };
+        let (module, _) = compile(&module_with_body(), &Table::new(), &options)?;
+        assert_eq!(module.funcs[1].name, INIT_ID);
+        assert_eq!(module.start, None);
+        assert_eq!(module.exports[0].name, INIT_ID);
+        Ok(())
+    }
+
+    #[test]
+    fn test_checker() {
+        let mut checker = Checker::new();
+        let x = var_decl("x", "CHAR", false, 1);
+        checker.decls(&[x]).unwrap();
+        assert_eq!(checker.expr(&var("x", 2)).unwrap(), Type::new_char());
+
+        // A declaration in error doesn't declare any of the names with it.
+        let decls = [
+            var_decl("y", "INTEGER", false, 3),
+            var_decl("x", "INTEGER", false, 3),
+        ];
+        assert_eq!(
+            error_tag(checker.decls(&decls)),
+            Some(ErrorTag::NameRedefinition("x".to_owned()))
+        );
+        assert_eq!(
+            error_tag(checker.expr(&var("y", 4))),
+            Some(ErrorTag::UndefinedName("y".to_owned()))
+        );
+        assert_eq!(
+            error_tag(checker.stmts(&[assign("x", integer(1, 5), 5)])),
+            Some(ErrorTag::TypeMismatch {
+                expected: "CHAR".to_owned(),
+                got: "INTEGER".to_owned()
+            })
+        );
+    }
+
+    /// Compiles a module's source text.
+    fn compile_source(source: &str) -> Result<wat::Module, Diagnostics> {
+        let module = crate::parser::Parser::new(source).module()?;
+        Ok(compile(&module, &Table::new(), &Options::default())?.0)
+    }
+
+    #[test]
+    fn test_compile_predeclared() -> ResultTest {
+        let source = "MODULE M;
+VAR x: INTEGER; b: BOOLEAN;
+BEGIN
+  INC(x); DEC(x, 2); b := ODD(ABS(x)); ASSERT(b); HALT(3)
+END M.";
+        let module = compile_source(source)?;
+        use wat::Instr::*;
+        assert_eq!(
+            module.funcs[0].body,
+            vec![
+                GlobalGet("x".to_owned()),
+                I32Const(1),
+                I32Add,
+                GlobalSet("x".to_owned()),
+                GlobalGet("x".to_owned()),
+                I32Const(2),
+                I32Sub,
+                GlobalSet("x".to_owned()),
+                GlobalGet("x".to_owned()),
+                Call(ABS_ID.to_owned()),
+                I32Const(1),
+                I32And,
+                GlobalSet("b".to_owned()),
+                GlobalGet("b".to_owned()),
+                I32Eqz,
+                If(vec![Unreachable(ASSERT_TRAP_CODE)]),
+                Unreachable(3),
+            ]
+        );
+        assert_eq!(module.funcs[1].name, ABS_ID);
+
+        let module = compile_source("MODULE M; VAR x*: INTEGER; BEGIN x := MIN(INTEGER) END M.")?;
+        assert_eq!(module.funcs[0].body[0], I32Const(i32::MIN));
+        assert_eq!(module.funcs.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_real() -> ResultTest {
+        let source = "MODULE M;
+VAR r*: REAL; x: INTEGER;
+BEGIN
+  r := 2.5E1; x := FLOOR(r); r := FLT(x); r := MAX(REAL); x := SIZE(REAL);
+  r := ABS(r)
+END M.";
+        let module = compile_source(source)?;
+        use wat::Instr::*;
+        assert_eq!(module.globals[0].t, wat::Type::F64);
+        assert_eq!(module.globals[0].init, F64Const(0.0));
+        assert_eq!(
+            module.funcs[0].body,
+            vec![
+                F64Const(25.0),
+                GlobalSet("r".to_owned()),
+                GlobalGet("r".to_owned()),
+                F64Floor,
+                I32TruncF64S,
+                GlobalSet("x".to_owned()),
+                GlobalGet("x".to_owned()),
+                F64ConvertI32S,
+                GlobalSet("r".to_owned()),
+                F64Const(f64::MAX),
+                GlobalSet("r".to_owned()),
+                I32Const(8),
+                GlobalSet("x".to_owned()),
+                GlobalGet("r".to_owned()),
+                F64Abs,
+                GlobalSet("r".to_owned()),
+            ]
+        );
+        assert_eq!(module.funcs.len(), 1);
+
+        let source = "MODULE M;\nVAR r: REAL; x: INTEGER;\nBEGIN\n  x := FLOOR(x)\nEND M.";
+        assert!(matches!(
+            error_tag(compile_source(source)),
+            Some(ErrorTag::TypeMismatch { .. })
+        ));
+        let source = "MODULE M;\nVAR r: REAL; x: INTEGER;\nBEGIN\n  x := ABS(r)\nEND M.";
+        assert!(matches!(
+            error_tag(compile_source(source)),
+            Some(ErrorTag::TypeMismatch { .. })
+        ));
+        let source = "MODULE M;\nVAR r: REAL; x: INTEGER;\nBEGIN\n  x := r\nEND M.";
+        assert!(matches!(
+            error_tag(compile_source(source)),
+            Some(ErrorTag::TypeMismatch { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_string() -> ResultTest {
+        let source = "MODULE M;
+IMPORT Out;
+BEGIN
+  Out.String(\"ab\"); Out.String(\"c\"); Out.String(\"ab\")
+END M.";
+        let module = crate::parser::Parser::new(source).module()?;
+        let mut symbols = Table::new();
+        symbols.push("Out", library::symbols("Out").unwrap());
+        let (module, _) = compile(&module, &symbols, &Options::default())?;
+        use wat::Instr::*;
+        let data = || GlobalGet(DATA_ID.to_owned());
+        let call = || Call("Out.String".to_owned());
+        assert_eq!(
+            module.funcs[0].body,
+            vec![
+                data(),
+                I32Const(4),
+                I32Add,
+                call(),
+                data(),
+                I32Const(12),
+                I32Add,
+                call(),
+                data(),
+                I32Const(4),
+                I32Add,
+                call(),
+            ]
+        );
+        assert_eq!(
+            module.data,
+            vec![wat::Data {
+                offset: DATA_BASE,
+                bytes: b"\x03\0\0\0ab\0\0\x02\0\0\0c\0".to_vec()
+            }]
+        );
+        let global = module.globals.iter().find(|g| g.name == DATA_ID).unwrap();
+        assert_eq!(
+            (global.mutable, &global.init),
+            (false, &I32Const(DATA_BASE as i32))
+        );
+        assert_eq!(module.memories[0].min, 1);
+
+        let source = "MODULE M;\nVAR c: CHAR;\nBEGIN\n  c := \"a\"\nEND M.";
+        assert_eq!(
+            error_tag(compile_source(source)),
+            Some(ErrorTag::TypeMismatch {
+                expected: "CHAR".to_owned(),
+                got: "ARRAY OF CHAR".to_owned()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_array() -> ResultTest {
+        let source = "MODULE M;
+IMPORT In;
+VAR s: ARRAY 3 OF CHAR;
+BEGIN
+  In.String(s); In.String(\"a\")
+END M.";
+        let module = crate::parser::Parser::new(source).module()?;
+        let mut symbols = Table::new();
+        symbols.push("In", library::symbols("In").unwrap());
+        let (module, _) = compile(&module, &symbols, &Options::default())?;
+        use wat::Instr::*;
+        let data = || GlobalGet(DATA_ID.to_owned());
+        let call = || Call("In.String".to_owned());
+        assert_eq!(
+            module.funcs[0].body,
+            vec![
+                data(),
+                I32Const(12),
+                I32Add,
+                call(),
+                data(),
+                I32Const(4),
+                I32Add,
+                call()
+            ]
+        );
+        assert_eq!(
+            module.data[0].bytes,
+            b"\x02\0\0\0a\0\0\0\x03\0\0\0\0\0\0".to_vec()
+        );
+        assert!(module.globals.iter().all(|g| g.name != "s"));
+
+        let error = |source: &str| error_tag(compile_source(source));
+        let array = |name: &str| Some(ErrorTag::ArrayVariable(name.to_owned()));
+        assert_eq!(
+            error("MODULE M; VAR s*: ARRAY 3 OF CHAR; END M."),
+            array("s")
+        );
+        assert_eq!(
+            error("MODULE M; PROCEDURE P; VAR s: ARRAY 3 OF CHAR; END; END M."),
+            array("s")
+        );
+        assert_eq!(
+            error("MODULE M; VAR s: ARRAY 3 OF CHAR; BEGIN s := s END M."),
+            array("s")
+        );
+        assert_eq!(
+            error("MODULE M; VAR s: ARRAY 0 OF CHAR; END M."),
+            Some(ErrorTag::IntegerOutOfRange("0".to_owned()))
+        );
+        assert_eq!(
+            error("MODULE M; VAR s: ARRAY 3 OF INTEGER; END M."),
+            Some(ErrorTag::TypeMismatch {
+                expected: "CHAR".to_owned(),
+                got: "INTEGER".to_owned()
+            })
+        );
+        assert_eq!(
+            error("MODULE M; VAR s: ARRAY 3 OF CHAR; c: CHAR; BEGIN c := s END M."),
+            Some(ErrorTag::TypeMismatch {
+                expected: "CHAR".to_owned(),
+                got: "ARRAY 3 OF CHAR".to_owned()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_assert_pragma() -> ResultTest {
+        let source = "MODULE M;
+VAR b: BOOLEAN;
+BEGIN
+  (*$ASSERT-*) ASSERT(b); ASSERT(b, 9);
+  (*$ASSERT+*) ASSERT(b)
+END M.";
+        let module = compile_source(source)?;
+        use wat::Instr::*;
+        assert_eq!(
+            module.funcs[0].body,
+            vec![
+                GlobalGet("b".to_owned()),
+                I32Eqz,
+                If(vec![Unreachable(ASSERT_TRAP_CODE)]),
+            ]
+        );
+
+        let source = "MODULE M;\nVAR x: INTEGER;\nBEGIN\n  (*$ASSERT-*) ASSERT(x)\nEND M.";
+        assert!(matches!(
+            error_tag(compile_source(source)),
+            Some(ErrorTag::TypeMismatch { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_predeclared_shadowed() -> ResultTest {
+        let source = "MODULE M;
+PROCEDURE ABS(a, b: INTEGER): INTEGER;
+BEGIN
+  RETURN a
+END;
+BEGIN
+  ABS(1, 2)
+END M.";
+        let module = compile_source(source)?;
+        assert_eq!(module.funcs.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_predeclared_errors() {
+        let error = |stmts: &str| {
+            let source = format!(
+                "MODULE M;\nVAR x: INTEGER; c: CHAR; b: BOOLEAN;\nBEGIN\n  {stmts}\nEND M."
+            );
+            error_tag(compile_source(&source))
+        };
+        let mismatch = |expected: &str, got: &str| {
+            Some(ErrorTag::TypeMismatch {
+                expected: expected.to_owned(),
+                got: got.to_owned(),
+            })
+        };
+
+        assert_eq!(
+            error("INC(x, 1, 2)"),
+            Some(ErrorTag::ArgumentCountRange {
+                name: "INC".to_owned(),
+                min: 1,
+                max: 2,
+                got: 3
+            })
+        );
+        assert_eq!(
+            error("x := ABS()"),
+            Some(ErrorTag::ArgumentCount {
+                name: "ABS".to_owned(),
+                expected: 1,
+                got: 0
+            })
+        );
+        assert_eq!(
+            error("INC(3)"),
+            Some(ErrorTag::ExpectedVariableArgument("INC".to_owned()))
+        );
+        assert_eq!(error("DEC(c)"), mismatch("INTEGER", "CHAR"));
+        assert_eq!(error("INC(x, c)"), mismatch("INTEGER", "CHAR"));
+        assert_eq!(error("ASSERT(x)"), mismatch("BOOLEAN", "INTEGER"));
+        assert_eq!(error("x := ODD(x)"), mismatch("INTEGER", "BOOLEAN"));
+        assert_eq!(error("x := ABS(c)"), mismatch("INTEGER", "CHAR"));
+        assert_eq!(
+            error("x := MAX(x)"),
+            Some(ErrorTag::ExpectedType("MAX".to_owned()))
+        );
+        assert_eq!(
+            error("x := SIZE(1)"),
+            Some(ErrorTag::ExpectedType("SIZE".to_owned()))
+        );
+        assert_eq!(
+            error("HALT(x)"),
+            Some(ErrorTag::ExpectedTrapCode("HALT".to_owned()))
+        );
+        assert_eq!(
+            error("x := INC(x)"),
+            Some(ErrorTag::ExpectedFunctionProcedure("INC".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_compile_undefined_type() {
+        let undefined = |source: &str, tid: &str| {
+            assert_eq!(
+                error_tag(compile_source(source)),
+                Some(ErrorTag::UndefinedName(tid.to_owned())),
+                "{source}"
+            );
+        };
+        undefined("MODULE M; VAR x: Integr; END M.", "Integr");
+        undefined("MODULE M; PROCEDURE P(a: Chr); END; END M.", "Chr");
+        undefined(
+            "MODULE M; PROCEDURE P(): Bool; BEGIN RETURN 1 END; END M.",
+            "Bool",
+        );
+        undefined("MODULE M; PROCEDURE P; VAR a: T; END; END M.", "T");
+    }
+
+    #[test]
+    fn test_compile_suggestions() {
+        let help = |source: &str| compile_source(source).unwrap_err().errors()[0].help.clone();
+        assert_eq!(
+            help("MODULE M; VAR x: Integr; END M."),
+            Some("did you mean `INTEGER`?".to_owned())
+        );
+        assert_eq!(
+            help("MODULE M; VAR x: INTEGER; BEGIN INCC(x) END M."),
+            Some("did you mean `INC`?".to_owned())
+        );
+    }
 }
@@ -6,29 +6,296 @@ pub fn emit_module(module: &Module) -> String {
     let indent = "    ";
     let mut code = String::new();
 
-    code.push_str("(module $");
-    code.push_str(&module.name);
-    code.push_str("\n");
+    code.push_str("(module ");
+    emit_id(&mut code, &module.name);
+    code.push('\n');
+
+    for import in module.imports.iter() {
+        code.push_str(indent);
+        code.push_str("(import ");
+        emit_string(&mut code, import.module.as_bytes());
+        code.push(' ');
+        emit_string(&mut code, import.name.as_bytes());
+        code.push_str(" (func ");
+        emit_id(&mut code, &import.id);
+        for param in import.params.iter() {
+            code.push_str(" (param ");
+            emit_type(&mut code, param);
+            code.push(')');
+        }
+        emit_result(&mut code, &import.result);
+        code.push_str("))\n");
+    }
+
+    for table in module.tables.iter() {
+        code.push_str(indent);
+        code.push_str("(table ");
+        emit_id(&mut code, &table.name);
+        emit_limits(&mut code, table.min, table.max);
+        code.push_str(" funcref)\n");
+    }
+
+    for memory in module.memories.iter() {
+        code.push_str(indent);
+        code.push_str("(memory ");
+        emit_id(&mut code, &memory.name);
+        emit_limits(&mut code, memory.min, memory.max);
+        code.push_str(")\n");
+    }
+
+    for global in module.globals.iter() {
+        code.push_str(indent);
+        code.push_str("(global ");
+        emit_id(&mut code, &global.name);
+        code.push(' ');
+        if global.mutable {
+            code.push_str("(mut ");
+            emit_type(&mut code, &global.t);
+            code.push(')');
+        } else {
+            emit_type(&mut code, &global.t);
+        }
+        code.push_str(" (");
+        emit_instr(&mut code, &global.init);
+        code.push_str("))\n");
+    }
 
     for func in module.funcs.iter() {
         code.push_str(indent);
-        code.push_str("(func $");
-        code.push_str(&func.name);
-        code.push_str("\n");
+        code.push_str("(func ");
+        emit_id(&mut code, &func.name);
+        for param in func.params.iter() {
+            code.push_str(" (param ");
+            emit_id(&mut code, &param.name);
+            code.push(' ');
+            emit_type(&mut code, &param.t);
+            code.push(')');
+        }
+        emit_result(&mut code, &func.result);
+        for local in func.locals.iter() {
+            code.push_str(" (local ");
+            emit_id(&mut code, &local.name);
+            code.push(' ');
+            emit_type(&mut code, &local.t);
+            code.push(')');
+        }
+        code.push('\n');
+        emit_instrs(&mut code, &func.body, 2);
         code.push_str(indent);
         code.push_str(")\n");
     }
 
     for export in module.exports.iter() {
         code.push_str(indent);
-        code.push_str("(export \"");
-        code.push_str(&export.name);
-        code.push_str("\" (func $");
-        code.push_str(&export.name);
+        code.push_str("(export ");
+        emit_string(&mut code, export.name.as_bytes());
+        code.push_str(" (");
+        let (kind, id) = match &export.desc {
+            ExportDesc::Func(id) => ("func", id),
+            ExportDesc::Table(id) => ("table", id),
+            ExportDesc::Memory(id) => ("memory", id),
+            ExportDesc::Global(id) => ("global", id),
+        };
+        code.push_str(kind);
+        code.push(' ');
+        emit_id(&mut code, id);
         code.push_str("))\n");
     }
 
+    if let Some(start) = &module.start {
+        code.push_str(indent);
+        code.push_str("(start ");
+        emit_id(&mut code, start);
+        code.push_str(")\n");
+    }
+
+    for data in module.data.iter() {
+        code.push_str(indent);
+        code.push_str("(data (i32.const ");
+        code.push_str(&data.offset.to_string());
+        code.push_str(") ");
+        emit_string(&mut code, &data.bytes);
+        code.push_str(")\n");
+    }
+
     code.push_str(")\n");
 
     code
 }
+
+/// Emits an identifier. Identifiers can only have printable ASCII characters
+/// other than spaces, quotes, brackets, `,` and `;`, so other characters are
+/// written as their hex code, e.g. `Größe` as `$Gr_uf6__udf_e`. Source names
+/// can't have `_`, so this doesn't clash.
+fn emit_id(code: &mut String, id: &str) {
+    code.push('$');
+    for c in id.chars() {
+        if c.is_ascii_graphic() && !matches!(c, '"' | '(' | ')' | ',' | ';' | '[' | ']' | '{' | '}')
+        {
+            code.push(c);
+        } else {
+            code.push_str(&format!("_u{:x}_", c as u32));
+        }
+    }
+}
+
+/// Emits a string, escaping quotes, backslashes and bytes that aren't
+/// printable ASCII.
+fn emit_string(code: &mut String, bytes: &[u8]) {
+    code.push('"');
+    for &byte in bytes.iter() {
+        match byte {
+            b'"' | b'\\' => {
+                code.push('\\');
+                code.push(byte as char);
+            }
+            0x20..=0x7E => code.push(byte as char),
+            _ => code.push_str(&format!("\\{byte:02x}")),
+        }
+    }
+    code.push('"');
+}
+
+/// Emits a float, in the shortest decimal form that reads back as the same
+/// value, e.g. `0.1` or `1e300`, or as `inf`, `-inf` or `nan`.
+fn emit_f64(code: &mut String, value: f64) {
+    if value.is_nan() {
+        code.push_str("nan");
+    } else if value.is_infinite() {
+        code.push_str(if value < 0.0 { "-inf" } else { "inf" });
+    } else {
+        code.push_str(&format!("{value:?}"));
+    }
+}
+
+fn emit_limits(code: &mut String, min: u32, max: Option<u32>) {
+    code.push(' ');
+    code.push_str(&min.to_string());
+    if let Some(max) = max {
+        code.push(' ');
+        code.push_str(&max.to_string());
+    }
+}
+
+fn emit_result(code: &mut String, result: &Option<Type>) {
+    if let Some(result) = result {
+        code.push_str(" (result ");
+        emit_type(code, result);
+        code.push(')');
+    }
+}
+
+fn emit_type(code: &mut String, t: &Type) {
+    match t {
+        Type::F64 => code.push_str("f64"),
+        Type::I32 => code.push_str("i32"),
+    }
+}
+
+/// Emits instructions one per line, indented by the given depth, with the
+/// instructions in a block indented further.
+fn emit_instrs(code: &mut String, instrs: &[Instr], depth: usize) {
+    let indent = "    ".repeat(depth);
+    for instr in instrs.iter() {
+        code.push_str(&indent);
+        match instr {
+            Instr::If(body) => emit_block(code, "if", body, depth),
+            Instr::Loop(body) => emit_block(code, "loop", body, depth),
+            _ => emit_instr(code, instr),
+        }
+        code.push('\n');
+    }
+}
+
+/// Emits a block's instructions after its keyword, ending with `end`.
+fn emit_block(code: &mut String, keyword: &str, body: &[Instr], depth: usize) {
+    code.push_str(keyword);
+    code.push('\n');
+    emit_instrs(code, body, depth + 1);
+    code.push_str(&"    ".repeat(depth));
+    code.push_str("end");
+}
+
+/// Emits a memory instruction, with its offset unless it's 0.
+fn emit_memarg(code: &mut String, name: &str, offset: u32) {
+    code.push_str(name);
+    if offset != 0 {
+        code.push_str(" offset=");
+        code.push_str(&offset.to_string());
+    }
+}
+
+/// Emits an instruction that isn't a block.
+fn emit_instr(code: &mut String, instr: &Instr) {
+    match instr {
+        Instr::BrIf(label) => {
+            code.push_str("br_if ");
+            code.push_str(&label.to_string());
+        }
+        Instr::Call(id) => {
+            code.push_str("call ");
+            emit_id(code, id);
+        }
+        Instr::Drop => code.push_str("drop"),
+        Instr::F64Abs => code.push_str("f64.abs"),
+        Instr::F64Add => code.push_str("f64.add"),
+        Instr::F64Const(value) => {
+            code.push_str("f64.const ");
+            emit_f64(code, *value);
+        }
+        Instr::F64ConvertI32S => code.push_str("f64.convert_i32_s"),
+        Instr::F64Div => code.push_str("f64.div"),
+        Instr::F64Eq => code.push_str("f64.eq"),
+        Instr::F64Floor => code.push_str("f64.floor"),
+        Instr::F64Gt => code.push_str("f64.gt"),
+        Instr::F64Lt => code.push_str("f64.lt"),
+        Instr::F64Mul => code.push_str("f64.mul"),
+        Instr::F64Ne => code.push_str("f64.ne"),
+        Instr::F64Nearest => code.push_str("f64.nearest"),
+        Instr::F64Neg => code.push_str("f64.neg"),
+        Instr::F64Sqrt => code.push_str("f64.sqrt"),
+        Instr::F64Sub => code.push_str("f64.sub"),
+        Instr::GlobalGet(name) => {
+            code.push_str("global.get ");
+            emit_id(code, name);
+        }
+        Instr::GlobalSet(name) => {
+            code.push_str("global.set ");
+            emit_id(code, name);
+        }
+        Instr::I32Add => code.push_str("i32.add"),
+        Instr::I32And => code.push_str("i32.and"),
+        Instr::I32Const(value) => {
+            code.push_str("i32.const ");
+            code.push_str(&value.to_string());
+        }
+        Instr::I32DivU => code.push_str("i32.div_u"),
+        Instr::I32Eq => code.push_str("i32.eq"),
+        Instr::I32Eqz => code.push_str("i32.eqz"),
+        Instr::I32Load(offset) => emit_memarg(code, "i32.load", *offset),
+        Instr::I32Load8U(offset) => emit_memarg(code, "i32.load8_u", *offset),
+        Instr::I32LtS => code.push_str("i32.lt_s"),
+        Instr::I32LtU => code.push_str("i32.lt_u"),
+        Instr::I32Mul => code.push_str("i32.mul"),
+        Instr::I32RemU => code.push_str("i32.rem_u"),
+        Instr::I32Store(offset) => emit_memarg(code, "i32.store", *offset),
+        Instr::I32Store8(offset) => emit_memarg(code, "i32.store8", *offset),
+        Instr::I32Sub => code.push_str("i32.sub"),
+        Instr::I32TruncF64S => code.push_str("i32.trunc_f64_s"),
+        Instr::If(_) | Instr::Loop(_) => unreachable!("blocks are emitted by emit_instrs"),
+        Instr::LocalGet(name) => {
+            code.push_str("local.get ");
+            emit_id(code, name);
+        }
+        Instr::LocalSet(name) => {
+            code.push_str("local.set ");
+            emit_id(code, name);
+        }
+        Instr::Return => code.push_str("return"),
+        Instr::Unreachable(trap_code) => {
+            code.push_str("unreachable (; trap ");
+            code.push_str(&trap_code.to_string());
+            code.push_str(" ;)");
+        }
+    }
+}
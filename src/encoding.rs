@@ -0,0 +1,552 @@
+//! Binary encoding of WAT modules as WebAssembly `.wasm` files.
+//!
+//! Identifiers are resolved to indices in their index spaces, where imported
+//! functions come before the module's functions. Function types are shared
+//! between functions with the same signature.
+
+use std::collections::HashMap;
+
+use crate::ast::wat::*;
+
+/// The `\0asm` magic number followed by the binary format version.
+const PREAMBLE: [u8; 8] = [0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+
+/// Section identifiers.
+const SECTION_TYPE: u8 = 1;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_FUNCTION: u8 = 3;
+const SECTION_TABLE: u8 = 4;
+const SECTION_MEMORY: u8 = 5;
+const SECTION_GLOBAL: u8 = 6;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_START: u8 = 8;
+const SECTION_CODE: u8 = 10;
+const SECTION_DATA: u8 = 11;
+
+/// Encodes a module in the WebAssembly binary format.
+pub fn encode_module(module: &Module) -> Vec<u8> {
+    let mut types: Vec<(Vec<Type>, Option<Type>)> = Vec::new();
+    let mut type_index = |params: Vec<Type>, result: Option<Type>| {
+        let signature = (params, result);
+        match types.iter().position(|t| *t == signature) {
+            Some(i) => i,
+            None => {
+                types.push(signature);
+                types.len() - 1
+            }
+        }
+    };
+
+    let import_types: Vec<usize> = module
+        .imports
+        .iter()
+        .map(|import| type_index(import.params.clone(), import.result.clone()))
+        .collect();
+    let func_types: Vec<usize> = module
+        .funcs
+        .iter()
+        .map(|func| {
+            let params = func.params.iter().map(|param| param.t.clone()).collect();
+            type_index(params, func.result.clone())
+        })
+        .collect();
+
+    let ids = Ids::new(module);
+    let mut code = PREAMBLE.to_vec();
+
+    section(
+        &mut code,
+        SECTION_TYPE,
+        &types,
+        |bytes, (params, result)| {
+            bytes.push(0x60);
+            vec(bytes, params, encode_type);
+            vec(bytes, result.as_slice(), encode_type);
+        },
+    );
+
+    let imports: Vec<_> = module.imports.iter().zip(import_types).collect();
+    section(&mut code, SECTION_IMPORT, &imports, |bytes, (import, t)| {
+        name(bytes, &import.module);
+        name(bytes, &import.name);
+        bytes.push(0x00);
+        unsigned(bytes, *t as u64);
+    });
+
+    section(&mut code, SECTION_FUNCTION, &func_types, |bytes, t| {
+        unsigned(bytes, *t as u64);
+    });
+
+    section(&mut code, SECTION_TABLE, &module.tables, |bytes, table| {
+        bytes.push(0x70);
+        limits(bytes, table.min, table.max);
+    });
+
+    section(
+        &mut code,
+        SECTION_MEMORY,
+        &module.memories,
+        |bytes, memory| {
+            limits(bytes, memory.min, memory.max);
+        },
+    );
+
+    section(
+        &mut code,
+        SECTION_GLOBAL,
+        &module.globals,
+        |bytes, global| {
+            encode_type(bytes, &global.t);
+            bytes.push(global.mutable as u8);
+            encode_instr(bytes, &ids, &HashMap::new(), &global.init);
+            bytes.push(0x0B);
+        },
+    );
+
+    section(
+        &mut code,
+        SECTION_EXPORT,
+        &module.exports,
+        |bytes, export| {
+            name(bytes, &export.name);
+            let (kind, index) = match &export.desc {
+                ExportDesc::Func(id) => (0x00, ids.func(id)),
+                ExportDesc::Table(id) => (0x01, ids.table(id)),
+                ExportDesc::Memory(id) => (0x02, ids.memory(id)),
+                ExportDesc::Global(id) => (0x03, ids.global(id)),
+            };
+            bytes.push(kind);
+            unsigned(bytes, index as u64);
+        },
+    );
+
+    if let Some(start) = &module.start {
+        let mut bytes = Vec::new();
+        unsigned(&mut bytes, ids.func(start) as u64);
+        code.push(SECTION_START);
+        unsigned(&mut code, bytes.len() as u64);
+        code.extend(bytes);
+    }
+
+    section(&mut code, SECTION_CODE, &module.funcs, |bytes, func| {
+        let locals = func.params.iter().chain(func.locals.iter());
+        let locals = index_of(locals.map(|local| &local.name));
+        let mut body = Vec::new();
+        // A local declaration for each local, rather than one per run of
+        // locals with the same type.
+        vec(&mut body, &func.locals, |bytes, local| {
+            unsigned(bytes, 1);
+            encode_type(bytes, &local.t);
+        });
+        for instr in func.body.iter() {
+            encode_instr(&mut body, &ids, &locals, instr);
+        }
+        body.push(0x0B);
+        unsigned(bytes, body.len() as u64);
+        bytes.extend(body);
+    });
+
+    section(&mut code, SECTION_DATA, &module.data, |bytes, data| {
+        // An active segment in memory 0.
+        bytes.push(0x00);
+        encode_instr(
+            bytes,
+            &ids,
+            &HashMap::new(),
+            &Instr::I32Const(data.offset as i32),
+        );
+        bytes.push(0x0B);
+        unsigned(bytes, data.bytes.len() as u64);
+        bytes.extend(&data.bytes);
+    });
+
+    code
+}
+
+/// The indices of a module's identifiers, by index space.
+struct Ids<'a> {
+    funcs: HashMap<&'a str, usize>,
+    tables: HashMap<&'a str, usize>,
+    memories: HashMap<&'a str, usize>,
+    globals: HashMap<&'a str, usize>,
+}
+
+impl<'a> Ids<'a> {
+    fn new(module: &'a Module) -> Self {
+        let imports = module.imports.iter().map(|import| &import.id);
+        let funcs = module.funcs.iter().map(|func| &func.name);
+        Self {
+            funcs: index_of(imports.chain(funcs)),
+            tables: index_of(module.tables.iter().map(|table| &table.name)),
+            memories: index_of(module.memories.iter().map(|memory| &memory.name)),
+            globals: index_of(module.globals.iter().map(|global| &global.name)),
+        }
+    }
+
+    fn func(&self, id: &str) -> usize {
+        lookup(&self.funcs, "function", id)
+    }
+
+    fn table(&self, id: &str) -> usize {
+        lookup(&self.tables, "table", id)
+    }
+
+    fn memory(&self, id: &str) -> usize {
+        lookup(&self.memories, "memory", id)
+    }
+
+    fn global(&self, id: &str) -> usize {
+        lookup(&self.globals, "global", id)
+    }
+}
+
+/// Maps identifiers to their position.
+fn index_of<'a>(ids: impl Iterator<Item = &'a String>) -> HashMap<&'a str, usize> {
+    ids.enumerate().map(|(i, id)| (id.as_str(), i)).collect()
+}
+
+/// Lookup the index of an identifier, which the compiler has already checked
+/// is defined.
+fn lookup(index: &HashMap<&str, usize>, kind: &str, id: &str) -> usize {
+    match index.get(id) {
+        Some(&i) => i,
+        None => panic!("undefined {kind} `{id}`"),
+    }
+}
+
+/// Appends a section holding a vector of items, unless there are no items.
+fn section<T>(code: &mut Vec<u8>, id: u8, items: &[T], encode: impl Fn(&mut Vec<u8>, &T)) {
+    if items.is_empty() {
+        return;
+    }
+    let mut bytes = Vec::new();
+    vec(&mut bytes, items, encode);
+    code.push(id);
+    unsigned(code, bytes.len() as u64);
+    code.extend(bytes);
+}
+
+/// Appends a vector, its length followed by its items.
+fn vec<T>(bytes: &mut Vec<u8>, items: &[T], encode: impl Fn(&mut Vec<u8>, &T)) {
+    unsigned(bytes, items.len() as u64);
+    for item in items {
+        encode(bytes, item);
+    }
+}
+
+/// Appends a UTF-8 name.
+fn name(bytes: &mut Vec<u8>, name: &str) {
+    unsigned(bytes, name.len() as u64);
+    bytes.extend(name.as_bytes());
+}
+
+/// Appends table or memory limits.
+fn limits(bytes: &mut Vec<u8>, min: u32, max: Option<u32>) {
+    match max {
+        None => {
+            bytes.push(0x00);
+            unsigned(bytes, min as u64);
+        }
+        Some(max) => {
+            bytes.push(0x01);
+            unsigned(bytes, min as u64);
+            unsigned(bytes, max as u64);
+        }
+    }
+}
+
+fn encode_type(bytes: &mut Vec<u8>, t: &Type) {
+    match t {
+        Type::F64 => bytes.push(0x7C),
+        Type::I32 => bytes.push(0x7F),
+    }
+}
+
+fn encode_instr(bytes: &mut Vec<u8>, ids: &Ids, locals: &HashMap<&str, usize>, instr: &Instr) {
+    match instr {
+        Instr::BrIf(label) => {
+            bytes.push(0x0D);
+            unsigned(bytes, *label as u64);
+        }
+        Instr::Call(id) => {
+            bytes.push(0x10);
+            unsigned(bytes, ids.func(id) as u64);
+        }
+        Instr::Drop => bytes.push(0x1A),
+        Instr::F64Abs => bytes.push(0x99),
+        Instr::F64Add => bytes.push(0xA0),
+        Instr::F64Const(value) => {
+            bytes.push(0x44);
+            bytes.extend(value.to_le_bytes());
+        }
+        Instr::F64ConvertI32S => bytes.push(0xB7),
+        Instr::F64Div => bytes.push(0xA3),
+        Instr::F64Eq => bytes.push(0x61),
+        Instr::F64Floor => bytes.push(0x9C),
+        Instr::F64Gt => bytes.push(0x64),
+        Instr::F64Lt => bytes.push(0x63),
+        Instr::F64Mul => bytes.push(0xA2),
+        Instr::F64Ne => bytes.push(0x62),
+        Instr::F64Nearest => bytes.push(0x9E),
+        Instr::F64Neg => bytes.push(0x9A),
+        Instr::F64Sqrt => bytes.push(0x9F),
+        Instr::F64Sub => bytes.push(0xA1),
+        Instr::GlobalGet(id) => {
+            bytes.push(0x23);
+            unsigned(bytes, ids.global(id) as u64);
+        }
+        Instr::GlobalSet(id) => {
+            bytes.push(0x24);
+            unsigned(bytes, ids.global(id) as u64);
+        }
+        Instr::I32Add => bytes.push(0x6A),
+        Instr::I32And => bytes.push(0x71),
+        Instr::I32Const(value) => {
+            bytes.push(0x41);
+            signed(bytes, *value as i64);
+        }
+        Instr::I32DivU => bytes.push(0x6E),
+        Instr::I32Eq => bytes.push(0x46),
+        Instr::I32Eqz => bytes.push(0x45),
+        Instr::I32Load(offset) => {
+            // Aligned to 4 bytes.
+            bytes.extend([0x28, 0x02]);
+            unsigned(bytes, *offset as u64);
+        }
+        Instr::I32Load8U(offset) => {
+            bytes.extend([0x2D, 0x00]);
+            unsigned(bytes, *offset as u64);
+        }
+        Instr::I32LtS => bytes.push(0x48),
+        Instr::I32LtU => bytes.push(0x49),
+        Instr::I32Mul => bytes.push(0x6C),
+        Instr::I32RemU => bytes.push(0x70),
+        Instr::I32Store(offset) => {
+            // Aligned to 4 bytes.
+            bytes.extend([0x36, 0x02]);
+            unsigned(bytes, *offset as u64);
+        }
+        Instr::I32Store8(offset) => {
+            bytes.extend([0x3A, 0x00]);
+            unsigned(bytes, *offset as u64);
+        }
+        Instr::I32Sub => bytes.push(0x6B),
+        Instr::I32TruncF64S => bytes.push(0xAA),
+        Instr::If(body) => {
+            // A block with no parameters or results.
+            bytes.extend([0x04, 0x40]);
+            for instr in body.iter() {
+                encode_instr(bytes, ids, locals, instr);
+            }
+            bytes.push(0x0B);
+        }
+        Instr::LocalGet(name) => {
+            bytes.push(0x20);
+            unsigned(bytes, lookup(locals, "local", name) as u64);
+        }
+        Instr::LocalSet(name) => {
+            bytes.push(0x21);
+            unsigned(bytes, lookup(locals, "local", name) as u64);
+        }
+        Instr::Loop(body) => {
+            bytes.extend([0x03, 0x40]);
+            for instr in body.iter() {
+                encode_instr(bytes, ids, locals, instr);
+            }
+            bytes.push(0x0B);
+        }
+        Instr::Return => bytes.push(0x0F),
+        // The trap code has no encoding.
+        Instr::Unreachable(_) => bytes.push(0x00),
+    }
+}
+
+/// Appends an unsigned LEB128 integer.
+fn unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Appends a signed LEB128 integer.
+fn signed(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::wat::builder::*;
+
+    fn module(name: &str) -> Module {
+        Module {
+            name: name.to_owned(),
+            imports: Vec::new(),
+            tables: Vec::new(),
+            memories: Vec::new(),
+            globals: Vec::new(),
+            funcs: Vec::new(),
+            exports: Vec::new(),
+            start: None,
+            data: Vec::new(),
+        }
+    }
+
+    fn leb128(encode: impl Fn(&mut Vec<u8>)) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        encode(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn test_unsigned() {
+        assert_eq!(leb128(|b| unsigned(b, 0)), vec![0x00]);
+        assert_eq!(leb128(|b| unsigned(b, 127)), vec![0x7F]);
+        assert_eq!(leb128(|b| unsigned(b, 128)), vec![0x80, 0x01]);
+        assert_eq!(leb128(|b| unsigned(b, 624485)), vec![0xE5, 0x8E, 0x26]);
+    }
+
+    #[test]
+    fn test_signed() {
+        assert_eq!(leb128(|b| signed(b, 0)), vec![0x00]);
+        assert_eq!(leb128(|b| signed(b, 63)), vec![0x3F]);
+        assert_eq!(leb128(|b| signed(b, 64)), vec![0xC0, 0x00]);
+        assert_eq!(leb128(|b| signed(b, -1)), vec![0x7F]);
+        assert_eq!(leb128(|b| signed(b, -123456)), vec![0xC0, 0xBB, 0x78]);
+        assert_eq!(
+            leb128(|b| signed(b, i32::MIN as i64)),
+            vec![0x80, 0x80, 0x80, 0x80, 0x78]
+        );
+    }
+
+    #[test]
+    fn test_encode_module_empty() {
+        assert_eq!(encode_module(&module("M")), PREAMBLE.to_vec());
+    }
+
+    #[test]
+    fn test_encode_module_func() {
+        let mut m = module("M");
+        m.imports.push(Import {
+            module: "env".to_owned(),
+            name: "log".to_owned(),
+            id: "Log".to_owned(),
+            params: vec![Type::I32],
+            result: None,
+        });
+        m.funcs.push(
+            BuilderFunc::new()
+                .set_name("P")
+                .add_param("x", Type::I32)
+                .set_body(vec![
+                    Instr::LocalGet("x".to_owned()),
+                    Instr::Call("Log".to_owned()),
+                ])
+                .build(),
+        );
+        m.exports.push(Export {
+            name: "p".to_owned(),
+            desc: ExportDesc::Func("P".to_owned()),
+        });
+
+        let mut expected = PREAMBLE.to_vec();
+        // One shared type, (i32) -> ().
+        expected.extend([SECTION_TYPE, 5, 1, 0x60, 1, 0x7F, 0]);
+        expected.extend([SECTION_IMPORT, 11, 1, 3, b'e', b'n', b'v', 3]);
+        expected.extend([b'l', b'o', b'g', 0x00, 0]);
+        expected.extend([SECTION_FUNCTION, 2, 1, 0]);
+        expected.extend([SECTION_EXPORT, 5, 1, 1, b'p', 0x00, 1]);
+        expected.extend([SECTION_CODE, 8, 1, 6, 0, 0x20, 0, 0x10, 0, 0x0B]);
+        assert_eq!(encode_module(&m), expected);
+    }
+
+    #[test]
+    fn test_encode_module_blocks() {
+        let mut m = module("M");
+        m.funcs.push(
+            BuilderFunc::new()
+                .set_name("P")
+                .set_body(vec![
+                    Instr::Loop(vec![Instr::I32Const(0), Instr::BrIf(0)]),
+                    Instr::I32Const(0),
+                    Instr::I32Const(1),
+                    Instr::I32Store8(2),
+                ])
+                .build(),
+        );
+
+        let mut expected = PREAMBLE.to_vec();
+        expected.extend([SECTION_TYPE, 4, 1, 0x60, 0, 0]);
+        expected.extend([SECTION_FUNCTION, 2, 1, 0]);
+        expected.extend([SECTION_CODE, 18, 1, 16, 0]);
+        expected.extend([0x03, 0x40, 0x41, 0, 0x0D, 0, 0x0B]);
+        expected.extend([0x41, 0, 0x41, 1, 0x3A, 0, 2, 0x0B]);
+        assert_eq!(encode_module(&m), expected);
+    }
+
+    #[test]
+    fn test_encode_module_f64() {
+        let mut m = module("M");
+        m.funcs.push(
+            BuilderFunc::new()
+                .set_name("P")
+                .add_param("x", Type::F64)
+                .set_result(Some(Type::F64))
+                .set_body(vec![
+                    Instr::LocalGet("x".to_owned()),
+                    Instr::F64Const(1.5),
+                    Instr::F64Add,
+                ])
+                .build(),
+        );
+
+        let mut expected = PREAMBLE.to_vec();
+        expected.extend([SECTION_TYPE, 6, 1, 0x60, 1, 0x7C, 1, 0x7C]);
+        expected.extend([SECTION_FUNCTION, 2, 1, 0]);
+        expected.extend([SECTION_CODE, 16, 1, 14, 0, 0x20, 0]);
+        expected.extend([0x44, 0, 0, 0, 0, 0, 0, 0xF8, 0x3F, 0xA0, 0x0B]);
+        assert_eq!(encode_module(&m), expected);
+    }
+
+    #[test]
+    fn test_encode_module_memory_global_data() {
+        let mut m = module("M");
+        m.memories.push(Memory {
+            name: "memory".to_owned(),
+            min: 1,
+            max: Some(2),
+        });
+        m.globals.push(Global {
+            name: "x".to_owned(),
+            t: Type::I32,
+            mutable: true,
+            init: Instr::I32Const(-1),
+        });
+        m.data.push(Data {
+            offset: 8,
+            bytes: b"hi".to_vec(),
+        });
+
+        let mut expected = PREAMBLE.to_vec();
+        expected.extend([SECTION_MEMORY, 4, 1, 0x01, 1, 2]);
+        expected.extend([SECTION_GLOBAL, 6, 1, 0x7F, 1, 0x41, 0x7F, 0x0B]);
+        expected.extend([SECTION_DATA, 8, 1, 0x00, 0x41, 8, 0x0B, 2, b'h', b'i']);
+        assert_eq!(encode_module(&m), expected);
+    }
+}
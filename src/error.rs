@@ -1,25 +1,176 @@
 //! Error handling.
 use std::fmt;
 
+use crate::json;
 use crate::scanner::TokenTag;
+use crate::span::Span;
 
 /// Enumerates all possible errors.
 #[derive(Clone, Debug, PartialEq)]
 pub enum ErrorTag {
+    /// A call has the wrong number of actual parameters.
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+
+    /// A call of a predeclared procedure with optional parameters has the
+    /// wrong number of actual parameters.
+    ArgumentCountRange {
+        name: String,
+        min: usize,
+        max: usize,
+        got: usize,
+    },
+
+    /// A variable is declared as an array, or an array variable is used,
+    /// other than as a module variable passed to an `ARRAY OF CHAR`
+    /// parameter.
+    ArrayVariable(String),
+
+    /// A character literal's code is too big for `CHAR`.
+    CharacterOutOfRange(String),
+
     /// Expected an identifier token tag, but got a different token tag.
     ExpectedIdentifier { got: TokenTag },
 
     /// Expected a token tag, but got a different token tag.
     ExpectedToken { expected: TokenTag, got: TokenTag },
 
+    /// A procedure without a result was called in an expression.
+    ExpectedFunctionProcedure(String),
+
+    /// A name that isn't a procedure was called as one.
+    ExpectedProcedure(String),
+
+    /// A trap code, for the named predeclared procedure, isn't an integer
+    /// literal.
+    ExpectedTrapCode(String),
+
+    /// An argument of the named predeclared procedure isn't a type.
+    ExpectedType(String),
+
+    /// A name that isn't a variable was used as one.
+    ExpectedVariable(String),
+
+    /// The argument the named predeclared procedure changes isn't a
+    /// variable.
+    ExpectedVariableArgument(String),
+
+    /// An export name is used more than once.
+    ExportRedefinition(String),
+
+    /// A procedure's local variable is marked for export.
+    ExportedLocal(String),
+
+    /// An integer literal doesn't fit in its type.
+    IntegerOutOfRange(String),
+
+    /// A number literal has hex digits but no `H` or `X` suffix.
+    InvalidNumber(String),
+
+    /// A source text isn't UTF-8, from the byte offset given on.
+    InvalidUtf8(usize),
+
+    /// A symbol file couldn't be read.
+    InvalidSymbolFile(String),
+
+    /// The name at the end of a module doesn't match the module's name.
+    ModuleNameMismatch { expected: String, got: String },
+
+    /// A function procedure can end without returning a value.
+    MissingReturn(String),
+
+    /// A function procedure has a `RETURN` without a value.
+    MissingReturnValue(String),
+
+    /// A real literal's scale factor, after its `E`, has no digits.
+    MissingScaleFactor(String),
+
     /// A name previously defined was used in a definition.
     NameRedefinition(String),
 
+    /// A qualified identifier referred to a name the module doesn't export.
+    NotExported { module: String, name: String },
+
+    /// A real literal is too big for `REAL`.
+    RealOutOfRange(String),
+
+    /// An expression's type isn't the type expected.
+    TypeMismatch { expected: String, got: String },
+
+    /// A module was used but never imported, or has no symbol file.
+    UndefinedModule(String),
+
+    /// A symbol file read for an import is for a different module.
+    SymbolModuleMismatch { expected: String, got: String },
+
+    /// A name was used but never defined.
+    UndefinedName(String),
+
     /// An unexpected character was encountered.
     UnexpectedCharacter(char),
 
+    /// A proper procedure or module body has a `RETURN` with a value.
+    UnexpectedReturnValue,
+
+    /// A procedure has an export name but isn't exported.
+    UnexportedExportName(String),
+
+    /// A procedure attribute isn't known.
+    UnknownAttribute(String),
+
     /// A comment was not terminated.
     UnterminatedComment,
+
+    /// A string was not terminated before the end of its line.
+    UnterminatedString,
+}
+
+impl ErrorTag {
+    /// The error's stable code, for tools reading the compiler's output.
+    pub fn code(&self) -> &'static str {
+        use ErrorTag::*;
+
+        match self {
+            ArgumentCount { .. } => "E001",
+            ExpectedIdentifier { .. } => "E002",
+            ExpectedToken { .. } => "E003",
+            ExpectedFunctionProcedure(_) => "E004",
+            ExpectedVariable(_) => "E005",
+            ExportRedefinition(_) => "E006",
+            ExportedLocal(_) => "E007",
+            IntegerOutOfRange(_) => "E008",
+            InvalidSymbolFile(_) => "E009",
+            ModuleNameMismatch { .. } => "E010",
+            NameRedefinition(_) => "E011",
+            NotExported { .. } => "E012",
+            UndefinedModule(_) => "E013",
+            UndefinedName(_) => "E014",
+            UnexpectedCharacter(_) => "E015",
+            UnexportedExportName(_) => "E016",
+            UnknownAttribute(_) => "E017",
+            UnterminatedComment => "E018",
+            UnterminatedString => "E019",
+            MissingReturn(_) => "E020",
+            MissingReturnValue(_) => "E021",
+            TypeMismatch { .. } => "E022",
+            UnexpectedReturnValue => "E023",
+            CharacterOutOfRange(_) => "E024",
+            InvalidNumber(_) => "E025",
+            InvalidUtf8(_) => "E026",
+            ArgumentCountRange { .. } => "E027",
+            ExpectedTrapCode(_) => "E028",
+            ExpectedType(_) => "E029",
+            ExpectedVariableArgument(_) => "E030",
+            SymbolModuleMismatch { .. } => "E031",
+            RealOutOfRange(_) => "E032",
+            MissingScaleFactor(_) => "E033",
+            ArrayVariable(_) => "E034",
+            ExpectedProcedure(_) => "E035",
+        }
+    }
 }
 
 impl fmt::Display for ErrorTag {
@@ -27,19 +178,126 @@ impl fmt::Display for ErrorTag {
         use ErrorTag::*;
 
         match self {
+            ArgumentCount {
+                name,
+                expected,
+                got,
+            } => {
+                write!(f, "`{name}` expects {expected} argument(s) but got {got}")
+            }
+            ArgumentCountRange {
+                name,
+                min,
+                max,
+                got,
+            } => {
+                write!(f, "`{name}` expects {min} to {max} arguments but got {got}")
+            }
+            ArrayVariable(name) => {
+                write!(
+                    f,
+                    "array `{name}` can only be an unexported module variable passed to a procedure"
+                )
+            }
+            CharacterOutOfRange(lexeme) => {
+                write!(f, "character `{lexeme}` is out of range")
+            }
             ExpectedIdentifier { got } => {
                 write!(f, "expected an identifier but got `{got}`")
             }
             ExpectedToken { expected, got } => {
                 write!(f, "expected `{expected}` but got `{got}`")
             }
+            ExpectedFunctionProcedure(name) => {
+                write!(f, "procedure `{name}` doesn't return a value")
+            }
+            ExpectedProcedure(name) => {
+                write!(f, "`{name}` is not a procedure")
+            }
+            ExpectedTrapCode(name) => {
+                write!(f, "the trap code of `{name}` must be an integer literal")
+            }
+            ExpectedType(name) => {
+                write!(f, "the argument of `{name}` must be a type")
+            }
+            ExpectedVariable(name) => {
+                write!(f, "`{name}` is not a variable")
+            }
+            ExpectedVariableArgument(name) => {
+                write!(f, "the first argument of `{name}` must be a variable")
+            }
+            ExportRedefinition(name) => {
+                write!(f, "export name `{name}` was previously used")
+            }
+            ExportedLocal(name) => {
+                write!(f, "local variable `{name}` can't be exported")
+            }
+            IntegerOutOfRange(lexeme) => {
+                write!(f, "integer `{lexeme}` is out of range")
+            }
+            InvalidNumber(lexeme) => {
+                write!(
+                    f,
+                    "number `{lexeme}` has hex digits but no `H` or `X` suffix"
+                )
+            }
+            InvalidUtf8(offset) => {
+                write!(f, "invalid UTF-8 at byte offset {offset}")
+            }
+            InvalidSymbolFile(reason) => {
+                write!(f, "invalid symbol file: {reason}")
+            }
+            ModuleNameMismatch { expected, got } => {
+                write!(f, "expected module name `{expected}` but got `{got}`")
+            }
+            MissingReturn(name) => {
+                write!(
+                    f,
+                    "function procedure `{name}` can end without returning a value"
+                )
+            }
+            MissingReturnValue(name) => {
+                write!(f, "function procedure `{name}` must return a value")
+            }
+            MissingScaleFactor(lexeme) => {
+                write!(f, "real `{lexeme}` has no digits after `E`")
+            }
             NameRedefinition(name) => {
                 write!(f, "name `{name}` was previously defined")
             }
+            NotExported { module, name } => {
+                write!(f, "module `{module}` doesn't export `{name}`")
+            }
+            RealOutOfRange(lexeme) => {
+                write!(f, "real `{lexeme}` is out of range")
+            }
+            SymbolModuleMismatch { expected, got } => {
+                write!(f, "expected symbols of module `{expected}` but got `{got}`")
+            }
+            TypeMismatch { expected, got } => {
+                write!(f, "expected type `{expected}` but got `{got}`")
+            }
+            UndefinedModule(name) => {
+                write!(f, "module `{name}` is not defined")
+            }
+            UndefinedName(name) => {
+                write!(f, "name `{name}` is not defined")
+            }
             UnexpectedCharacter(c) => {
                 write!(f, "unexpected character `{c}`")
             }
+            UnexpectedReturnValue => {
+                write!(f, "only function procedures can return a value")
+            }
+            UnexportedExportName(name) => {
+                write!(
+                    f,
+                    "procedure `{name}` has an export name but isn't exported"
+                )
+            }
+            UnknownAttribute(name) => write!(f, "unknown attribute `{name}`"),
             UnterminatedComment => write!(f, "unterminated comment"),
+            UnterminatedString => write!(f, "unterminated string"),
         }
     }
 }
@@ -50,37 +308,254 @@ pub struct Error {
     /// What kind of error was encountered.
     pub tag: ErrorTag,
 
-    /// On which line the error is located.
-    pub line: usize,
+    /// Where in the source text the error is located.
+    pub span: Span,
+
+    /// A suggestion for fixing the error, if there's a likely one.
+    pub help: Option<String>,
 }
 
 impl Error {
     /// Constructs a new `Error` value.
-    pub fn new(tag: ErrorTag, line: usize) -> Self {
-        Self { tag, line }
+    pub fn new(tag: ErrorTag, span: Span) -> Self {
+        Self {
+            tag,
+            span,
+            help: None,
+        }
     }
 
-    pub fn name_redefinition<T>(name: &str, line: usize) -> Result<T, Self> {
+    /// Adds a suggestion for fixing the error.
+    pub fn with_help(self, help: Option<String>) -> Self {
+        Self { help, ..self }
+    }
+
+    pub fn name_redefinition<T>(name: &str, span: Span) -> Result<T, Self> {
         let name = name.to_owned();
         let tag = ErrorTag::NameRedefinition(name);
-        let error = Self { tag, line };
-        Err(error)
+        Err(Self::new(tag, span))
+    }
+
+    /// Formats the error followed by the source line it's on, with carets
+    /// under the error's span, and any suggestion, e.g.
+    ///
+    /// ```text
+    /// error at line 4, column 3: name `Sum` is not defined
+    ///   4 |   Sum(1)
+    ///     |   ^^^
+    ///   = help: did you mean `Sum2`?
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let mut text = self.to_string();
+        if let Some(snippet) = self.span.snippet(source) {
+            text.push('\n');
+            text.push_str(&snippet);
+        }
+        if let Some(help) = &self.help {
+            text.push_str(&format!("\n  = help: {help}"));
+        }
+        text
+    }
+
+    /// Formats the error as a JSON object, see `json::diagnostic`.
+    pub fn to_json(&self, file: &str, source: &str) -> String {
+        let message = self.tag.to_string();
+        let help = self.help.as_deref();
+        json::diagnostic(
+            "error",
+            self.tag.code(),
+            &message,
+            help,
+            file,
+            self.span,
+            source,
+        )
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "error at line {}: {}", self.line, self.tag)
+        write!(
+            f,
+            "error at line {}, column {}: {}",
+            self.span.line, self.span.column, self.tag
+        )
     }
 }
 
 impl std::error::Error for Error {}
 
-/// If result is an error, returns the tag. Otherwise returns `None`.
+/// The most errors reported in one run.
+pub const MAX_ERRORS: usize = 20;
+
+/// Collects the errors found in a run, up to `MAX_ERRORS`.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    /// The errors, in the order found.
+    errors: Vec<Error>,
+
+    /// Whether errors were dropped because there were too many.
+    truncated: bool,
+}
+
+impl Diagnostics {
+    /// Constructs an empty collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an error, unless there are already too many.
+    pub fn push(&mut self, error: Error) {
+        if self.is_full() {
+            self.truncated = true;
+        } else {
+            self.errors.push(error);
+        }
+    }
+
+    /// Determines if no more errors will be kept.
+    pub fn is_full(&self) -> bool {
+        self.errors.len() >= MAX_ERRORS
+    }
+
+    /// Determines if there are no errors.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// The errors, in the order found.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// Formats each error with its source line, see `Error::render`.
+    pub fn render(&self, source: &str) -> String {
+        let mut lines: Vec<String> = self.errors.iter().map(|e| e.render(source)).collect();
+        if self.truncated {
+            lines.push(too_many_errors());
+        }
+        lines.join("\n")
+    }
+
+    /// Formats each error as a JSON object on its own line.
+    pub fn to_json(&self, file: &str, source: &str) -> String {
+        let mut lines: Vec<String> = self
+            .errors
+            .iter()
+            .map(|e| e.to_json(file, source))
+            .collect();
+        if self.truncated {
+            lines.push(json::message("note", &too_many_errors()));
+        }
+        lines.join("\n")
+    }
+}
+
+impl From<Error> for Diagnostics {
+    fn from(error: Error) -> Self {
+        let mut diagnostics = Self::new();
+        diagnostics.push(error);
+        diagnostics
+    }
+}
+
+/// Takes the first error, so tests can match on it.
 #[cfg(test)]
-pub fn error_tag<T>(result: Result<T, Error>) -> Option<ErrorTag> {
+impl From<Diagnostics> for Error {
+    fn from(diagnostics: Diagnostics) -> Self {
+        diagnostics
+            .errors
+            .into_iter()
+            .next()
+            .expect("at least one error")
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{error}")?;
+        }
+        if self.truncated {
+            write!(f, "\n{}", too_many_errors())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// The note added when errors are dropped.
+fn too_many_errors() -> String {
+    format!("too many errors, only the first {MAX_ERRORS} are shown")
+}
+
+/// If result is an error, returns the tag of its first error. Otherwise
+/// returns `None`.
+#[cfg(test)]
+pub fn error_tag<T, E: Into<Error>>(result: Result<T, E>) -> Option<ErrorTag> {
     match result {
-        Err(Error { tag, .. }) => Some(tag),
+        Err(e) => Some(e.into().tag),
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let source = "MODULE M;\n\tPROCEDURE é P;\nEND M.\n";
+        let span = Span {
+            offset: 24,
+            len: 1,
+            line: 2,
+            column: 14,
+        };
+        let error = Error::new(ErrorTag::NameRedefinition("P".to_owned()), span);
+        assert_eq!(
+            error.render(source),
+            "error at line 2, column 14: name `P` was previously defined\n  \
+             2 | \tPROCEDURE é P;\n    \
+             | \t            ^"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_cap() {
+        let mut diagnostics = Diagnostics::new();
+        for line in 1..=MAX_ERRORS + 5 {
+            diagnostics.push(Error::new(
+                ErrorTag::UnterminatedString,
+                Span::at_line(line),
+            ));
+        }
+        assert!(diagnostics.is_full());
+        assert_eq!(diagnostics.errors().len(), MAX_ERRORS);
+        assert!(diagnostics.to_string().ends_with(&too_many_errors()));
+    }
+
+    #[test]
+    fn test_render_end_of_file() {
+        let error = Error::new(ErrorTag::UnterminatedComment, Span::at_line(3));
+        assert_eq!(
+            error.render("MODULE M;\n(*\n"),
+            "error at line 3, column 1: unterminated comment"
+        );
+    }
+
+    #[test]
+    fn test_render_help() {
+        let help = Some("did you mean `Sum`?".to_owned());
+        let error = Error::new(ErrorTag::UndefinedName("Sun".to_owned()), Span::at_line(1));
+        assert_eq!(
+            error.with_help(help).render(""),
+            "error at line 1, column 1: name `Sun` is not defined\n  \
+             = help: did you mean `Sum`?"
+        );
+    }
+}
@@ -0,0 +1,663 @@
+//! Running Titania modules directly from their AST.
+//!
+//! The evaluator is a reference for what a module means, independent of the
+//! compiler: running a module here and running its compiled WAT in the
+//! `interpreter` must give the same results, which the tests check. It
+//! expects a module that compiles without errors. `INTEGER` values are 32
+//! bits, as in the compiled code. Procedures in other modules and `EXTERNAL`
+//! procedures aren't available, so calling one traps. Strings and arrays are
+//! only passed to procedures in other modules, so they aren't values here.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::src::*;
+use crate::ast::wat;
+use crate::compiler::{
+    ASSERT_TRAP_CODE, Predeclared, create_default_type_table, create_predeclared_table,
+};
+use crate::interpreter::{self, MAX_CALL_DEPTH, Trap};
+
+/// A Titania value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    Boolean(bool),
+    Char(u8),
+    Integer(i32),
+    Real(f64),
+}
+
+impl Value {
+    /// The zero value of the type with the given identifier, which variables
+    /// start with.
+    fn zero(tid: &str) -> Self {
+        match tid {
+            "BOOLEAN" => Value::Boolean(false),
+            "CHAR" => Value::Char(0),
+            "REAL" => Value::Real(0.0),
+            _ => Value::Integer(0),
+        }
+    }
+
+    /// Whether the value has the type with the given identifier.
+    fn has_type(&self, tid: &str) -> bool {
+        matches!(
+            (self, tid),
+            (Value::Boolean(_), "BOOLEAN")
+                | (Value::Char(_), "CHAR")
+                | (Value::Integer(_), "INTEGER")
+                | (Value::Real(_), "REAL")
+        )
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Boolean(true) => write!(f, "TRUE"),
+            Value::Boolean(false) => write!(f, "FALSE"),
+            Value::Char(value) => write!(f, "{value:03X}X"),
+            Value::Integer(value) => write!(f, "{value}"),
+            Value::Real(value) => write!(f, "{value:?}"),
+        }
+    }
+}
+
+/// The value the compiled code represents a value with.
+impl From<Value> for interpreter::Value {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Boolean(value) => interpreter::Value::I32(value.into()),
+            Value::Char(value) => interpreter::Value::I32(value.into()),
+            Value::Integer(value) => interpreter::Value::I32(value),
+            Value::Real(value) => interpreter::Value::F64(value),
+        }
+    }
+}
+
+/// How executing a statement sequence ended.
+enum Flow {
+    /// The last statement was executed.
+    Next,
+
+    /// A `RETURN` statement was executed, with its result if any.
+    Return(Option<Value>),
+}
+
+/// A module's variables and procedures while it runs.
+pub struct Evaluator<'a> {
+    module: &'a Module,
+    procs: HashMap<&'a str, &'a Proc>,
+    globals: HashMap<String, Value>,
+    depth: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    /// Creates a module's variables and runs its initialisation body.
+    pub fn new(module: &'a Module) -> Result<Self, Trap> {
+        let mut evaluator = Self::with_globals(module, HashMap::new());
+        evaluator.run(&module.body)?;
+        Ok(evaluator)
+    }
+
+    /// Creates a module's variables, with the values given for those that
+    /// have one, without running its initialisation body.
+    pub fn with_globals(module: &'a Module, mut values: HashMap<String, Value>) -> Self {
+        let mut procs = HashMap::new();
+        let mut globals = HashMap::new();
+        for decl in module.decls.iter() {
+            match decl {
+                Decl::Proc(proc) => {
+                    procs.insert(proc.name.as_str(), proc);
+                }
+                Decl::Var(var) if var.len.is_some() => (),
+                Decl::Var(var) => {
+                    let value = values.remove(&var.name);
+                    let value = value.unwrap_or_else(|| Value::zero(&var.tid));
+                    globals.insert(var.name.clone(), value);
+                }
+            }
+        }
+
+        Self {
+            module,
+            procs,
+            globals,
+            depth: 0,
+        }
+    }
+
+    /// The values of the module's variables, by name.
+    pub fn into_globals(self) -> HashMap<String, Value> {
+        self.globals
+    }
+
+    /// Runs a statement sequence as if it were in the module's body.
+    pub fn run(&mut self, stmts: &'a [Stmt]) -> Result<(), Trap> {
+        self.stmts(&mut HashMap::new(), stmts)?;
+        Ok(())
+    }
+
+    /// Evaluates an expression as if it were in the module's body.
+    pub fn eval(&mut self, expr: &'a Expr) -> Result<Value, Trap> {
+        self.expr(&mut HashMap::new(), expr)
+    }
+
+    /// Calls an exported procedure by the name it's exported as, returning
+    /// its result if it's a function procedure.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Option<Value>, Trap> {
+        let proc = self
+            .procs
+            .values()
+            .copied()
+            .find(|proc| proc.export && proc.export_name.as_deref().unwrap_or(&proc.name) == name)
+            .ok_or_else(|| Trap::NotExported(name.to_owned()))?;
+        self.invoke(proc, args)
+    }
+
+    /// The value of an exported variable, if there is one with the name.
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.module.decls.iter().find_map(|decl| match decl {
+            Decl::Var(var) if var.export && var.name == name => self.globals.get(name).copied(),
+            _ => None,
+        })
+    }
+
+    /// Calls a procedure with already evaluated arguments.
+    fn invoke(&mut self, proc: &'a Proc, args: Vec<Value>) -> Result<Option<Value>, Trap> {
+        if let Some(external) = &proc.external {
+            return Err(Trap::UnknownImport {
+                module: external.module.clone(),
+                name: external.name.clone(),
+            });
+        }
+
+        let matches = args.len() == proc.params.len()
+            && args
+                .iter()
+                .zip(proc.params.iter())
+                .all(|(a, p)| a.has_type(&p.tid));
+        if !matches {
+            return Err(Trap::ArgumentMismatch(proc.name.clone()));
+        }
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(Trap::CallStackExhausted);
+        }
+
+        let mut locals: HashMap<&str, Value> = proc
+            .params
+            .iter()
+            .map(|param| param.name.as_str())
+            .zip(args)
+            .collect();
+        for local in proc.locals.iter() {
+            locals.insert(&local.name, Value::zero(&local.tid));
+        }
+
+        self.depth += 1;
+        let flow = self.stmts(&mut locals, &proc.body);
+        self.depth -= 1;
+
+        match (flow?, &proc.tid_return) {
+            (Flow::Return(Some(result)), Some(_)) => Ok(Some(result)),
+            (Flow::Next | Flow::Return(None), None) => Ok(None),
+            _ => Err(Trap::Invalid(format!(
+                "procedure `{}` returned the wrong kind of result",
+                proc.name
+            ))),
+        }
+    }
+
+    /// Executes a statement sequence with the given local variables.
+    fn stmts(
+        &mut self,
+        locals: &mut HashMap<&'a str, Value>,
+        stmts: &'a [Stmt],
+    ) -> Result<Flow, Trap> {
+        for stmt in stmts.iter() {
+            match stmt {
+                Stmt::Assign(assign) => {
+                    let value = self.expr(locals, &assign.expr)?;
+                    *self.var(locals, &assign.var.name)? = value;
+                }
+                Stmt::Call(call) => {
+                    self.call_expr(locals, call)?;
+                }
+                Stmt::Return(stmt_return) => {
+                    let result = match &stmt_return.expr {
+                        Some(expr) => Some(self.expr(locals, expr)?),
+                        None => None,
+                    };
+                    return Ok(Flow::Return(result));
+                }
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Evaluates an expression with the given local variables.
+    fn expr(
+        &mut self,
+        locals: &mut HashMap<&'a str, Value>,
+        expr: &'a Expr,
+    ) -> Result<Value, Trap> {
+        match expr {
+            Expr::Call(call) => self
+                .call_expr(locals, call)?
+                .ok_or_else(|| Trap::Invalid(format!("procedure `{}` has no result", call.proc))),
+            Expr::Char(c) => Ok(Value::Char(c.value)),
+            Expr::Integer(integer) => match i32::try_from(integer.value) {
+                Ok(value) => Ok(Value::Integer(value)),
+                Err(_) => Err(Trap::Invalid(format!(
+                    "integer {} is out of range",
+                    integer.value
+                ))),
+            },
+            Expr::Real(real) => Ok(Value::Real(real.value)),
+            Expr::Str(_) => Err(Trap::Invalid("a string isn't a value".to_owned())),
+            Expr::Var(var)
+                if !locals.contains_key(var.name.name.as_str()) && self.is_array(var) =>
+            {
+                Err(Trap::Invalid(format!("array `{}` isn't a value", var.name)))
+            }
+            Expr::Var(var) => Ok(*self.var(locals, &var.name)?),
+        }
+    }
+
+    /// Evaluates a call's arguments and calls it.
+    fn call_expr(
+        &mut self,
+        locals: &mut HashMap<&'a str, Value>,
+        call: &'a Call,
+    ) -> Result<Option<Value>, Trap> {
+        if call.proc.module.is_none()
+            && !self.procs.contains_key(call.proc.name.as_str())
+            && let Some(&predeclared) = create_predeclared_table().lookup(&call.proc.name)
+        {
+            return self.predeclared(locals, predeclared, call);
+        }
+
+        // Strings and arrays are only passed to other modules' procedures,
+        // so their arguments aren't evaluated.
+        if let Some(alias) = &call.proc.module {
+            let module = self
+                .module
+                .imports
+                .iter()
+                .find(|import| &import.alias == alias)
+                .map_or(alias, |import| &import.name);
+            return Err(Trap::UnknownImport {
+                module: module.clone(),
+                name: call.proc.name.clone(),
+            });
+        }
+        let mut args = Vec::new();
+        for arg in call.args.iter() {
+            args.push(self.expr(locals, arg)?);
+        }
+        let proc = *self
+            .procs
+            .get(call.proc.name.as_str())
+            .ok_or_else(|| Trap::Invalid(format!("no procedure `{}`", call.proc)))?;
+        self.invoke(proc, args)
+    }
+
+    /// Calls a predeclared procedure, whose arguments aren't all values.
+    fn predeclared(
+        &mut self,
+        locals: &mut HashMap<&'a str, Value>,
+        predeclared: Predeclared,
+        call: &'a Call,
+    ) -> Result<Option<Value>, Trap> {
+        let args = &call.args;
+        let invalid = || Trap::Invalid(format!("wrong arguments for `{predeclared:?}`"));
+        let integer = |value| match value {
+            Value::Integer(value) => Ok(value),
+            _ => Err(invalid()),
+        };
+        let trap_code = |arg: Option<&Expr>| match arg {
+            Some(Expr::Integer(integer)) => i32::try_from(integer.value).map_err(|_| invalid()),
+            _ => Err(invalid()),
+        };
+
+        match predeclared {
+            Predeclared::Abs => match self.expr(locals, &args[0])? {
+                Value::Real(x) => Ok(Some(Value::Real(x.abs()))),
+                value => Ok(Some(Value::Integer(integer(value)?.wrapping_abs()))),
+            },
+            Predeclared::Assert => {
+                if !asserts_checked(&self.module.pragmas, call.span) {
+                    return Ok(None);
+                }
+                let Value::Boolean(b) = self.expr(locals, &args[0])? else {
+                    return Err(invalid());
+                };
+                let code = match args.get(1) {
+                    Some(arg) => trap_code(Some(arg))?,
+                    None => ASSERT_TRAP_CODE,
+                };
+                if !b {
+                    return Err(Trap::Unreachable(code));
+                }
+                Ok(None)
+            }
+            Predeclared::Dec | Predeclared::Inc => {
+                let Expr::Var(var) = &args[0] else {
+                    return Err(invalid());
+                };
+                let step = match args.get(1) {
+                    Some(step) => integer(self.expr(locals, step)?)?,
+                    None => 1,
+                };
+                let value = self.var(locals, &var.name)?;
+                let x = integer(*value)?;
+                *value = Value::Integer(match predeclared {
+                    Predeclared::Inc => x.wrapping_add(step),
+                    _ => x.wrapping_sub(step),
+                });
+                Ok(None)
+            }
+            Predeclared::Floor => {
+                let Value::Real(x) = self.expr(locals, &args[0])? else {
+                    return Err(invalid());
+                };
+                Ok(Some(Value::Integer(interpreter::trunc_f64_s(x.floor())?)))
+            }
+            Predeclared::Flt => {
+                let x = integer(self.expr(locals, &args[0])?)?;
+                Ok(Some(Value::Real(x.into())))
+            }
+            Predeclared::Halt => Err(Trap::Unreachable(trap_code(args.first())?)),
+            Predeclared::Max | Predeclared::Min | Predeclared::Size => {
+                let Expr::Var(var) = &args[0] else {
+                    return Err(invalid());
+                };
+                let table_type = create_default_type_table();
+                let t = table_type.lookup(&var.name.name).ok_or_else(invalid)?;
+                let value = match predeclared.eval_type(t.tag()) {
+                    wat::Instr::F64Const(value) => return Ok(Some(Value::Real(value))),
+                    wat::Instr::I32Const(value) => value,
+                    _ => return Err(invalid()),
+                };
+                Ok(Some(match (predeclared, var.name.name.as_str()) {
+                    (Predeclared::Size, _) | (_, "INTEGER") => Value::Integer(value),
+                    (_, "BOOLEAN") => Value::Boolean(value != 0),
+                    _ => Value::Char(value as u8),
+                }))
+            }
+            Predeclared::Odd => {
+                let x = integer(self.expr(locals, &args[0])?)?;
+                Ok(Some(Value::Boolean(x % 2 != 0)))
+            }
+        }
+    }
+
+    /// Whether a variable that isn't local is one of the module's arrays.
+    fn is_array(&self, var: &Var) -> bool {
+        var.name.module.is_none()
+            && self.module.decls.iter().any(|decl| {
+                matches!(decl, Decl::Var(decl_var)
+                    if decl_var.name == var.name.name && decl_var.len.is_some())
+            })
+    }
+
+    /// The local or global variable with the given name.
+    fn var<'b>(
+        &'b mut self,
+        locals: &'b mut HashMap<&'a str, Value>,
+        name: &QualIdent,
+    ) -> Result<&'b mut Value, Trap> {
+        let value = match &name.module {
+            Some(_) => None,
+            None => match locals.get_mut(name.name.as_str()) {
+                Some(value) => Some(value),
+                None => self.globals.get_mut(name.name.as_str()),
+            },
+        };
+        value.ok_or_else(|| Trap::Invalid(format!("no variable `{name}`")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Options;
+    use crate::interpreter::Instance;
+    use crate::parser::Parser;
+    use crate::{compile_str, interpreter};
+
+    /// Runs a module both with the evaluator and compiled with the
+    /// interpreter, checking that each call gives the same result and leaves
+    /// the exported variables the same.
+    fn differential(source: &str, calls: &[(&str, Vec<Value>)]) {
+        let module = Parser::new(source).module().unwrap();
+        let compiled = compile_str(source, &Options::default()).unwrap().module;
+        let mut evaluator = Evaluator::new(&module).unwrap();
+        let mut instance = Instance::new(&compiled).unwrap();
+
+        let globals: Vec<&str> = module
+            .decls
+            .iter()
+            .filter_map(|decl| match decl {
+                Decl::Var(var) if var.export => Some(var.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        let check_globals = |evaluator: &Evaluator, instance: &Instance| {
+            for name in globals.iter() {
+                let expected = evaluator.global(name).map(interpreter::Value::from);
+                assert_eq!(instance.global(name), expected, "variable `{name}`");
+            }
+        };
+
+        check_globals(&evaluator, &instance);
+        for (name, args) in calls.iter() {
+            let expected = evaluator
+                .call(name, args.clone())
+                .map(|result| result.map(interpreter::Value::from));
+            let wasm_args = args.iter().copied().map(interpreter::Value::from).collect();
+            assert_eq!(instance.call(name, wasm_args), expected, "call `{name}`");
+            check_globals(&evaluator, &instance);
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let source = "MODULE M;
+VAR x*: INTEGER; c*: CHAR;
+PROCEDURE Id*(a: INTEGER): INTEGER;
+BEGIN
+  RETURN a
+END;
+PROCEDURE Set*(a: INTEGER);
+  VAR b: INTEGER;
+BEGIN
+  b := Id(a);
+  x := b;
+  RETURN;
+  x := 0
+END;
+BEGIN
+  x := Id(7FFFFFFFH);
+  c := 41X
+END M.";
+        let module = Parser::new(source).module().unwrap();
+        let mut evaluator = Evaluator::new(&module).unwrap();
+        assert_eq!(evaluator.global("x"), Some(Value::Integer(i32::MAX)));
+        assert_eq!(evaluator.global("c"), Some(Value::Char(0x41)));
+        assert_eq!(evaluator.call("Set", vec![Value::Integer(-5)]), Ok(None));
+        assert_eq!(evaluator.global("x"), Some(Value::Integer(-5)));
+        assert_eq!(
+            evaluator.call("Id", vec![Value::Char(1)]),
+            Err(Trap::ArgumentMismatch("Id".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_shadowing() {
+        let source = "MODULE M;
+VAR x*: INTEGER;
+PROCEDURE P*(x: INTEGER): INTEGER;
+BEGIN
+  x := 2;
+  RETURN x
+END;
+END M.";
+        let module = Parser::new(source).module().unwrap();
+        let mut evaluator = Evaluator::new(&module).unwrap();
+        let result = evaluator.call("P", vec![Value::Integer(1)]);
+        assert_eq!(result, Ok(Some(Value::Integer(2))));
+        assert_eq!(evaluator.global("x"), Some(Value::Integer(0)));
+    }
+
+    #[test]
+    fn test_evaluate_import() {
+        let source = "MODULE M;
+IMPORT Out;
+VAR s: ARRAY 4 OF CHAR;
+BEGIN
+  Out.String(s)
+END M.";
+        let module = Parser::new(source).module().unwrap();
+        assert_eq!(
+            Evaluator::new(&module).err(),
+            Some(Trap::UnknownImport {
+                module: "Out".to_owned(),
+                name: "String".to_owned()
+            })
+        );
+        let mut evaluator = Evaluator::with_globals(&module, HashMap::new());
+        let expr = Parser::new("s").expr().unwrap();
+        assert!(matches!(evaluator.eval(&expr), Err(Trap::Invalid(_))));
+    }
+
+    #[test]
+    fn test_differential() {
+        let source = "MODULE M;
+VAR x*, y*: INTEGER; c*: CHAR;
+PROCEDURE [EXTERNAL \"env\", \"log\"] Log(a: INTEGER);
+PROCEDURE Second(a, b: INTEGER): INTEGER;
+BEGIN
+  RETURN b
+END;
+PROCEDURE Swap*;
+  VAR t: INTEGER;
+BEGIN
+  t := x;
+  x := y;
+  y := t
+END;
+PROCEDURE [EXPORT \"set\"] Set*(a: INTEGER; b: CHAR): INTEGER;
+BEGIN
+  x := Second(y, a);
+  c := b;
+  RETURN x
+END;
+PROCEDURE Log2*;
+BEGIN
+  Log(x)
+END;
+BEGIN
+  y := 7FFFFFFFH;
+  c := 0FFX
+END M.";
+        use Value::*;
+        differential(
+            source,
+            &[
+                ("Swap", vec![]),
+                ("set", vec![Integer(3), Char(b'z')]),
+                ("Swap", vec![]),
+                ("set", vec![Integer(i32::MAX), Char(0)]),
+                ("Set", vec![]),
+                ("set", vec![Integer(1)]),
+                ("Log2", vec![]),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_differential_predeclared() {
+        let source = "MODULE M;
+VAR x*, y*: INTEGER; b*: BOOLEAN; c*: CHAR;
+PROCEDURE Step*(a: INTEGER);
+BEGIN
+  INC(x); INC(x, a); DEC(x, 3); DEC(x); b := ODD(x)
+END;
+PROCEDURE Abs*(a: INTEGER): INTEGER;
+BEGIN
+  RETURN ABS(a)
+END;
+PROCEDURE Limits*;
+BEGIN
+  y := MIN(INTEGER); c := MAX(CHAR); b := MAX(BOOLEAN); x := SIZE(CHAR)
+END;
+PROCEDURE Check*(a: INTEGER);
+BEGIN
+  ASSERT(ODD(a)); ASSERT(ODD(ABS(a)), 9); b := ODD(a)
+END;
+PROCEDURE Unchecked*(a: INTEGER);
+BEGIN
+  (*$ASSERT-*) ASSERT(ODD(a)); (*$ASSERT+*) b := ODD(a)
+END;
+PROCEDURE Halt*;
+BEGIN
+  HALT(42)
+END;
+END M.";
+        use Value::*;
+        differential(
+            source,
+            &[
+                ("Step", vec![Integer(5)]),
+                ("Step", vec![Integer(i32::MAX)]),
+                ("Abs", vec![Integer(-7)]),
+                ("Abs", vec![Integer(i32::MIN)]),
+                ("Limits", vec![]),
+                ("Check", vec![Integer(-3)]),
+                ("Check", vec![Integer(4)]),
+                ("Unchecked", vec![Integer(4)]),
+                ("Halt", vec![]),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_differential_real() {
+        let source = "MODULE M;
+VAR r*: REAL; x*: INTEGER;
+PROCEDURE Floor*(a: REAL): INTEGER;
+BEGIN
+  RETURN FLOOR(a)
+END;
+PROCEDURE Flt*(a: INTEGER): REAL;
+BEGIN
+  RETURN FLT(a)
+END;
+PROCEDURE Abs*(a: REAL): REAL;
+BEGIN
+  RETURN ABS(a)
+END;
+PROCEDURE Limits*;
+BEGIN
+  r := MIN(REAL); x := SIZE(REAL); r := MAX(REAL)
+END;
+BEGIN
+  r := 1.5E-3
+END M.";
+        use Value::*;
+        differential(
+            source,
+            &[
+                ("Floor", vec![Real(-2.5)]),
+                ("Floor", vec![Real(2147483647.9)]),
+                ("Floor", vec![Real(-2147483648.5)]),
+                ("Floor", vec![Real(f64::INFINITY)]),
+                ("Flt", vec![Integer(i32::MIN)]),
+                ("Abs", vec![Real(-2.5)]),
+                ("Abs", vec![Real(f64::NEG_INFINITY)]),
+                ("Limits", vec![]),
+            ],
+        );
+    }
+}
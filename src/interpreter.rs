@@ -0,0 +1,834 @@
+//! Running WAT modules without a WebAssembly runtime.
+//!
+//! The interpreter executes the instructions the compiler produces directly
+//! from the WAT AST, rather than from the binary format. A module is
+//! instantiated by creating its globals and memory and running its start
+//! function, after which its exported functions can be called. Of the
+//! imported functions, only WASI's `fd_read` and `fd_write` are available,
+//! reading the instance's input and writing its output and errors, so calling
+//! any other traps.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::ast::wat::*;
+use crate::library::WASI_MODULE;
+
+/// The deepest calls can nest before the call stack is exhausted.
+pub(crate) const MAX_CALL_DEPTH: usize = 1000;
+
+/// The stack size of the thread `with_call_stack` runs on, which fits
+/// `MAX_CALL_DEPTH` nested calls in the interpreter or the evaluator, even in
+/// a debug build.
+const CALL_STACK_SIZE: usize = 64 << 20;
+
+/// Runs a function on a thread with a stack big enough for running a
+/// program, whose calls are nested Rust calls, so that deep recursion traps
+/// with `CallStackExhausted` rather than overflowing the caller's stack.
+pub fn with_call_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(CALL_STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("the thread can be created")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// A WebAssembly value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    F64(f64),
+    I32(i32),
+}
+
+impl Value {
+    /// The zero value of a type, which locals start with.
+    fn zero(t: &Type) -> Self {
+        match t {
+            Type::F64 => Value::F64(0.0),
+            Type::I32 => Value::I32(0),
+        }
+    }
+
+    /// Whether the value has the given type.
+    fn has_type(&self, t: &Type) -> bool {
+        matches!(
+            (self, t),
+            (Value::F64(_), Type::F64) | (Value::I32(_), Type::I32)
+        )
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::F64(value) => write!(f, "{value:?}"),
+            Value::I32(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// Enumerates the ways running a module can fail.
+#[derive(Debug, PartialEq)]
+pub enum Trap {
+    /// A function was called with the wrong number or types of arguments.
+    /// Holds the function's identifier.
+    ArgumentMismatch(String),
+
+    /// Calls nested more deeply than `MAX_CALL_DEPTH`.
+    CallStackExhausted,
+
+    /// An integer was divided by zero.
+    DivideByZero,
+
+    /// A float converted to an integer is too big for it.
+    IntegerOverflow,
+
+    /// A NaN was converted to an integer.
+    InvalidConversion,
+
+    /// An address, or a data segment, is outside memory.
+    MemoryOutOfBounds,
+
+    /// An export isn't a function the module defines.
+    NotExported(String),
+
+    /// An imported function was called, and isn't available.
+    UnknownImport { module: String, name: String },
+
+    /// An `unreachable` instruction was executed, e.g. for a failed
+    /// `ASSERT`. Holds its trap code.
+    Unreachable(i32),
+
+    /// An instruction refers to a function, global or local that doesn't
+    /// exist, or pops a value that isn't there or has the wrong type, so the
+    /// module is invalid.
+    Invalid(String),
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Trap::*;
+
+        match self {
+            ArgumentMismatch(id) => write!(f, "wrong arguments for function `{id}`"),
+            CallStackExhausted => write!(f, "call stack exhausted"),
+            DivideByZero => write!(f, "integer divide by zero"),
+            IntegerOverflow => write!(f, "integer overflow"),
+            InvalidConversion => write!(f, "invalid conversion to integer"),
+            MemoryOutOfBounds => write!(f, "out of bounds memory access"),
+            NotExported(name) => write!(f, "no exported function `{name}`"),
+            UnknownImport { module, name } => {
+                write!(f, "imported function `{module}.{name}` isn't available")
+            }
+            Unreachable(code) => write!(f, "unreachable executed with trap code {code}"),
+            Invalid(message) => write!(f, "invalid module: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Trap {}
+
+/// How executing a sequence of instructions ended.
+enum Flow {
+    /// The last instruction was executed.
+    Next,
+
+    /// A branch was taken to the given enclosing block, where 0 is the
+    /// innermost block.
+    Branch(u32),
+
+    /// A `return` instruction was executed.
+    Return,
+}
+
+/// A function's locals and operand stack while it runs.
+struct Frame {
+    locals: HashMap<String, Value>,
+    stack: Vec<Value>,
+}
+
+impl Frame {
+    fn pop(&mut self) -> Result<Value, Trap> {
+        self.stack
+            .pop()
+            .ok_or_else(|| Trap::Invalid("operand stack is empty".to_owned()))
+    }
+
+    fn pop_i32(&mut self) -> Result<i32, Trap> {
+        match self.pop()? {
+            Value::I32(value) => Ok(value),
+            value => Err(Trap::Invalid(format!("`{value}` isn't an i32"))),
+        }
+    }
+
+    fn pop_f64(&mut self) -> Result<f64, Trap> {
+        match self.pop()? {
+            Value::F64(value) => Ok(value),
+            value => Err(Trap::Invalid(format!("`{value}` isn't an f64"))),
+        }
+    }
+
+    /// Pops two `i32` values and pushes the result of an operation on them,
+    /// or `None` if the second is zero and the operation divides by it.
+    fn binary(&mut self, op: impl Fn(i32, i32) -> Option<i32>) -> Result<(), Trap> {
+        let b = self.pop_i32()?;
+        let a = self.pop_i32()?;
+        let result = op(a, b).ok_or(Trap::DivideByZero)?;
+        self.stack.push(Value::I32(result));
+        Ok(())
+    }
+
+    /// Pops an `f64` value and pushes the result of an operation on it.
+    fn unary_f64(&mut self, op: impl Fn(f64) -> Value) -> Result<(), Trap> {
+        let a = self.pop_f64()?;
+        self.stack.push(op(a));
+        Ok(())
+    }
+
+    /// Pops two `f64` values and pushes the result of an operation on them.
+    fn binary_f64(&mut self, op: impl Fn(f64, f64) -> Value) -> Result<(), Trap> {
+        let b = self.pop_f64()?;
+        let a = self.pop_f64()?;
+        self.stack.push(op(a, b));
+        Ok(())
+    }
+
+    fn local(&mut self, id: &str) -> Result<&mut Value, Trap> {
+        self.locals
+            .get_mut(id)
+            .ok_or_else(|| Trap::Invalid(format!("no local `{id}`")))
+    }
+}
+
+/// An instantiated module.
+pub struct Instance<'a> {
+    module: &'a Module,
+    globals: HashMap<String, Value>,
+    memory: Vec<u8>,
+    depth: usize,
+
+    /// Where `fd_read` reads standard input from.
+    input: Box<dyn Read + 'a>,
+
+    /// Where `fd_write` writes standard output to.
+    output: Box<dyn Write + 'a>,
+
+    /// Where `fd_write` writes standard error to.
+    errors: Box<dyn Write + 'a>,
+}
+
+impl<'a> Instance<'a> {
+    /// Instantiates a module, running its start function if it has one, with
+    /// standard input, output and error.
+    pub fn new(module: &'a Module) -> Result<Self, Trap> {
+        Self::with_io(module, io::stdin(), io::stdout(), io::stderr())
+    }
+
+    /// Instantiates a module, running its start function if it has one, with
+    /// its input read from `input`, its output going to `output` and its
+    /// errors going to `errors`.
+    pub fn with_io(
+        module: &'a Module,
+        input: impl Read + 'a,
+        output: impl Write + 'a,
+        errors: impl Write + 'a,
+    ) -> Result<Self, Trap> {
+        let mut globals = HashMap::new();
+        for global in module.globals.iter() {
+            let value = match &global.init {
+                Instr::F64Const(value) => Value::F64(*value),
+                Instr::I32Const(value) => Value::I32(*value),
+                Instr::GlobalGet(id) => *globals
+                    .get(id)
+                    .ok_or_else(|| Trap::Invalid(format!("no global `{id}`")))?,
+                init => return Err(Trap::Invalid(format!("`{init:?}` isn't constant"))),
+            };
+            globals.insert(global.name.clone(), value);
+        }
+
+        let pages: usize = module.memories.iter().map(|m| m.min as usize).sum();
+        let mut memory = vec![0; pages * PAGE_SIZE as usize];
+        for data in module.data.iter() {
+            let start = data.offset as usize;
+            memory
+                .get_mut(start..start + data.bytes.len())
+                .ok_or(Trap::MemoryOutOfBounds)?
+                .copy_from_slice(&data.bytes);
+        }
+
+        let mut instance = Self {
+            module,
+            globals,
+            memory,
+            depth: 0,
+            input: Box::new(input),
+            output: Box::new(output),
+            errors: Box::new(errors),
+        };
+        if let Some(start) = &module.start {
+            instance.invoke(start, Vec::new())?;
+        }
+        Ok(instance)
+    }
+
+    /// Calls an exported function, returning its result if it has one.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Option<Value>, Trap> {
+        let id = self
+            .module
+            .exports
+            .iter()
+            .find_map(|export| match &export.desc {
+                ExportDesc::Func(id) if export.name == name => Some(id),
+                _ => None,
+            })
+            .ok_or_else(|| Trap::NotExported(name.to_owned()))?;
+        self.invoke(id, args)
+    }
+
+    /// The value of an exported global, if there is one with the name.
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.module
+            .exports
+            .iter()
+            .find_map(|export| match &export.desc {
+                ExportDesc::Global(id) if export.name == name => self.globals.get(id).copied(),
+                _ => None,
+            })
+    }
+
+    /// The module's memory.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Calls the function or import with the given identifier.
+    fn invoke(&mut self, id: &str, args: Vec<Value>) -> Result<Option<Value>, Trap> {
+        let module = self.module;
+        if let Some(import) = module.imports.iter().find(|import| import.id == id) {
+            return match (import.module.as_str(), import.name.as_str()) {
+                (WASI_MODULE, "fd_read") => self.fd_read(&args).map(Some),
+                (WASI_MODULE, "fd_write") => self.fd_write(&args).map(Some),
+                _ => Err(Trap::UnknownImport {
+                    module: import.module.clone(),
+                    name: import.name.clone(),
+                }),
+            };
+        }
+        let func = module
+            .funcs
+            .iter()
+            .find(|func| func.name == id)
+            .ok_or_else(|| Trap::Invalid(format!("no function `{id}`")))?;
+
+        let matches = args.len() == func.params.len()
+            && args
+                .iter()
+                .zip(func.params.iter())
+                .all(|(a, p)| a.has_type(&p.t));
+        if !matches {
+            return Err(Trap::ArgumentMismatch(id.to_owned()));
+        }
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(Trap::CallStackExhausted);
+        }
+
+        let mut locals: HashMap<String, Value> = func
+            .params
+            .iter()
+            .map(|param| param.name.clone())
+            .zip(args)
+            .collect();
+        for local in func.locals.iter() {
+            locals.insert(local.name.clone(), Value::zero(&local.t));
+        }
+        let mut frame = Frame {
+            locals,
+            stack: Vec::new(),
+        };
+
+        self.depth += 1;
+        let flow = self.block(&mut frame, &func.body);
+        self.depth -= 1;
+        flow?;
+
+        match &func.result {
+            Some(_) => Ok(Some(frame.pop()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Executes a sequence of instructions in a function's frame.
+    fn block(&mut self, frame: &mut Frame, instrs: &[Instr]) -> Result<Flow, Trap> {
+        for instr in instrs.iter() {
+            match instr {
+                Instr::BrIf(label) => {
+                    if frame.pop_i32()? != 0 {
+                        return Ok(Flow::Branch(*label));
+                    }
+                }
+                Instr::Call(id) => {
+                    let n = self.arity(id)?;
+                    if frame.stack.len() < n {
+                        return Err(Trap::Invalid("operand stack is empty".to_owned()));
+                    }
+                    let args = frame.stack.split_off(frame.stack.len() - n);
+                    if let Some(result) = self.invoke(id, args)? {
+                        frame.stack.push(result);
+                    }
+                }
+                Instr::Drop => {
+                    frame.pop()?;
+                }
+                Instr::F64Abs => frame.unary_f64(|a| Value::F64(a.abs()))?,
+                Instr::F64Add => frame.binary_f64(|a, b| Value::F64(a + b))?,
+                Instr::F64Const(value) => frame.stack.push(Value::F64(*value)),
+                Instr::F64ConvertI32S => {
+                    let value = frame.pop_i32()?;
+                    frame.stack.push(Value::F64(value.into()));
+                }
+                Instr::F64Div => frame.binary_f64(|a, b| Value::F64(a / b))?,
+                Instr::F64Eq => frame.binary_f64(|a, b| Value::I32((a == b).into()))?,
+                Instr::F64Floor => frame.unary_f64(|a| Value::F64(a.floor()))?,
+                Instr::F64Gt => frame.binary_f64(|a, b| Value::I32((a > b).into()))?,
+                Instr::F64Lt => frame.binary_f64(|a, b| Value::I32((a < b).into()))?,
+                Instr::F64Mul => frame.binary_f64(|a, b| Value::F64(a * b))?,
+                Instr::F64Ne => frame.binary_f64(|a, b| Value::I32((a != b).into()))?,
+                Instr::F64Nearest => frame.unary_f64(|a| Value::F64(a.round_ties_even()))?,
+                Instr::F64Neg => frame.unary_f64(|a| Value::F64(-a))?,
+                Instr::F64Sqrt => frame.unary_f64(|a| Value::F64(a.sqrt()))?,
+                Instr::F64Sub => frame.binary_f64(|a, b| Value::F64(a - b))?,
+                Instr::GlobalGet(id) => {
+                    let value = *self.global_mut(id)?;
+                    frame.stack.push(value);
+                }
+                Instr::GlobalSet(id) => {
+                    let value = frame.pop()?;
+                    *self.global_mut(id)? = value;
+                }
+                Instr::I32Add => frame.binary(|a, b| Some(a.wrapping_add(b)))?,
+                Instr::I32And => frame.binary(|a, b| Some(a & b))?,
+                Instr::I32Const(value) => frame.stack.push(Value::I32(*value)),
+                Instr::I32DivU => {
+                    frame.binary(|a, b| (a as u32).checked_div(b as u32).map(|q| q as i32))?
+                }
+                Instr::I32Eq => frame.binary(|a, b| Some((a == b).into()))?,
+                Instr::I32Eqz => {
+                    let value = frame.pop_i32()?;
+                    frame.stack.push(Value::I32((value == 0).into()));
+                }
+                Instr::I32Load(offset) => {
+                    let address = frame.pop_i32()?;
+                    let bytes = self.load(address, *offset, 4)?;
+                    let value = i32::from_le_bytes(bytes.try_into().expect("4 bytes"));
+                    frame.stack.push(Value::I32(value));
+                }
+                Instr::I32Load8U(offset) => {
+                    let address = frame.pop_i32()?;
+                    let value = self.load(address, *offset, 1)?[0];
+                    frame.stack.push(Value::I32(value.into()));
+                }
+                Instr::I32LtS => frame.binary(|a, b| Some((a < b).into()))?,
+                Instr::I32LtU => frame.binary(|a, b| Some(((a as u32) < (b as u32)).into()))?,
+                Instr::I32Mul => frame.binary(|a, b| Some(a.wrapping_mul(b)))?,
+                Instr::I32RemU => {
+                    frame.binary(|a, b| (a as u32).checked_rem(b as u32).map(|r| r as i32))?
+                }
+                Instr::I32Store(offset) => {
+                    let value = frame.pop_i32()?;
+                    let address = frame.pop_i32()?;
+                    self.store(address, *offset, &value.to_le_bytes())?;
+                }
+                Instr::I32Store8(offset) => {
+                    let value = frame.pop_i32()?;
+                    let address = frame.pop_i32()?;
+                    self.store(address, *offset, &[value as u8])?;
+                }
+                Instr::I32Sub => frame.binary(|a, b| Some(a.wrapping_sub(b)))?,
+                Instr::I32TruncF64S => {
+                    let value = trunc_f64_s(frame.pop_f64()?)?;
+                    frame.stack.push(Value::I32(value));
+                }
+                Instr::If(body) => {
+                    if frame.pop_i32()? != 0 {
+                        match self.nested(frame, body)? {
+                            Flow::Next | Flow::Branch(0) => (),
+                            Flow::Branch(label) => return Ok(Flow::Branch(label - 1)),
+                            Flow::Return => return Ok(Flow::Return),
+                        }
+                    }
+                }
+                Instr::LocalGet(id) => {
+                    let value = *frame.local(id)?;
+                    frame.stack.push(value);
+                }
+                Instr::LocalSet(id) => {
+                    let value = frame.pop()?;
+                    *frame.local(id)? = value;
+                }
+                Instr::Loop(body) => loop {
+                    match self.nested(frame, body)? {
+                        Flow::Branch(0) => continue,
+                        Flow::Next => break,
+                        Flow::Branch(label) => return Ok(Flow::Branch(label - 1)),
+                        Flow::Return => return Ok(Flow::Return),
+                    }
+                },
+                Instr::Return => return Ok(Flow::Return),
+                Instr::Unreachable(code) => return Err(Trap::Unreachable(*code)),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Executes a block's instructions. A branch out of the block discards
+    /// the values pushed in it.
+    fn nested(&mut self, frame: &mut Frame, body: &[Instr]) -> Result<Flow, Trap> {
+        let height = frame.stack.len();
+        let flow = self.block(frame, body)?;
+        if let Flow::Branch(_) = flow {
+            frame.stack.truncate(height);
+        }
+        Ok(flow)
+    }
+
+    /// Stores bytes at an address plus an offset.
+    fn store(&mut self, address: i32, offset: u32, bytes: &[u8]) -> Result<(), Trap> {
+        let start = address as u32 as usize + offset as usize;
+        self.memory
+            .get_mut(start..start + bytes.len())
+            .ok_or(Trap::MemoryOutOfBounds)?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Loads bytes from an address plus an offset.
+    fn load(&self, address: i32, offset: u32, len: usize) -> Result<&[u8], Trap> {
+        let start = address as u32 as usize + offset as usize;
+        self.memory
+            .get(start..start + len)
+            .ok_or(Trap::MemoryOutOfBounds)
+    }
+
+    /// Reads WASI's I/O vectors, each the address and length of a buffer.
+    fn iovecs(&self, iovs: i32, iovs_len: i32) -> Result<Vec<(i32, usize)>, Trap> {
+        let field = |address: i32, offset: u32| -> Result<u32, Trap> {
+            let bytes = self.load(address, offset, 4)?;
+            Ok(u32::from_le_bytes(bytes.try_into().expect("4 bytes")))
+        };
+        (0..iovs_len as u32)
+            .map(|i| {
+                let iov = (iovs as u32).wrapping_add(8 * i) as i32;
+                Ok((field(iov, 0)? as i32, field(iov, 4)? as usize))
+            })
+            .collect()
+    }
+
+    /// WASI's `fd_read(fd, iovs, iovs_len, nread) -> errno`, which reads
+    /// from a file into the buffers described by `iovs`. Only standard input
+    /// can be read from, and a read stops at the first buffer that isn't
+    /// filled, so that input is read a line at a time from a terminal.
+    fn fd_read(&mut self, args: &[Value]) -> Result<Value, Trap> {
+        let &[
+            Value::I32(fd),
+            Value::I32(iovs),
+            Value::I32(iovs_len),
+            Value::I32(nread),
+        ] = args
+        else {
+            return Err(Trap::ArgumentMismatch("fd_read".to_owned()));
+        };
+        if fd != 0 {
+            // EBADF
+            return Ok(Value::I32(8));
+        }
+
+        let mut total = 0;
+        for (address, len) in self.iovecs(iovs, iovs_len)? {
+            let mut buffer = vec![0; len];
+            let Ok(n) = self.input.read(&mut buffer) else {
+                // EIO
+                return Ok(Value::I32(29));
+            };
+            self.store(address, 0, &buffer[..n])?;
+            total += n;
+            if n < len {
+                break;
+            }
+        }
+        self.store(nread, 0, &(total as u32).to_le_bytes())?;
+        Ok(Value::I32(0))
+    }
+
+    /// WASI's `fd_write(fd, iovs, iovs_len, nwritten) -> errno`, which
+    /// writes the buffers described by `iovs` to a file. Only standard
+    /// output and standard error can be written to.
+    fn fd_write(&mut self, args: &[Value]) -> Result<Value, Trap> {
+        let &[
+            Value::I32(fd),
+            Value::I32(iovs),
+            Value::I32(iovs_len),
+            Value::I32(nwritten),
+        ] = args
+        else {
+            return Err(Trap::ArgumentMismatch("fd_write".to_owned()));
+        };
+
+        let mut bytes = Vec::new();
+        for (address, len) in self.iovecs(iovs, iovs_len)? {
+            bytes.extend_from_slice(self.load(address, 0, len)?);
+        }
+
+        let written = match fd {
+            1 => self.output.write_all(&bytes),
+            2 => self.errors.write_all(&bytes),
+            // EBADF
+            _ => return Ok(Value::I32(8)),
+        };
+        if written.is_err() {
+            // EIO
+            return Ok(Value::I32(29));
+        }
+        self.store(nwritten, 0, &(bytes.len() as u32).to_le_bytes())?;
+        Ok(Value::I32(0))
+    }
+
+    /// The number of parameters of the function or import with the given
+    /// identifier.
+    fn arity(&self, id: &str) -> Result<usize, Trap> {
+        let module = self.module;
+        let import = module.imports.iter().find(|import| import.id == id);
+        let func = module.funcs.iter().find(|func| func.name == id);
+        match (import, func) {
+            (Some(import), _) => Ok(import.params.len()),
+            (_, Some(func)) => Ok(func.params.len()),
+            _ => Err(Trap::Invalid(format!("no function `{id}`"))),
+        }
+    }
+
+    fn global_mut(&mut self, id: &str) -> Result<&mut Value, Trap> {
+        self.globals
+            .get_mut(id)
+            .ok_or_else(|| Trap::Invalid(format!("no global `{id}`")))
+    }
+}
+
+/// An `f64` truncated towards zero as an `i32`, as `i32.trunc_f64_s` does,
+/// trapping if it's NaN or too big.
+pub(crate) fn trunc_f64_s(value: f64) -> Result<i32, Trap> {
+    if value.is_nan() {
+        return Err(Trap::InvalidConversion);
+    }
+    if value <= f64::from(i32::MIN) - 1.0 || value >= -f64::from(i32::MIN) {
+        return Err(Trap::IntegerOverflow);
+    }
+    Ok(value as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_str;
+    use crate::compiler::Options;
+
+    fn compile(source: &str) -> Module {
+        compile_str(source, &Options::default()).unwrap().module
+    }
+
+    #[test]
+    fn test_call() {
+        let module = compile(
+            "MODULE M;
+VAR x*: INTEGER;
+PROCEDURE Get*(): INTEGER;
+BEGIN
+  RETURN x
+END;
+PROCEDURE Second*(a, b: INTEGER): INTEGER;
+BEGIN
+  RETURN b
+END;
+BEGIN
+  x := Second(1, 42)
+END M.",
+        );
+        let mut instance = Instance::new(&module).unwrap();
+        assert_eq!(instance.call("Get", vec![]), Ok(Some(Value::I32(42))));
+        assert_eq!(instance.global("x"), Some(Value::I32(42)));
+        let args = vec![Value::I32(1), Value::I32(-2)];
+        assert_eq!(instance.call("Second", args), Ok(Some(Value::I32(-2))));
+    }
+
+    #[test]
+    fn test_call_locals() {
+        let module = compile(
+            "MODULE M;
+PROCEDURE P*(a: INTEGER): INTEGER;
+  VAR b: INTEGER;
+BEGIN
+  b := a;
+  RETURN b
+END;
+END M.",
+        );
+        let mut instance = Instance::new(&module).unwrap();
+        assert_eq!(
+            instance.call("P", vec![Value::I32(7)]),
+            Ok(Some(Value::I32(7)))
+        );
+    }
+
+    #[test]
+    fn test_call_errors() {
+        let module = compile(
+            "MODULE M;
+PROCEDURE [EXTERNAL \"env\", \"log\"] Log(x: INTEGER);
+PROCEDURE P*;
+BEGIN
+  Log(1)
+END;
+END M.",
+        );
+        let mut instance = Instance::new(&module).unwrap();
+        assert_eq!(
+            instance.call("R", vec![]),
+            Err(Trap::NotExported("R".to_owned()))
+        );
+        assert_eq!(
+            instance.call("P", vec![Value::I32(1)]),
+            Err(Trap::ArgumentMismatch("P".to_owned()))
+        );
+        assert_eq!(
+            instance.call("P", vec![]),
+            Err(Trap::UnknownImport {
+                module: "env".to_owned(),
+                name: "log".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn test_call_real() {
+        let module = compile(
+            "MODULE M;
+VAR r*: REAL;
+PROCEDURE Floor*(a: REAL): INTEGER;
+BEGIN
+  r := a;
+  RETURN FLOOR(a)
+END;
+END M.",
+        );
+        let mut instance = Instance::new(&module).unwrap();
+        assert_eq!(instance.global("r"), Some(Value::F64(0.0)));
+        let mut floor = |a: f64| instance.call("Floor", vec![Value::F64(a)]);
+        assert_eq!(floor(-0.5), Ok(Some(Value::I32(-1))));
+        assert_eq!(floor(-2147483648.5), Err(Trap::IntegerOverflow));
+        assert_eq!(floor(f64::NAN), Err(Trap::InvalidConversion));
+        assert_eq!(
+            instance.call("Floor", vec![Value::I32(1)]),
+            Err(Trap::ArgumentMismatch("Floor".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_trunc_f64_s() {
+        assert_eq!(trunc_f64_s(-2147483648.9), Ok(i32::MIN));
+        assert_eq!(trunc_f64_s(2147483647.9), Ok(i32::MAX));
+        assert_eq!(trunc_f64_s(-1.5), Ok(-1));
+        assert_eq!(trunc_f64_s(2147483648.0), Err(Trap::IntegerOverflow));
+        assert_eq!(trunc_f64_s(f64::NEG_INFINITY), Err(Trap::IntegerOverflow));
+    }
+
+    #[test]
+    fn test_call_stack_exhausted() {
+        let module = compile(
+            "MODULE M;
+PROCEDURE P*;
+BEGIN
+  P
+END;
+END M.",
+        );
+        let result = with_call_stack(|| Instance::new(&module).unwrap().call("P", vec![]));
+        assert_eq!(result, Err(Trap::CallStackExhausted));
+    }
+
+    #[test]
+    fn test_fd_write() {
+        use Instr::*;
+
+        // Writes "out" from 16 to standard output and "err" from 19 to
+        // standard error, with the I/O vector at 0.
+        let write = |fd, address| {
+            vec![
+                I32Const(0),
+                I32Const(address),
+                I32Store(0),
+                I32Const(0),
+                I32Const(3),
+                I32Store(4),
+                I32Const(fd),
+                I32Const(0),
+                I32Const(1),
+                I32Const(8),
+                Call("fd_write".to_owned()),
+                Drop,
+            ]
+        };
+        let module = Module {
+            name: "M".to_owned(),
+            imports: vec![Import {
+                module: WASI_MODULE.to_owned(),
+                name: "fd_write".to_owned(),
+                id: "fd_write".to_owned(),
+                params: vec![Type::I32; 4],
+                result: Some(Type::I32),
+            }],
+            tables: Vec::new(),
+            memories: vec![Memory {
+                name: "memory".to_owned(),
+                min: 1,
+                max: None,
+            }],
+            globals: Vec::new(),
+            funcs: vec![Func {
+                name: "P".to_owned(),
+                params: Vec::new(),
+                result: None,
+                locals: Vec::new(),
+                body: write(1, 16).into_iter().chain(write(2, 19)).collect(),
+            }],
+            exports: Vec::new(),
+            start: Some("P".to_owned()),
+            data: vec![Data {
+                offset: 16,
+                bytes: b"outerr".to_vec(),
+            }],
+        };
+
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        Instance::with_io(&module, io::empty(), &mut output, &mut errors).unwrap();
+        assert_eq!(output, b"out");
+        assert_eq!(errors, b"err");
+    }
+
+    #[test]
+    fn test_memory() {
+        let module = compile_str(
+            "MODULE M; END M.",
+            &Options {
+                export_memory: true,
+                ..Options::default()
+            },
+        )
+        .unwrap()
+        .module;
+        let instance = Instance::new(&module).unwrap();
+        assert_eq!(instance.memory().len(), PAGE_SIZE as usize);
+    }
+}
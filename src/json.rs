@@ -0,0 +1,125 @@
+//! Formatting diagnostics as JSON, for tools reading the compiler's output.
+//!
+//! Each diagnostic is a JSON object on its own line, e.g.
+//!
+//! ```text
+//! {"severity":"error","code":"E011","message":"name `P` was previously defined","help":null,"file":"M.ta","line":2,"column":11,"end_line":2,"end_column":12}
+//! ```
+//!
+//! Columns count characters from 1, and the end is just past the span. `help`
+//! is a suggestion for fixing the error, or `null`.
+use crate::span::Span;
+
+/// Formats a diagnostic located in a source file.
+pub fn diagnostic(
+    severity: &str,
+    code: &str,
+    message: &str,
+    help: Option<&str>,
+    file: &str,
+    span: Span,
+    source: &str,
+) -> String {
+    let (end_line, end_column) = span.end(source);
+    format!(
+        "{{\"severity\":{},\"code\":{},\"message\":{},\"help\":{},\"file\":{},\
+         \"line\":{},\"column\":{},\"end_line\":{end_line},\"end_column\":{end_column}}}",
+        string(severity),
+        string(code),
+        string(message),
+        help.map_or("null".to_owned(), string),
+        string(file),
+        span.line,
+        span.column,
+    )
+}
+
+/// Formats a diagnostic that isn't located in a source file, e.g. a file
+/// that couldn't be read.
+pub fn message(severity: &str, message: &str) -> String {
+    format!(
+        "{{\"severity\":{},\"code\":null,\"message\":{}}}",
+        string(severity),
+        string(message)
+    )
+}
+
+/// Formats a diagnostic about a whole file, e.g. one that couldn't be read.
+pub fn file_message(severity: &str, message: &str, file: &str) -> String {
+    format!(
+        "{{\"severity\":{},\"code\":null,\"message\":{},\"file\":{}}}",
+        string(severity),
+        string(message),
+        string(file)
+    )
+}
+
+/// Formats a JSON string.
+fn string(s: &str) -> String {
+    let mut text = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            c if c.is_control() => text.push_str(&format!("\\u{:04x}", c as u32)),
+            c => text.push(c),
+        }
+    }
+    text.push('"');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string() {
+        assert_eq!(string("a\"b\\c\nd\u{1}é"), "\"a\\\"b\\\\c\\nd\\u0001é\"");
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let source = "MODULE M;\nPROCEDURE Pé;\nEND M.";
+        let span = Span {
+            offset: 20,
+            len: 3,
+            line: 2,
+            column: 11,
+        };
+        assert_eq!(
+            diagnostic(
+                "error",
+                "E011",
+                "name `Pé` was \"defined\"",
+                Some("did you mean `Q`?"),
+                "M.ta",
+                span,
+                source
+            ),
+            "{\"severity\":\"error\",\"code\":\"E011\",\
+             \"message\":\"name `Pé` was \\\"defined\\\"\",\
+             \"help\":\"did you mean `Q`?\",\"file\":\"M.ta\",\
+             \"line\":2,\"column\":11,\"end_line\":2,\"end_column\":13}"
+        );
+    }
+
+    #[test]
+    fn test_message() {
+        assert_eq!(
+            message("error", "no such file"),
+            "{\"severity\":\"error\",\"code\":null,\"message\":\"no such file\"}"
+        );
+    }
+
+    #[test]
+    fn test_file_message() {
+        assert_eq!(
+            file_message("error", "no such file", "A.ta"),
+            "{\"severity\":\"error\",\"code\":null,\"message\":\"no such file\",\"file\":\"A.ta\"}"
+        );
+    }
+}
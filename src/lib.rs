@@ -0,0 +1,191 @@
+//! A compiler from Titania, a subset of Oberon-07, to WebAssembly.
+//!
+//! The stages are usable on their own: a [`Scanner`] turns source text into
+//! tokens, a [`Parser`] turns them into a syntax tree, [`compile`] turns that
+//! into a WebAssembly module, and [`emit_module`] and [`encode_module`] write
+//! it in the text and binary formats. [`compile_str`] does all of them at
+//! once, e.g.
+//!
+//! ```
+//! let output = titania::compile_str(
+//!     "MODULE M; VAR x*: INTEGER; BEGIN x := 1 END M.",
+//!     &titania::Options::default(),
+//! )
+//! .unwrap();
+//! assert!(output.wat().starts_with("(module $M"));
+//! ```
+pub mod ast;
+pub mod compiler;
+pub mod emission;
+pub mod encoding;
+pub mod error;
+pub mod evaluator;
+pub mod interpreter;
+pub mod json;
+pub mod library;
+pub mod linker;
+pub mod parser;
+pub mod repl;
+pub mod scanner;
+pub mod span;
+mod suggest;
+pub mod symbols;
+pub mod table;
+pub mod types;
+mod unicode;
+pub mod warnings;
+
+pub use crate::compiler::{Options, compile};
+pub use crate::emission::emit_module;
+pub use crate::encoding::encode_module;
+pub use crate::error::Diagnostics;
+pub use crate::parser::Parser;
+pub use crate::scanner::Scanner;
+
+use crate::ast::wat;
+use crate::symbols::Symbols;
+use crate::table::Table;
+use crate::warnings::Warning;
+
+/// A compiled module.
+pub struct Output {
+    /// The WebAssembly module.
+    pub module: wat::Module,
+
+    /// The symbols the module exports, as written to its symbol file.
+    pub symbols: Symbols,
+
+    /// The warnings about the module, in source order.
+    pub warnings: Vec<Warning>,
+}
+
+impl Output {
+    /// The module in WebAssembly text format.
+    pub fn wat(&self) -> String {
+        emit_module(&self.module)
+    }
+
+    /// The module in WebAssembly binary format.
+    pub fn wasm(&self) -> Vec<u8> {
+        encode_module(&self.module)
+    }
+}
+
+/// Compiles a module's source text, linking in the library modules it
+/// imports. The module can't import other modules; use [`compile`] with
+/// their symbols for that.
+pub fn compile_str(source: &str, options: &Options) -> Result<Output, Diagnostics> {
+    let scanner = Scanner::new(source).unicode_identifiers(options.unicode_identifiers);
+    let module = Parser::with_scanner(scanner).module()?;
+    let warnings = warnings::check(&module);
+    let mut table = Table::new();
+    for import in module.imports.iter() {
+        if let Some(symbols) = library::symbols(&import.name) {
+            table.push(&import.name, symbols);
+        }
+    }
+    let (module, symbols) = compile(&module, &table, options)?;
+    let module = library::link_program(vec![module]).expect("library modules link");
+    Ok(Output {
+        module,
+        symbols,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ErrorTag, error_tag};
+
+    #[test]
+    fn test_compile_str() {
+        let source = "MODULE M;
+PROCEDURE P*(x: INTEGER): INTEGER;
+BEGIN
+  RETURN x;
+  x := 1
+END;
+END M.";
+        let output = compile_str(source, &Options::default()).unwrap();
+        assert_eq!(output.module.name, "M");
+        assert_eq!(output.warnings.len(), 1);
+        assert!(output.wat().contains("(export \"P\" (func $P))"));
+        assert_eq!(&output.wasm()[..4], b"\0asm");
+    }
+
+    #[test]
+    fn test_compile_str_library() {
+        let source = "MODULE M; IMPORT Out; BEGIN Out.Ln END M.";
+        let output = compile_str(source, &Options::default()).unwrap();
+        assert_eq!(output.module.start, None);
+        assert!(output.wat().contains("(export \"_start\" (func $_start))"));
+    }
+
+    #[test]
+    fn test_compile_str_escapes_names() {
+        let source = r#"MODULE M;
+PROCEDURE [EXTERNAL "my\env", "log"] Log(x: INTEGER);
+PROCEDURE [EXPORT "a\b"] P*;
+END;
+END M."#;
+        let wat = compile_str(source, &Options::default()).unwrap().wat();
+        assert!(wat.contains(r#"(import "my\\env" "log" (func $Log"#));
+        assert!(wat.contains(r#"(export "a\\b" (func $P))"#));
+    }
+
+    #[test]
+    fn test_compile_str_start_export() {
+        let source = "MODULE M;
+IMPORT Out;
+PROCEDURE [EXPORT \"_start\"] P*;
+END;
+BEGIN
+  Out.Ln
+END M.";
+        assert_eq!(
+            error_tag(compile_str(source, &Options::default())),
+            Some(ErrorTag::ExportRedefinition("_start".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_compile_str_memory_export() {
+        let source = "MODULE M;
+IMPORT Out;
+PROCEDURE [EXPORT \"memory\"] P*;
+END;
+END M.";
+        assert_eq!(
+            error_tag(compile_str(source, &Options::default())),
+            Some(ErrorTag::ExportRedefinition("memory".to_string()))
+        );
+        let options = Options {
+            export_memory: true,
+            ..Options::default()
+        };
+        let source = "MODULE M; IMPORT Out; BEGIN Out.Ln END M.";
+        let wat = compile_str(source, &options).unwrap().wat();
+        assert!(wat.contains("(export \"memory\" (memory $memory))"));
+    }
+
+    #[test]
+    fn test_compile_str_unicode_identifiers() {
+        let source = "MODULE M; VAR größe: INTEGER; BEGIN größe := 1 END M.";
+        assert!(compile_str(source, &Options::default()).is_err());
+        let options = Options {
+            unicode_identifiers: true,
+            ..Options::default()
+        };
+        assert!(compile_str(source, &options).is_ok());
+    }
+
+    #[test]
+    fn test_compile_str_error() {
+        let result = compile_str("MODULE M; BEGIN x := 1 END M.", &Options::default());
+        assert_eq!(
+            error_tag(result),
+            Some(ErrorTag::UndefinedName("x".to_string()))
+        );
+    }
+}
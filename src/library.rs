@@ -0,0 +1,2412 @@
+//! The library modules built into the compiler.
+//!
+//! A library module is imported like any other module, but its symbols are
+//! known to the compiler and its implementation is a WAT module the compiler
+//! provides, which is linked into the program. The modules do their input and
+//! output through WASI, so a program using them runs under any WASI runtime.
+//!
+//! - `In` reads from standard input: `In.Int()` reads an integer in decimal,
+//!   after any white space, `In.Char()` reads a character, `In.String(s)`
+//!   reads a string in double quotes into the array `s`, and `In.Done()` is
+//!   `TRUE` if the last read succeeded. Titania has no `VAR` parameters, so
+//!   `Int` and `Char` are function procedures rather than Oberon's proper
+//!   procedures, and `String` writes to the array it's given the address of.
+//! - `Out` writes to standard output: `Out.Int(x)` writes an integer in
+//!   decimal, `Out.Real(x)` a real, `Out.Char(c)` a character,
+//!   `Out.String(s)` a string and `Out.Ln` a line break.
+//! - `Math` has the real functions `Math.Sqrt(x)`, `Math.Sin(x)`,
+//!   `Math.Cos(x)`, `Math.Exp(x)` and `Math.Ln(x)`. `Sqrt` is Wasm's
+//!   `f64.sqrt`, and the others reduce their argument to a small range and
+//!   sum a polynomial.
+//! - `Strings` has Oakwood's `Strings.Length(s)`, `Strings.Append(extra,
+//!   dest)`, `Strings.Insert(source, pos, dest)`, `Strings.Delete(s, pos, n)`,
+//!   `Strings.Extract(source, pos, n, dest)` and `Strings.Pos(pattern, s,
+//!   pos)`. A result that doesn't fit in its array is truncated.
+//!
+//! `In` and `Out` share the linked program's memory, each using its own part
+//! of the first kilobyte as scratch space. `Strings` works on the arrays it's
+//! given in that memory, and `Math` uses no memory or WASI.
+
+use crate::ast::wat::builder::BuilderFunc;
+use crate::ast::wat::{self, Export, ExportDesc, Func, Global, Import, Instr, Memory, Type};
+use crate::compiler::MEMORY_ID;
+use crate::linker::{LinkError, link};
+use crate::symbols::Symbols;
+use crate::types::{self, TypeProc};
+
+/// The module WASI's functions are imported from.
+pub const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+/// The export WASI runtimes call to run a program.
+pub const START_EXPORT: &str = "_start";
+
+/// The scratch memory `In` reads into. The I/O vector for `fd_read` is at 64,
+/// the number of bytes read at 72 and the buffer starts at 128.
+const IN_IOVEC: i32 = 64;
+const IN_NREAD: i32 = 72;
+const IN_BUFFER: i32 = 128;
+const IN_BUFFER_SIZE: i32 = 256;
+
+/// The scratch memory `Out` writes from. The I/O vector for `fd_write` is at
+/// 0, the number of bytes written at 8, a character at 16, an integer's
+/// digits end at 32 and a real's digits start at 32.
+const OUT_IOVEC: i32 = 0;
+const OUT_NWRITTEN: i32 = 8;
+const OUT_CHAR: i32 = 16;
+const OUT_DIGITS: i32 = 32;
+const OUT_REAL: i32 = 32;
+
+/// `ln(2)` split into a part with its low bits zero, so that multiplying it
+/// by an integer exponent is exact, and the rest.
+const LN2_HI: f64 = 6.931_471_803_691_238e-1;
+const LN2_LO: f64 = 1.908_214_929_270_587_7e-10;
+
+/// `pi / 2` split into three parts, the first two with their low bits zero,
+/// so that subtracting a multiple of it loses little precision.
+const PIO2_1: f64 = 1.570_796_326_734_125_6;
+const PIO2_2: f64 = 6.077_100_506_303_966e-11;
+const PIO2_3: f64 = 2.022_266_248_795_950_6e-21;
+
+/// The first 48 24-bit digits of `2 / pi`, enough for `Sin` and `Cos` to
+/// reduce any finite argument with 144 bits to spare below the point.
+const TWO_OVER_PI: [u32; 48] = [
+    0xA2F983, 0x6E4E44, 0x1529FC, 0x2757D1, 0xF534DD, 0xC0DB62, 0x95993C, 0x439041, 0xFE5163,
+    0xABDEBB, 0xC561B7, 0x246E3A, 0x424DD2, 0xE00649, 0x2EEA09, 0xD1921C, 0xFE1DEB, 0x1CB129,
+    0xA73EE8, 0x8235F5, 0x2EBB44, 0x84E99C, 0x7026B4, 0x5F7E41, 0x3991D6, 0x398353, 0x39F49C,
+    0x845F8B, 0xBDF928, 0x3B1FF8, 0x97FFDE, 0x05980F, 0xEF2F11, 0x8B5A0A, 0x6D1F6D, 0x367ECF,
+    0x27CB09, 0xB74F46, 0x3F669E, 0x5FEA2D, 0x7527BA, 0xC7EBE5, 0xF17B3D, 0x0739F7, 0x8A5292,
+    0xEA6BFB, 0x5FB11F, 0x8D5D08,
+];
+
+/// The largest argument `Sin` and `Cos` reduce with `PIO2_1`, `PIO2_2` and
+/// `PIO2_3`, as the multiple of `pi / 2` subtracted is then exact.
+const TRIG_SMALL: f64 = 1048576.0;
+
+/// The largest argument of `Exp` whose result is finite, and the smallest
+/// whose result isn't rounded to zero.
+const EXP_MAX: f64 = 709.782_712_893_384;
+const EXP_MIN: f64 = -745.2;
+
+/// Whether there's a library module with the given name.
+pub fn is_library(name: &str) -> bool {
+    matches!(name, "In" | "Math" | "Out" | "Strings")
+}
+
+/// Whether the library module with the given name uses WASI, so a program
+/// importing it must export its memory and is run by `_start`.
+pub fn uses_wasi(name: &str) -> bool {
+    matches!(name, "In" | "Out")
+}
+
+/// The symbols of a library module, if there's one with the given name.
+pub fn symbols(name: &str) -> Option<Symbols> {
+    let bool = types::Type::new_bool;
+    let int = types::Type::new_int;
+    let char = types::Type::new_char;
+    let real = types::Type::new_real;
+    let char_array = types::Type::new_char_array;
+    let procs = match name {
+        "In" => vec![
+            ("Char", TypeProc::new(Vec::new(), Some(char()))),
+            ("Done", TypeProc::new(Vec::new(), Some(bool()))),
+            ("Int", TypeProc::new(Vec::new(), Some(int()))),
+            ("String", TypeProc::new(vec![char_array()], None)),
+        ],
+        "Math" => ["Cos", "Exp", "Ln", "Sin", "Sqrt"]
+            .into_iter()
+            .map(|name| (name, TypeProc::new(vec![real()], Some(real()))))
+            .collect(),
+        "Out" => vec![
+            ("Char", TypeProc::new(vec![char()], None)),
+            ("Int", TypeProc::new(vec![int()], None)),
+            ("Ln", TypeProc::new(Vec::new(), None)),
+            ("Real", TypeProc::new(vec![real()], None)),
+            ("String", TypeProc::new(vec![char_array()], None)),
+        ],
+        "Strings" => vec![
+            (
+                "Append",
+                TypeProc::new(vec![char_array(), char_array()], None),
+            ),
+            (
+                "Delete",
+                TypeProc::new(vec![char_array(), int(), int()], None),
+            ),
+            (
+                "Extract",
+                TypeProc::new(vec![char_array(), int(), int(), char_array()], None),
+            ),
+            (
+                "Insert",
+                TypeProc::new(vec![char_array(), int(), char_array()], None),
+            ),
+            ("Length", TypeProc::new(vec![char_array()], Some(int()))),
+            (
+                "Pos",
+                TypeProc::new(vec![char_array(), char_array(), int()], Some(int())),
+            ),
+        ],
+        _ => return None,
+    };
+
+    let mut symbols = Symbols::new(name);
+    for (name, t_proc) in procs {
+        symbols.procs.push(name, t_proc);
+    }
+    Some(symbols)
+}
+
+/// The implementation of a library module, if there's one with the given
+/// name.
+pub fn module(name: &str) -> Option<wat::Module> {
+    match name {
+        "In" => Some(module_in()),
+        "Math" => Some(module_math()),
+        "Out" => Some(module_out()),
+        "Strings" => Some(module_strings()),
+        _ => None,
+    }
+}
+
+/// Links compiled modules with the library modules they import, into a
+/// module named after the last module. A single module that imports no
+/// library module is returned as it is.
+///
+/// A program using WASI is run by calling its `_start` export rather than by
+/// its start function, since WASI's functions can't be called until the
+/// module has been instantiated. WASI also needs the memory exported as
+/// `memory`, so a program using `In` or `Out` can't export anything else
+/// under that name.
+pub fn link_program(mut modules: Vec<wat::Module>) -> Result<wat::Module, LinkError> {
+    let mut library: Vec<wat::Module> = Vec::new();
+    for import in modules.iter().flat_map(|module| module.imports.iter()) {
+        let linked = |module: &wat::Module| module.name == import.module;
+        if modules.iter().any(linked) || library.iter().any(linked) {
+            continue;
+        }
+        library.extend(module(&import.module));
+    }
+
+    let memory_clash = |module: &wat::Module| {
+        module
+            .exports
+            .iter()
+            .any(|e| e.name == MEMORY_ID && !matches!(e.desc, ExportDesc::Memory(_)))
+    };
+    let memory = library.iter().any(|module| !module.memories.is_empty());
+    if memory && modules.iter().any(memory_clash) {
+        return Err(LinkError::ReservedExport(MEMORY_ID.to_owned()));
+    }
+
+    let mut program = match modules.pop() {
+        Some(module) if modules.is_empty() && library.is_empty() => module,
+        Some(module) => {
+            let name = module.name.clone();
+            library.append(&mut modules);
+            library.push(module);
+            link(&name, library)?
+        }
+        None => panic!("there must be a module to link"),
+    };
+
+    let wasi = program.imports.iter().any(|i| i.module == WASI_MODULE);
+    if wasi && let Some(start) = program.start.take() {
+        if program.exports.iter().any(|e| e.name == START_EXPORT) {
+            return Err(LinkError::ReservedExport(START_EXPORT.to_owned()));
+        }
+        program.exports.push(Export {
+            name: START_EXPORT.to_owned(),
+            desc: ExportDesc::Func(start),
+        });
+    }
+    Ok(program)
+}
+
+/// `In`, which reads with WASI's `fd_read` into a buffer.
+fn module_in() -> wat::Module {
+    use Instr::*;
+
+    // peek() returns the next byte without reading it, or -1 at the end of
+    // the input, filling the buffer when it's empty. An error reading is
+    // taken as the end of the input.
+    let peek = BuilderFunc::new()
+        .set_name("peek")
+        .set_result(Some(Type::I32))
+        .set_body(vec![
+            GlobalGet("pos".to_owned()),
+            GlobalGet("len".to_owned()),
+            I32Eq,
+            If(vec![
+                I32Const(IN_IOVEC),
+                I32Const(IN_BUFFER),
+                I32Store(0),
+                I32Const(IN_IOVEC),
+                I32Const(IN_BUFFER_SIZE),
+                I32Store(4),
+                I32Const(IN_NREAD),
+                I32Const(0),
+                I32Store(0),
+                I32Const(0),
+                I32Const(IN_IOVEC),
+                I32Const(1),
+                I32Const(IN_NREAD),
+                Call("fd_read".to_owned()),
+                Drop,
+                I32Const(0),
+                GlobalSet("pos".to_owned()),
+                I32Const(IN_NREAD),
+                I32Load(0),
+                GlobalSet("len".to_owned()),
+            ]),
+            GlobalGet("pos".to_owned()),
+            GlobalGet("len".to_owned()),
+            I32LtU,
+            If(vec![
+                GlobalGet("pos".to_owned()),
+                I32Load8U(IN_BUFFER as u32),
+                Return,
+            ]),
+            I32Const(-1),
+        ])
+        .build();
+
+    // advance() reads the byte peek() returned.
+    let advance = BuilderFunc::new()
+        .set_name("advance")
+        .set_body(vec![
+            GlobalGet("pos".to_owned()),
+            I32Const(1),
+            I32Add,
+            GlobalSet("pos".to_owned()),
+        ])
+        .build();
+
+    let char = BuilderFunc::new()
+        .set_name("Char")
+        .set_result(Some(Type::I32))
+        .add_local("c", Type::I32)
+        .set_body(vec![
+            Call("peek".to_owned()),
+            LocalSet("c".to_owned()),
+            LocalGet("c".to_owned()),
+            I32Const(0),
+            I32LtS,
+            If(vec![
+                I32Const(0),
+                GlobalSet("done".to_owned()),
+                I32Const(0),
+                Return,
+            ]),
+            Call("advance".to_owned()),
+            I32Const(1),
+            GlobalSet("done".to_owned()),
+            LocalGet("c".to_owned()),
+        ])
+        .build();
+
+    let done = BuilderFunc::new()
+        .set_name("Done")
+        .set_result(Some(Type::I32))
+        .set_body(vec![GlobalGet("done".to_owned())])
+        .build();
+
+    // Int() skips white space and control characters, then reads an
+    // optional `-` and the digits. It fails, returning 0, if there are no
+    // digits or the number doesn't fit in an INTEGER. Comparing unsigned,
+    // c < 33 excludes the end of the input, d < 10 checks that c is a digit,
+    // and (limit - d) / 10 < n checks that n * 10 + d would be over the
+    // limit, 2^31 - 1 or 2^31 if the number is negative.
+    let int = BuilderFunc::new()
+        .set_name("Int")
+        .set_result(Some(Type::I32))
+        .add_local("c", Type::I32)
+        .add_local("d", Type::I32)
+        .add_local("n", Type::I32)
+        .add_local("negative", Type::I32)
+        .add_local("digits", Type::I32)
+        .add_local("overflow", Type::I32)
+        .set_body(vec![
+            Loop(vec![
+                Call("peek".to_owned()),
+                LocalSet("c".to_owned()),
+                LocalGet("c".to_owned()),
+                I32Const(33),
+                I32LtU,
+                If(vec![Call("advance".to_owned())]),
+                LocalGet("c".to_owned()),
+                I32Const(33),
+                I32LtU,
+                BrIf(0),
+            ]),
+            LocalGet("c".to_owned()),
+            I32Const(b'-' as i32),
+            I32Eq,
+            If(vec![
+                Call("advance".to_owned()),
+                I32Const(1),
+                LocalSet("negative".to_owned()),
+            ]),
+            Loop(vec![
+                Call("peek".to_owned()),
+                I32Const(b'0' as i32),
+                I32Sub,
+                LocalSet("d".to_owned()),
+                LocalGet("d".to_owned()),
+                I32Const(10),
+                I32LtU,
+                If(vec![
+                    Call("advance".to_owned()),
+                    I32Const(i32::MAX),
+                    LocalGet("negative".to_owned()),
+                    I32Add,
+                    LocalGet("d".to_owned()),
+                    I32Sub,
+                    I32Const(10),
+                    I32DivU,
+                    LocalGet("n".to_owned()),
+                    I32LtU,
+                    If(vec![I32Const(1), LocalSet("overflow".to_owned())]),
+                    LocalGet("overflow".to_owned()),
+                    I32Eqz,
+                    If(vec![
+                        LocalGet("n".to_owned()),
+                        I32Const(10),
+                        I32Mul,
+                        LocalGet("d".to_owned()),
+                        I32Add,
+                        LocalSet("n".to_owned()),
+                    ]),
+                    I32Const(1),
+                    LocalSet("digits".to_owned()),
+                ]),
+                LocalGet("d".to_owned()),
+                I32Const(10),
+                I32LtU,
+                BrIf(0),
+            ]),
+            LocalGet("digits".to_owned()),
+            LocalGet("overflow".to_owned()),
+            I32Eqz,
+            I32And,
+            GlobalSet("done".to_owned()),
+            LocalGet("overflow".to_owned()),
+            If(vec![I32Const(0), LocalSet("n".to_owned())]),
+            LocalGet("negative".to_owned()),
+            If(vec![
+                I32Const(0),
+                LocalGet("n".to_owned()),
+                I32Sub,
+                LocalSet("n".to_owned()),
+            ]),
+            LocalGet("n".to_owned()),
+        ])
+        .build();
+
+    // String(s) skips white space and control characters, then reads a
+    // string in double quotes, which can't run past the end of its line,
+    // into s, ending it with 0X. It fails if there's no string or it doesn't
+    // fit, leaving s with as much as fits. s's length, counting the 0X, is
+    // in the word before it. All of the string's n characters are read, but
+    // only those before the limit are stored.
+    let get = |name: &str| LocalGet(name.to_owned());
+    let set = |name: &str| LocalSet(name.to_owned());
+    let string = BuilderFunc::new()
+        .set_name("String")
+        .add_param("s", Type::I32)
+        .add_local("c", Type::I32)
+        .add_local("n", Type::I32)
+        .add_local("limit", Type::I32)
+        .set_body(vec![
+            Loop(vec![
+                Call("peek".to_owned()),
+                set("c"),
+                get("c"),
+                I32Const(33),
+                I32LtU,
+                If(vec![Call("advance".to_owned())]),
+                get("c"),
+                I32Const(33),
+                I32LtU,
+                BrIf(0),
+            ]),
+            get("s"),
+            I32Const(4),
+            I32Sub,
+            I32Load(0),
+            I32Const(1),
+            I32Sub,
+            set("limit"),
+            I32Const(0),
+            GlobalSet("done".to_owned()),
+            get("c"),
+            I32Const(b'"' as i32),
+            I32Eq,
+            If(vec![
+                Call("advance".to_owned()),
+                while_loop(
+                    vec![
+                        Call("peek".to_owned()),
+                        set("c"),
+                        get("c"),
+                        I32Const(b'"' as i32),
+                        I32Eq,
+                        I32Eqz,
+                        get("c"),
+                        I32Const(b' ' as i32),
+                        I32LtS,
+                        I32Eqz,
+                        I32And,
+                    ],
+                    vec![
+                        Call("advance".to_owned()),
+                        get("n"),
+                        get("limit"),
+                        I32LtU,
+                        If(vec![get("s"), get("n"), I32Add, get("c"), I32Store8(0)]),
+                        get("n"),
+                        I32Const(1),
+                        I32Add,
+                        set("n"),
+                    ],
+                ),
+                get("c"),
+                I32Const(b'"' as i32),
+                I32Eq,
+                If(vec![
+                    Call("advance".to_owned()),
+                    get("limit"),
+                    get("n"),
+                    I32LtU,
+                    I32Eqz,
+                    GlobalSet("done".to_owned()),
+                ]),
+                get("limit"),
+                get("n"),
+                I32LtU,
+                If(vec![get("limit"), set("n")]),
+            ]),
+            get("s"),
+            get("n"),
+            I32Add,
+            I32Const(0),
+            I32Store8(0),
+        ])
+        .build();
+
+    let global = |name: &str, value| Global {
+        name: name.to_owned(),
+        t: Type::I32,
+        mutable: true,
+        init: I32Const(value),
+    };
+    let globals = vec![global("pos", 0), global("len", 0), global("done", 1)];
+
+    library_module(
+        "In",
+        vec![wasi_import("fd_read")],
+        globals,
+        vec![peek, advance, char, done, int, string],
+    )
+}
+
+/// `Out`, which writes with WASI's `fd_write`.
+fn module_out() -> wat::Module {
+    use Instr::*;
+
+    // write(ptr, len) writes len bytes from ptr to standard output.
+    let write = BuilderFunc::new()
+        .set_name("write")
+        .add_param("ptr", Type::I32)
+        .add_param("len", Type::I32)
+        .set_body(vec![
+            I32Const(OUT_IOVEC),
+            LocalGet("ptr".to_owned()),
+            I32Store(0),
+            I32Const(OUT_IOVEC),
+            LocalGet("len".to_owned()),
+            I32Store(4),
+            I32Const(1),
+            I32Const(OUT_IOVEC),
+            I32Const(1),
+            I32Const(OUT_NWRITTEN),
+            Call("fd_write".to_owned()),
+            Drop,
+        ])
+        .build();
+
+    // Int(x) writes x's digits backwards from the end of the buffer. The
+    // magnitude is divided unsigned, so the most negative integer works too.
+    let int = BuilderFunc::new()
+        .set_name("Int")
+        .add_param("x", Type::I32)
+        .add_local("n", Type::I32)
+        .add_local("p", Type::I32)
+        .set_body(vec![
+            I32Const(OUT_DIGITS),
+            LocalSet("p".to_owned()),
+            LocalGet("x".to_owned()),
+            LocalSet("n".to_owned()),
+            LocalGet("x".to_owned()),
+            I32Const(0),
+            I32LtS,
+            If(vec![
+                I32Const(0),
+                LocalGet("x".to_owned()),
+                I32Sub,
+                LocalSet("n".to_owned()),
+            ]),
+            Loop(vec![
+                LocalGet("p".to_owned()),
+                I32Const(1),
+                I32Sub,
+                LocalSet("p".to_owned()),
+                LocalGet("p".to_owned()),
+                LocalGet("n".to_owned()),
+                I32Const(10),
+                I32RemU,
+                I32Const(b'0' as i32),
+                I32Add,
+                I32Store8(0),
+                LocalGet("n".to_owned()),
+                I32Const(10),
+                I32DivU,
+                LocalSet("n".to_owned()),
+                LocalGet("n".to_owned()),
+                BrIf(0),
+            ]),
+            LocalGet("x".to_owned()),
+            I32Const(0),
+            I32LtS,
+            If(vec![
+                LocalGet("p".to_owned()),
+                I32Const(1),
+                I32Sub,
+                LocalSet("p".to_owned()),
+                LocalGet("p".to_owned()),
+                I32Const(b'-' as i32),
+                I32Store8(0),
+            ]),
+            LocalGet("p".to_owned()),
+            I32Const(OUT_DIGITS),
+            LocalGet("p".to_owned()),
+            I32Sub,
+            Call("write".to_owned()),
+        ])
+        .build();
+
+    let write_char = |name: &str, param: Option<&str>| {
+        let mut builder = BuilderFunc::new();
+        builder.set_name(name);
+        let c = match param {
+            Some(param) => {
+                builder.add_param(param, Type::I32);
+                LocalGet(param.to_owned())
+            }
+            None => I32Const(b'\n' as i32),
+        };
+        builder
+            .set_body(vec![
+                I32Const(OUT_CHAR),
+                c,
+                I32Store8(0),
+                I32Const(OUT_CHAR),
+                I32Const(1),
+                Call("write".to_owned()),
+            ])
+            .build()
+    };
+
+    let get = |name: &str| LocalGet(name.to_owned());
+    let set = |name: &str| LocalSet(name.to_owned());
+
+    // String(s) writes the characters from s up to the 0X after them.
+    let string = BuilderFunc::new()
+        .set_name("String")
+        .add_param("s", Type::I32)
+        .add_local("n", Type::I32)
+        .set_body(vec![
+            while_loop(
+                vec![get("s"), get("n"), I32Add, I32Load8U(0)],
+                vec![get("n"), I32Const(1), I32Add, set("n")],
+            ),
+            get("s"),
+            get("n"),
+            Call("write".to_owned()),
+        ])
+        .build();
+
+    // scale(x, p) is x * 10^p, multiplying or dividing by powers of ten that
+    // are exact, so that it's rounded at most a few times.
+    let mut body = vec![
+        while_loop(
+            vec![get("p"), F64Const(22.0), F64Gt],
+            vec![
+                get("x"),
+                F64Const(1e22),
+                F64Mul,
+                set("x"),
+                get("p"),
+                F64Const(22.0),
+                F64Sub,
+                set("p"),
+            ],
+        ),
+        while_loop(
+            vec![get("p"), F64Const(-22.0), F64Lt],
+            vec![
+                get("x"),
+                F64Const(1e22),
+                F64Div,
+                set("x"),
+                get("p"),
+                F64Const(22.0),
+                F64Add,
+                set("p"),
+            ],
+        ),
+    ];
+    for (condition, step, apply) in [(F64Gt, F64Sub, F64Mul), (F64Lt, F64Add, F64Div)] {
+        body.extend([
+            F64Const(1.0),
+            set("t"),
+            while_loop(
+                vec![get("p"), F64Const(0.0), condition],
+                vec![
+                    get("t"),
+                    F64Const(10.0),
+                    F64Mul,
+                    set("t"),
+                    get("p"),
+                    F64Const(1.0),
+                    step,
+                    set("p"),
+                ],
+            ),
+            get("x"),
+            get("t"),
+            apply,
+            set("x"),
+        ]);
+    }
+    body.push(get("x"));
+    let scale = BuilderFunc::new()
+        .set_name("scale")
+        .add_param("x", Type::F64)
+        .add_param("p", Type::F64)
+        .set_result(Some(Type::F64))
+        .add_local("t", Type::F64)
+        .set_body(body)
+        .build();
+
+    // Real(x) writes x to 15 significant digits, with trailing zeros dropped,
+    // as a real literal with a scale factor, e.g. 1.5E-3, or as nan, inf or
+    // -inf. The digits are those of m, the integer nearest x * 10^(14 - e),
+    // where e is the exponent making 10^14 <= m < 10^15. e is estimated by
+    // dividing a copy of x by powers of ten, and corrected if m is out of
+    // range. Scaling x is rounded, so when x * 10^(14 - e) is near halfway
+    // between integers, m may be the other one. m's digits are written in
+    // two parts, each fitting an `i32`.
+    let write_and_return = |text: &str| -> Vec<Instr> {
+        text.bytes()
+            .flat_map(|c| [I32Const(i32::from(c)), Call("Char".to_owned())])
+            .chain([Return])
+            .collect()
+    };
+    // Sets m for e, after adding the given amount to e.
+    let mantissa = |change: f64| {
+        vec![
+            get("e"),
+            F64Const(change),
+            F64Add,
+            set("e"),
+            get("x"),
+            F64Const(14.0),
+            get("e"),
+            F64Sub,
+            Call("scale".to_owned()),
+            F64Nearest,
+            set("m"),
+        ]
+    };
+    let exponent_step = |power: f64, divide: bool| {
+        let (op, e_op) = if divide {
+            (F64Div, F64Add)
+        } else {
+            (F64Mul, F64Sub)
+        };
+        vec![
+            get("y"),
+            F64Const(10f64.powf(power)),
+            op,
+            set("y"),
+            get("e"),
+            F64Const(power),
+            e_op,
+            set("e"),
+        ]
+    };
+    let negate = || {
+        vec![
+            I32Const(i32::from(b'-')),
+            Call("Char".to_owned()),
+            get("x"),
+            F64Neg,
+            set("x"),
+        ]
+    };
+    // Writes the digits of an integer backwards from p down to the given
+    // address.
+    let digits = |n: &str, end: i32| {
+        Loop(vec![
+            get("p"),
+            I32Const(1),
+            I32Sub,
+            set("p"),
+            get("p"),
+            get(n),
+            I32Const(10),
+            I32RemU,
+            I32Const(i32::from(b'0')),
+            I32Add,
+            I32Store8(0),
+            get(n),
+            I32Const(10),
+            I32DivU,
+            set(n),
+            get("p"),
+            I32Const(end),
+            I32Sub,
+            BrIf(0),
+        ])
+    };
+
+    let mut body = vec![get("x"), get("x"), F64Ne, If(write_and_return("nan"))];
+    body.extend([
+        get("x"),
+        F64Const(0.0),
+        F64Lt,
+        If(negate()),
+        // Only -0.0 is still negative, and 1 / -0.0 is -inf.
+        F64Const(1.0),
+        get("x"),
+        F64Div,
+        F64Const(0.0),
+        F64Lt,
+        If(negate()),
+        get("x"),
+        F64Const(f64::INFINITY),
+        F64Eq,
+        If(write_and_return("inf")),
+        get("x"),
+        F64Const(0.0),
+        F64Eq,
+        If(write_and_return("0.0E0")),
+        get("x"),
+        set("y"),
+        while_loop(
+            vec![get("y"), F64Const(1e10), F64Lt, I32Eqz],
+            exponent_step(10.0, true),
+        ),
+        while_loop(
+            vec![get("y"), F64Const(10.0), F64Lt, I32Eqz],
+            exponent_step(1.0, true),
+        ),
+        while_loop(
+            vec![get("y"), F64Const(1e-10), F64Lt],
+            exponent_step(10.0, false),
+        ),
+        while_loop(
+            vec![get("y"), F64Const(1.0), F64Lt],
+            exponent_step(1.0, false),
+        ),
+    ]);
+    body.extend(mantissa(0.0));
+    body.extend([
+        get("m"),
+        F64Const(1e14),
+        F64Lt,
+        If(mantissa(-1.0)),
+        get("m"),
+        F64Const(1e15),
+        F64Lt,
+        I32Eqz,
+        If(mantissa(1.0)),
+        get("m"),
+        F64Const(1e8),
+        F64Div,
+        F64Floor,
+        I32TruncF64S,
+        set("hi"),
+        get("m"),
+        get("hi"),
+        F64ConvertI32S,
+        F64Const(1e8),
+        F64Mul,
+        F64Sub,
+        I32TruncF64S,
+        set("lo"),
+        // The 15 digits go from OUT_REAL + 1, and the first is then moved
+        // before a decimal point.
+        I32Const(OUT_REAL + 16),
+        set("p"),
+        digits("lo", OUT_REAL + 8),
+        digits("hi", OUT_REAL + 1),
+        I32Const(OUT_REAL),
+        I32Const(OUT_REAL + 1),
+        I32Load8U(0),
+        I32Store8(0),
+        I32Const(OUT_REAL + 1),
+        I32Const(i32::from(b'.')),
+        I32Store8(0),
+        I32Const(OUT_REAL + 16),
+        set("p"),
+        while_loop(
+            vec![
+                get("p"),
+                I32Const(1),
+                I32Sub,
+                I32Load8U(0),
+                I32Const(i32::from(b'0')),
+                I32Eq,
+                I32Const(OUT_REAL + 3),
+                get("p"),
+                I32LtS,
+                I32And,
+            ],
+            vec![get("p"), I32Const(1), I32Sub, set("p")],
+        ),
+        I32Const(OUT_REAL),
+        get("p"),
+        I32Const(OUT_REAL),
+        I32Sub,
+        Call("write".to_owned()),
+        I32Const(i32::from(b'E')),
+        Call("Char".to_owned()),
+        get("e"),
+        I32TruncF64S,
+        Call("Int".to_owned()),
+    ]);
+    let real = BuilderFunc::new()
+        .set_name("Real")
+        .add_param("x", Type::F64)
+        .add_local("e", Type::F64)
+        .add_local("y", Type::F64)
+        .add_local("m", Type::F64)
+        .add_local("hi", Type::I32)
+        .add_local("lo", Type::I32)
+        .add_local("p", Type::I32)
+        .set_body(body)
+        .build();
+
+    library_module(
+        "Out",
+        vec![wasi_import("fd_write")],
+        Vec::new(),
+        vec![
+            write,
+            int,
+            write_char("Char", Some("c")),
+            write_char("Ln", None),
+            string,
+            scale,
+            real,
+        ],
+    )
+}
+
+/// `Math`, whose functions follow Rust's `f64` functions, and so the C
+/// library's: a NaN gives a NaN, and so does `Ln` of a negative number, while
+/// `Ln(0.0)` is minus infinity. Each function reduces its argument to a
+/// small range and sums a Taylor series, long enough that its error is below
+/// the rounding error, so results are within a few ulps, for `Exp` only for
+/// results above the smallest normal number. `Sin` and `Cos` reduce arguments
+/// above 2^20 with enough digits of `2 / pi` to be as accurate for any finite
+/// argument.
+fn module_math() -> wat::Module {
+    use Instr::*;
+
+    let get = |name: &str| LocalGet(name.to_owned());
+    let set = |name: &str| LocalSet(name.to_owned());
+    // Sets a local to itself times a power of two, adding the power to k.
+    let scale = |name: &str, power: i32| {
+        vec![
+            get(name),
+            F64Const(2f64.powi(power)),
+            F64Mul,
+            set(name),
+            get("k"),
+            F64Const(-f64::from(power)),
+            F64Add,
+            set("k"),
+        ]
+    };
+    // Returns x if it's a NaN.
+    let nan = || vec![get("x"), get("x"), F64Ne, If(vec![get("x"), Return])];
+    let factorials = inverse_factorials(18);
+
+    let sqrt = BuilderFunc::new()
+        .set_name("Sqrt")
+        .add_param("x", Type::F64)
+        .set_result(Some(Type::F64))
+        .set_body(vec![get("x"), F64Sqrt])
+        .build();
+
+    // Exp(x) is 2^k e^r, where k is the integer nearest x / ln(2), so
+    // |r| <= ln(2) / 2. The power of two is applied 2^32 at a time, then 2 at
+    // a time, so that the result only overflows at the last step.
+    let mut body = nan();
+    body.extend([
+        get("x"),
+        F64Const(EXP_MAX),
+        F64Gt,
+        If(vec![F64Const(f64::INFINITY), Return]),
+        get("x"),
+        F64Const(EXP_MIN),
+        F64Lt,
+        If(vec![F64Const(0.0), Return]),
+        get("x"),
+        F64Const(std::f64::consts::LOG2_E),
+        F64Mul,
+        F64Nearest,
+        set("k"),
+        get("x"),
+        get("k"),
+        F64Const(LN2_HI),
+        F64Mul,
+        F64Sub,
+        get("k"),
+        F64Const(LN2_LO),
+        F64Mul,
+        F64Sub,
+        set("r"),
+    ]);
+    body.extend(horner("r", &factorials[..14]));
+    body.extend([
+        set("x"),
+        while_loop(vec![get("k"), F64Const(32.0), F64Gt], scale("x", 32)),
+        while_loop(vec![get("k"), F64Const(-32.0), F64Lt], scale("x", -32)),
+        while_loop(vec![get("k"), F64Const(0.0), F64Gt], scale("x", 1)),
+        while_loop(vec![get("k"), F64Const(0.0), F64Lt], scale("x", -1)),
+        get("x"),
+    ]);
+    let exp = BuilderFunc::new()
+        .set_name("Exp")
+        .add_param("x", Type::F64)
+        .set_result(Some(Type::F64))
+        .add_local("k", Type::F64)
+        .add_local("r", Type::F64)
+        .set_body(body)
+        .build();
+
+    // Ln(x) is k ln(2) + ln(1 + f), where x = 2^k (1 + f) and
+    // 1/sqrt(2) <= 1 + f <= sqrt(2). As in fdlibm, with s = f / (2 + f), so
+    // |s| < 0.172, and h = f^2 / 2, ln(1 + f) = 2s + 2s^3/3 + 2s^5/5 + ...
+    // is summed as f - h + s (h + R), where R = 2s^2/3 + 2s^4/5 + ..., since
+    // most of the rounding error is then in the small terms.
+    let coefficients: Vec<f64> = (1..11).map(|i| 2.0 / f64::from(2 * i + 1)).collect();
+    let mut body = nan();
+    body.extend([
+        get("x"),
+        F64Const(0.0),
+        F64Lt,
+        If(vec![F64Const(f64::NAN), Return]),
+        get("x"),
+        F64Const(0.0),
+        F64Eq,
+        If(vec![F64Const(f64::NEG_INFINITY), Return]),
+        get("x"),
+        F64Const(f64::INFINITY),
+        F64Eq,
+        If(vec![get("x"), Return]),
+        while_loop(
+            vec![get("x"), F64Const(2f64.powi(32)), F64Gt],
+            scale("x", -32),
+        ),
+        while_loop(
+            vec![get("x"), F64Const(2f64.powi(-32)), F64Lt],
+            scale("x", 32),
+        ),
+        while_loop(
+            vec![get("x"), F64Const(std::f64::consts::SQRT_2), F64Gt],
+            scale("x", -1),
+        ),
+        while_loop(
+            vec![get("x"), F64Const(std::f64::consts::FRAC_1_SQRT_2), F64Lt],
+            scale("x", 1),
+        ),
+        get("x"),
+        F64Const(1.0),
+        F64Sub,
+        set("f"),
+        get("f"),
+        get("f"),
+        F64Const(2.0),
+        F64Add,
+        F64Div,
+        set("s"),
+        get("s"),
+        get("s"),
+        F64Mul,
+        set("z"),
+        F64Const(0.5),
+        get("f"),
+        get("f"),
+        F64Mul,
+        F64Mul,
+        set("h"),
+        get("k"),
+        F64Const(LN2_HI),
+        F64Mul,
+        get("h"),
+        get("s"),
+        get("h"),
+        get("z"),
+    ]);
+    body.extend(horner("z", &coefficients));
+    body.extend([
+        F64Mul,
+        F64Add,
+        F64Mul,
+        get("k"),
+        F64Const(LN2_LO),
+        F64Mul,
+        F64Add,
+        F64Sub,
+        get("f"),
+        F64Sub,
+        F64Sub,
+    ]);
+    let ln = BuilderFunc::new()
+        .set_name("Ln")
+        .add_param("x", Type::F64)
+        .set_result(Some(Type::F64))
+        .add_local("k", Type::F64)
+        .add_local("f", Type::F64)
+        .add_local("h", Type::F64)
+        .add_local("s", Type::F64)
+        .add_local("z", Type::F64)
+        .set_body(body)
+        .build();
+
+    // trig(x, q) is sin(x + q pi/2). With k the integer nearest x / (pi/2)
+    // and r = x - k pi/2, so |r| <= pi/4, it's sin(r), cos(r), -sin(r) or
+    // -cos(r) as k + q is 0, 1, 2 or 3 modulo 4, where
+    // sin(r) = r - r^3/3! + r^5/5! - ... and cos(r) = 1 - r^2/2! + r^4/4! - ...
+    let series = |first: usize| -> Vec<f64> {
+        let signs = [-1.0, 1.0].into_iter().cycle();
+        factorials[first..]
+            .iter()
+            .step_by(2)
+            .zip(signs)
+            .map(|(f, sign)| f * sign)
+            .collect()
+    };
+    let sin_r = || {
+        let mut instrs = vec![get("r"), get("r"), get("z"), F64Mul];
+        instrs.extend(horner("z", &series(3)));
+        instrs.extend([F64Mul, F64Add]);
+        instrs
+    };
+    let cos_r = || {
+        let mut instrs = vec![F64Const(1.0), get("z")];
+        instrs.extend(horner("z", &series(2)));
+        instrs.extend([F64Mul, F64Add]);
+        instrs
+    };
+    // A larger argument is reduced as in fdlibm's __kernel_rem_pio2, by
+    // Payne and Hanek's method. With y = |x| = t 2^(24s) and t < 2^72, t's
+    // 24-bit digits a0 to a4 are worth 2^48 down to 2^-48, and the digits of
+    // 2/pi, digit(i), are worth 2^(-24(i + 1)), so a_j digit(n - j) is worth
+    // 2^(24(s + 1 - n)). Those with n <= s are multiples of 4, which don't
+    // change the quadrant, so q0 to q6 sum those with n from s + 1 to s + 7,
+    // giving y / (pi/2) from 2^0 to 2^-144 exactly. Carrying from q6 up
+    // leaves k = q0 modulo 4 and the fraction's digits in q1 to q6, which if
+    // it's at least 1/2 is replaced by its difference from 1. The fraction is
+    // summed from its smallest digit as f, plus the rounding error g, and
+    // multiplied by pi/2 with the high part of f split off, so that most of
+    // the product is exact.
+    let reduce_large = || {
+        let q = |o: usize| format!("q{o}");
+        let a = |j: usize| format!("a{j}");
+        let weight = |o: usize| 2f64.powi(-24 * o as i32);
+        let mut instrs = vec![
+            get("y"),
+            get("y"),
+            F64Sub,
+            get("y"),
+            get("y"),
+            F64Sub,
+            F64Ne,
+            If(vec![get("x"), get("x"), F64Sub, Return]),
+            get("y"),
+            set("t"),
+            F64Const(0.0),
+            set("s"),
+            while_loop(
+                vec![get("t"), F64Const(2f64.powi(72)), F64Lt, I32Eqz],
+                vec![
+                    get("t"),
+                    F64Const(2f64.powi(-24)),
+                    F64Mul,
+                    set("t"),
+                    get("s"),
+                    F64Const(1.0),
+                    F64Add,
+                    set("s"),
+                ],
+            ),
+        ];
+        for j in 0..5 {
+            let power = 24 * (2 - j as i32);
+            instrs.extend([
+                get("t"),
+                F64Const(2f64.powi(-power)),
+                F64Mul,
+                F64Floor,
+                set(&a(j)),
+                get("t"),
+                get(&a(j)),
+                F64Const(2f64.powi(power)),
+                F64Mul,
+                F64Sub,
+                set("t"),
+            ]);
+        }
+        for o in 0..7 {
+            instrs.push(F64Const(0.0));
+            for j in 0..5 {
+                instrs.extend([
+                    get(&a(j)),
+                    get("s"),
+                    F64Const(1.0 + o as f64 - j as f64),
+                    F64Add,
+                    Call("digit".to_owned()),
+                    F64Mul,
+                    F64Add,
+                ]);
+            }
+            instrs.push(set(&q(o)));
+        }
+        instrs.extend([F64Const(0.0), set("c")]);
+        for o in (0..7).rev() {
+            instrs.extend([get(&q(o)), get("c"), F64Add, set(&q(o))]);
+            if o > 0 {
+                instrs.extend([
+                    get(&q(o)),
+                    F64Const(2f64.powi(-24)),
+                    F64Mul,
+                    F64Floor,
+                    set("c"),
+                    get(&q(o)),
+                    get("c"),
+                    F64Const(2f64.powi(24)),
+                    F64Mul,
+                    F64Sub,
+                    set(&q(o)),
+                ]);
+            }
+        }
+        let mut complement = vec![
+            get("q0"),
+            F64Const(1.0),
+            F64Add,
+            set("q0"),
+            F64Const(-1.0),
+            set("e"),
+        ];
+        for o in 1..7 {
+            let top = if o == 6 {
+                2f64.powi(24)
+            } else {
+                2f64.powi(24) - 1.0
+            };
+            complement.extend([F64Const(top), get(&q(o)), F64Sub, set(&q(o))]);
+        }
+        instrs.extend([
+            get("q0"),
+            get("q0"),
+            F64Const(0.25),
+            F64Mul,
+            F64Floor,
+            F64Const(4.0),
+            F64Mul,
+            F64Sub,
+            set("q0"),
+            F64Const(1.0),
+            set("e"),
+            get("q1"),
+            F64Const(2f64.powi(23)),
+            F64Lt,
+            I32Eqz,
+            If(complement),
+            F64Const(0.0),
+        ]);
+        for o in (1..7).rev() {
+            instrs.extend([get(&q(o)), F64Const(weight(o)), F64Mul, F64Add]);
+        }
+        instrs.extend([
+            set("f"),
+            get("q1"),
+            F64Const(weight(1)),
+            F64Mul,
+            get("f"),
+            F64Sub,
+        ]);
+        for o in 2..7 {
+            instrs.extend([get(&q(o)), F64Const(weight(o)), F64Mul, F64Add]);
+        }
+        instrs.extend([
+            set("g"),
+            get("f"),
+            F64Const(2f64.powi(33) + 1.0),
+            F64Mul,
+            set("h"),
+            get("h"),
+            get("h"),
+            get("f"),
+            F64Sub,
+            F64Sub,
+            set("h"),
+            get("h"),
+            F64Const(PIO2_1),
+            F64Mul,
+            get("f"),
+            get("h"),
+            F64Sub,
+            F64Const(PIO2_1),
+            F64Mul,
+            get("f"),
+            F64Const(PIO2_2 + PIO2_3),
+            F64Mul,
+            F64Add,
+            get("g"),
+            F64Const(PIO2_1),
+            F64Mul,
+            F64Add,
+            F64Add,
+            get("e"),
+            F64Mul,
+            set("r"),
+            get("q0"),
+            set("k"),
+            get("x"),
+            F64Const(0.0),
+            F64Lt,
+            If(vec![get("k"), F64Neg, set("k"), get("r"), F64Neg, set("r")]),
+        ]);
+        instrs
+    };
+    let quadrant = |q: f64, mut result: Vec<Instr>, negate: bool| {
+        if negate {
+            result.push(F64Neg);
+        }
+        result.push(Return);
+        [get("q"), F64Const(q), F64Eq, If(result)]
+    };
+    let small = vec![
+        get("x"),
+        F64Const(std::f64::consts::FRAC_2_PI),
+        F64Mul,
+        F64Nearest,
+        set("k"),
+        get("x"),
+        get("k"),
+        F64Const(PIO2_1),
+        F64Mul,
+        F64Sub,
+        get("k"),
+        F64Const(PIO2_2),
+        F64Mul,
+        F64Sub,
+        get("k"),
+        F64Const(PIO2_3),
+        F64Mul,
+        F64Sub,
+        set("r"),
+    ];
+    let mut body = vec![
+        get("x"),
+        set("y"),
+        get("x"),
+        F64Const(0.0),
+        F64Lt,
+        If(vec![get("x"), F64Neg, set("y")]),
+        get("y"),
+        F64Const(TRIG_SMALL),
+        F64Gt,
+        I32Eqz,
+        If(small),
+        get("y"),
+        F64Const(TRIG_SMALL),
+        F64Gt,
+        If(reduce_large()),
+        get("r"),
+        get("r"),
+        F64Mul,
+        set("z"),
+        get("k"),
+        get("q"),
+        F64Add,
+        set("q"),
+        get("q"),
+        get("q"),
+        F64Const(0.25),
+        F64Mul,
+        F64Floor,
+        F64Const(4.0),
+        F64Mul,
+        F64Sub,
+        set("q"),
+    ];
+    body.extend(quadrant(0.0, sin_r(), false));
+    body.extend(quadrant(1.0, cos_r(), false));
+    body.extend(quadrant(2.0, sin_r(), true));
+    body.extend(cos_r());
+    body.push(F64Neg);
+    let mut trig = BuilderFunc::new();
+    trig.set_name("trig")
+        .add_param("x", Type::F64)
+        .add_param("q", Type::F64)
+        .set_result(Some(Type::F64));
+    let locals = ["k", "r", "z", "y", "t", "s", "c", "e", "f", "g", "h"]
+        .map(str::to_owned)
+        .into_iter()
+        .chain((0..5).map(|j| format!("a{j}")))
+        .chain((0..7).map(|o| format!("q{o}")));
+    for local in locals {
+        trig.add_local(&local, Type::F64);
+    }
+    let trig = trig.set_body(body).build();
+    // digit(i) is the ith digit of 2/pi, or 0 past the last.
+    let mut body: Vec<Instr> = TWO_OVER_PI
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &digit)| {
+            [
+                get("i"),
+                F64Const(i as f64),
+                F64Eq,
+                If(vec![F64Const(f64::from(digit)), Return]),
+            ]
+        })
+        .collect();
+    body.push(F64Const(0.0));
+    let digit = BuilderFunc::new()
+        .set_name("digit")
+        .add_param("i", Type::F64)
+        .set_result(Some(Type::F64))
+        .set_body(body)
+        .build();
+    // Sin(-0.0) is -0.0, but sin(r) adds 0.0 to it.
+    let sin = BuilderFunc::new()
+        .set_name("Sin")
+        .add_param("x", Type::F64)
+        .set_result(Some(Type::F64))
+        .set_body(vec![
+            get("x"),
+            F64Const(0.0),
+            F64Eq,
+            If(vec![get("x"), Return]),
+            get("x"),
+            F64Const(0.0),
+            Call("trig".to_owned()),
+        ])
+        .build();
+    let cos = BuilderFunc::new()
+        .set_name("Cos")
+        .add_param("x", Type::F64)
+        .set_result(Some(Type::F64))
+        .set_body(vec![get("x"), F64Const(1.0), Call("trig".to_owned())])
+        .build();
+
+    let funcs = vec![sqrt, exp, ln, digit, trig, sin, cos];
+    library_module("Math", Vec::new(), Vec::new(), funcs)
+}
+
+/// `Strings`, Oakwood's operations on arrays of characters, each ended by a
+/// 0X unless it fills its array. An array's address is passed, with its
+/// length in the word before it, so a destination that's too short gets as
+/// much of the result as fits, with a 0X after it, rather than trapping. A
+/// negative position or count is taken as 0. `Strings` defines no memory:
+/// the arrays are in the memory of the modules passing them.
+fn module_strings() -> wat::Module {
+    use Instr::*;
+
+    let get = |name: &str| LocalGet(name.to_owned());
+    let set = |name: &str| LocalSet(name.to_owned());
+    let call = |name: &str| Call(name.to_owned());
+    // Instructions setting a local to the sum of two others.
+    let add = |to: &str, a: &str, b: &str| vec![get(a), get(b), I32Add, set(to)];
+    // Instructions setting a local to 0 if it's negative.
+    let at_least_0 = |name: &str| {
+        vec![
+            get(name),
+            I32Const(0),
+            I32LtS,
+            If(vec![I32Const(0), set(name)]),
+        ]
+    };
+    // Instructions setting a local to another if that's less.
+    let at_most = |name: &str, other: &str| {
+        vec![
+            get(other),
+            get(name),
+            I32LtS,
+            If(vec![get(other), set(name)]),
+        ]
+    };
+    // Instructions pushing the most characters an array can hold before its
+    // 0X.
+    let limit = |s: &str| vec![get(s), I32Const(4), I32Sub, I32Load(0), I32Const(1), I32Sub];
+    // An instruction copying the character at `from + i` to `to + j`.
+    let copy = |to: &str, j: &str, from: &str, i: &str| {
+        vec![
+            get(to),
+            get(j),
+            I32Add,
+            get(from),
+            get(i),
+            I32Add,
+            I32Load8U(0),
+            I32Store8(0),
+        ]
+    };
+    let increment = |name: &str| vec![get(name), I32Const(1), I32Add, set(name)];
+
+    // Length(s) counts the characters before the 0X.
+    let length = BuilderFunc::new()
+        .set_name("Length")
+        .add_param("s", Type::I32)
+        .set_result(Some(Type::I32))
+        .add_local("n", Type::I32)
+        .add_local("limit", Type::I32)
+        .set_body(
+            [
+                limit("s"),
+                vec![set("limit")],
+                vec![while_loop(
+                    vec![
+                        get("n"),
+                        get("limit"),
+                        I32LtS,
+                        get("s"),
+                        get("n"),
+                        I32Add,
+                        I32Load8U(0),
+                        I32Eqz,
+                        I32Eqz,
+                        I32And,
+                    ],
+                    increment("n"),
+                )],
+                vec![get("n")],
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        )
+        .build();
+
+    // Insert(source, pos, dest) moves dest's characters from pos on along
+    // by source's length, from the last, then copies source's characters
+    // into the gap, dropping any past dest's limit. source can only be dest
+    // when inserting at the end.
+    let insert = BuilderFunc::new()
+        .set_name("Insert")
+        .add_param("source", Type::I32)
+        .add_param("pos", Type::I32)
+        .add_param("dest", Type::I32)
+        .add_local("n", Type::I32)
+        .add_local("d", Type::I32)
+        .add_local("limit", Type::I32)
+        .add_local("i", Type::I32)
+        .add_local("j", Type::I32)
+        .set_body(
+            [
+                vec![get("source"), call("Length"), set("n")],
+                vec![get("dest"), call("Length"), set("d")],
+                limit("dest"),
+                vec![set("limit")],
+                at_least_0("pos"),
+                at_most("pos", "d"),
+                vec![get("d"), set("i")],
+                vec![while_loop(
+                    vec![get("pos"), get("i"), I32LtS],
+                    [
+                        vec![get("i"), I32Const(1), I32Sub, set("i")],
+                        add("j", "i", "n"),
+                        vec![
+                            get("j"),
+                            get("limit"),
+                            I32LtS,
+                            If(copy("dest", "j", "dest", "i")),
+                        ],
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                )],
+                vec![I32Const(0), set("i")],
+                vec![while_loop(
+                    vec![get("i"), get("n"), I32LtS],
+                    [
+                        add("j", "pos", "i"),
+                        vec![
+                            get("j"),
+                            get("limit"),
+                            I32LtS,
+                            If(copy("dest", "j", "source", "i")),
+                        ],
+                        increment("i"),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                )],
+                add("j", "d", "n"),
+                at_most("j", "limit"),
+                vec![get("dest"), get("j"), I32Add, I32Const(0), I32Store8(0)],
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        )
+        .build();
+
+    // Append(extra, dest) inserts extra at the end of dest.
+    let append = BuilderFunc::new()
+        .set_name("Append")
+        .add_param("extra", Type::I32)
+        .add_param("dest", Type::I32)
+        .set_body(vec![
+            get("extra"),
+            get("dest"),
+            call("Length"),
+            get("dest"),
+            call("Insert"),
+        ])
+        .build();
+
+    // Delete(s, pos, n) moves the characters after the n from pos back over
+    // them. n is cut to the characters there are from pos.
+    let delete = BuilderFunc::new()
+        .set_name("Delete")
+        .add_param("s", Type::I32)
+        .add_param("pos", Type::I32)
+        .add_param("n", Type::I32)
+        .add_local("d", Type::I32)
+        .add_local("rest", Type::I32)
+        .add_local("i", Type::I32)
+        .add_local("j", Type::I32)
+        .set_body(
+            [
+                vec![get("s"), call("Length"), set("d")],
+                at_least_0("pos"),
+                at_least_0("n"),
+                vec![
+                    get("pos"),
+                    get("d"),
+                    I32LtS,
+                    If([
+                        vec![get("d"), get("pos"), I32Sub, set("rest")],
+                        at_most("n", "rest"),
+                        add("i", "pos", "n"),
+                        vec![get("pos"), set("j")],
+                        vec![while_loop(
+                            vec![get("i"), get("d"), I32LtS],
+                            [copy("s", "j", "s", "i"), increment("i"), increment("j")]
+                                .into_iter()
+                                .flatten()
+                                .collect(),
+                        )],
+                        vec![get("s"), get("j"), I32Add, I32Const(0), I32Store8(0)],
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect()),
+                ],
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        )
+        .build();
+
+    // Extract(source, pos, n, dest) copies the n characters from pos in
+    // source to dest. n is cut to the characters there are from pos, and to
+    // dest's limit.
+    let extract = BuilderFunc::new()
+        .set_name("Extract")
+        .add_param("source", Type::I32)
+        .add_param("pos", Type::I32)
+        .add_param("n", Type::I32)
+        .add_param("dest", Type::I32)
+        .add_local("rest", Type::I32)
+        .add_local("limit", Type::I32)
+        .add_local("i", Type::I32)
+        .add_local("j", Type::I32)
+        .set_body(
+            [
+                vec![get("source"), call("Length"), set("rest")],
+                limit("dest"),
+                vec![set("limit")],
+                at_least_0("pos"),
+                at_most("pos", "rest"),
+                vec![get("rest"), get("pos"), I32Sub, set("rest")],
+                at_least_0("n"),
+                at_most("n", "rest"),
+                at_most("n", "limit"),
+                vec![while_loop(
+                    vec![get("i"), get("n"), I32LtS],
+                    [
+                        add("j", "pos", "i"),
+                        copy("dest", "i", "source", "j"),
+                        increment("i"),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                )],
+                vec![get("dest"), get("n"), I32Add, I32Const(0), I32Store8(0)],
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        )
+        .build();
+
+    // Pos(pattern, s, pos) tries each position i from pos where pattern
+    // would fit in s, returning the first where j, the number of characters
+    // matching, reaches pattern's length, or -1 if there's none. Comparing
+    // pattern's 0X stops j at its length.
+    let pos = BuilderFunc::new()
+        .set_name("Pos")
+        .add_param("pattern", Type::I32)
+        .add_param("s", Type::I32)
+        .add_param("pos", Type::I32)
+        .set_result(Some(Type::I32))
+        .add_local("m", Type::I32)
+        .add_local("d", Type::I32)
+        .add_local("i", Type::I32)
+        .add_local("j", Type::I32)
+        .add_local("k", Type::I32)
+        .set_body(
+            [
+                vec![get("pattern"), call("Length"), set("m")],
+                vec![get("s"), call("Length"), set("d")],
+                at_least_0("pos"),
+                vec![get("pos"), set("i")],
+                vec![while_loop(
+                    vec![get("d"), get("i"), get("m"), I32Add, I32LtS, I32Eqz],
+                    [
+                        vec![I32Const(0), set("j")],
+                        vec![while_loop(
+                            [
+                                vec![get("j"), get("m"), I32LtS],
+                                add("k", "i", "j"),
+                                vec![
+                                    get("pattern"),
+                                    get("j"),
+                                    I32Add,
+                                    I32Load8U(0),
+                                    get("s"),
+                                    get("k"),
+                                    I32Add,
+                                    I32Load8U(0),
+                                    I32Eq,
+                                    I32And,
+                                ],
+                            ]
+                            .into_iter()
+                            .flatten()
+                            .collect(),
+                            increment("j"),
+                        )],
+                        vec![get("j"), get("m"), I32Eq, If(vec![get("i"), Return])],
+                        increment("i"),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                )],
+                vec![I32Const(-1)],
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        )
+        .build();
+
+    let funcs = vec![length, insert, append, delete, extract, pos];
+    library_module("Strings", Vec::new(), Vec::new(), funcs)
+}
+
+/// The coefficients `1/n!` for `n` up to but not including the given number.
+fn inverse_factorials(count: i32) -> Vec<f64> {
+    let mut coefficient = 1.0;
+    (0..count)
+        .map(|n| {
+            if n > 0 {
+                coefficient /= f64::from(n);
+            }
+            coefficient
+        })
+        .collect()
+}
+
+/// Instructions pushing the polynomial with the given coefficients, lowest
+/// degree first, of an `f64` local, by Horner's rule.
+fn horner(x: &str, coefficients: &[f64]) -> Vec<Instr> {
+    let mut coefficients = coefficients.iter().rev();
+    let last = coefficients.next().expect("a polynomial has a coefficient");
+    let mut instrs = vec![Instr::F64Const(*last)];
+    for &coefficient in coefficients {
+        instrs.extend([
+            Instr::LocalGet(x.to_owned()),
+            Instr::F64Mul,
+            Instr::F64Const(coefficient),
+            Instr::F64Add,
+        ]);
+    }
+    instrs
+}
+
+/// A loop running its body while the condition pushes a non-zero `i32`. The
+/// body's branch to label 1, the loop, runs it again.
+fn while_loop(condition: Vec<Instr>, mut body: Vec<Instr>) -> Instr {
+    body.extend([Instr::I32Const(1), Instr::BrIf(1)]);
+    let mut instrs = condition;
+    instrs.push(Instr::If(body));
+    Instr::Loop(instrs)
+}
+
+/// Imports a WASI function taking four `i32` values and returning an error
+/// number, as `fd_read` and `fd_write` do.
+fn wasi_import(name: &str) -> Import {
+    Import {
+        module: WASI_MODULE.to_owned(),
+        name: name.to_owned(),
+        id: name.to_owned(),
+        params: vec![Type::I32; 4],
+        result: Some(Type::I32),
+    }
+}
+
+/// A library module. One importing WASI's functions uses and exports the
+/// memory, which WASI reads and writes through.
+fn library_module(
+    name: &str,
+    imports: Vec<Import>,
+    globals: Vec<Global>,
+    funcs: Vec<Func>,
+) -> wat::Module {
+    let (memories, exports) = if imports.is_empty() {
+        (Vec::new(), Vec::new())
+    } else {
+        let memory = Memory {
+            name: MEMORY_ID.to_owned(),
+            min: 1,
+            max: None,
+        };
+        let export = Export {
+            name: MEMORY_ID.to_owned(),
+            desc: ExportDesc::Memory(MEMORY_ID.to_owned()),
+        };
+        (vec![memory], vec![export])
+    };
+    wat::Module {
+        name: name.to_owned(),
+        imports,
+        tables: Vec::new(),
+        memories,
+        globals,
+        funcs,
+        exports,
+        start: None,
+        data: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::interpreter::{Instance, Value};
+    use crate::parser::Parser;
+    use crate::table::Table;
+
+    /// Compiles and links a program, returning what running it writes given
+    /// the input.
+    fn run(source: &str, input: &str) -> String {
+        let module = Parser::new(source).module().unwrap();
+        let mut table = Table::new();
+        for import in module.imports.iter() {
+            table.push(&import.name, symbols(&import.name).unwrap());
+        }
+        let (module, _) = crate::compile(&module, &table, &Default::default()).unwrap();
+        let program = link_program(vec![module]).unwrap();
+
+        let mut output = Vec::new();
+        let mut instance =
+            Instance::with_io(&program, input.as_bytes(), &mut output, io::sink()).unwrap();
+        instance.call(START_EXPORT, Vec::new()).unwrap();
+        drop(instance);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_out() {
+        let source = "MODULE M;
+IMPORT Out;
+BEGIN
+  Out.Int(0); Out.Char(20X); Out.Int(2147483647); Out.Ln;
+  Out.Char(41X)
+END M.";
+        assert_eq!(run(source, ""), "0 2147483647\nA");
+    }
+
+    /// Calls `In`'s procedures in turn on the input, returning each one's
+    /// result with `In.Done()`'s after it.
+    fn read(input: &str, procs: &[&str]) -> Vec<(i32, bool)> {
+        let mut module = module("In").unwrap();
+        for name in ["Char", "Done", "Int"] {
+            module.exports.push(Export {
+                name: name.to_owned(),
+                desc: ExportDesc::Func(name.to_owned()),
+            });
+        }
+
+        let mut instance =
+            Instance::with_io(&module, input.as_bytes(), io::sink(), io::sink()).unwrap();
+        let mut call = |name: &str| match instance.call(name, Vec::new()) {
+            Ok(Some(Value::I32(value))) => value,
+            result => panic!("{name} returned {result:?}"),
+        };
+        procs
+            .iter()
+            .map(|name| (call(name), call("Done") != 0))
+            .collect()
+    }
+
+    #[test]
+    fn test_in() {
+        let procs = ["Int", "Int", "Char", "Int", "Char"];
+        assert_eq!(
+            read(" 12\n-7x", &procs),
+            vec![(12, true), (-7, true), (120, true), (0, false), (0, false)]
+        );
+        assert_eq!(
+            read("a", &procs),
+            vec![(0, false), (0, false), (97, true), (0, false), (0, false)]
+        );
+    }
+
+    #[test]
+    fn test_in_program() {
+        let source = "MODULE M;
+IMPORT In, Out;
+VAR done: BOOLEAN;
+BEGIN
+  Out.Int(In.Int()); done := In.Done(); ASSERT(done); Out.Char(In.Char())
+END M.";
+        assert_eq!(run(source, "42x"), "42x");
+    }
+
+    /// Calls `In.String` on the input the given number of times, reading
+    /// into an array of the given length, returning what each read and
+    /// `In.Done()`.
+    fn read_strings(input: &str, len: u32, times: usize) -> Vec<(String, bool)> {
+        const S: usize = 2048;
+        let mut module = module("In").unwrap();
+        for name in ["Done", "String"] {
+            module.exports.push(Export {
+                name: name.to_owned(),
+                desc: ExportDesc::Func(name.to_owned()),
+            });
+        }
+        module.data.push(wat::Data {
+            offset: S as u32 - 4,
+            bytes: len.to_le_bytes().to_vec(),
+        });
+
+        let mut instance =
+            Instance::with_io(&module, input.as_bytes(), io::sink(), io::sink()).unwrap();
+        let mut read = || {
+            instance.call("String", vec![Value::I32(S as i32)]).unwrap();
+            let done = instance.call("Done", Vec::new()).unwrap() == Some(Value::I32(1));
+            let s = &instance.memory()[S..];
+            let end = s.iter().position(|&b| b == 0).unwrap();
+            (String::from_utf8(s[..end].to_vec()).unwrap(), done)
+        };
+        (0..times).map(|_| read()).collect()
+    }
+
+    #[test]
+    fn test_in_string() {
+        let strings = |v: &[(&str, bool)]| -> Vec<(String, bool)> {
+            v.iter().map(|&(s, done)| (s.to_owned(), done)).collect()
+        };
+        assert_eq!(
+            read_strings(" \"ab c\"\n\"\"\"Größe\" \"x", 8, 5),
+            strings(&[
+                ("ab c", true),
+                ("", true),
+                ("Größe", true),
+                ("x", false),
+                ("", false)
+            ])
+        );
+        assert_eq!(
+            read_strings("\"abc\" \"ab\n\"a\" x", 3, 4),
+            strings(&[("ab", false), ("ab", false), ("a", true), ("", false)])
+        );
+    }
+
+    #[test]
+    fn test_in_string_program() {
+        let source = "MODULE M;
+IMPORT In, Out;
+VAR s: ARRAY 16 OF CHAR; t: ARRAY 4 OF CHAR;
+BEGIN
+  In.String(s); In.String(t); Out.String(s); Out.String(t)
+END M.";
+        assert_eq!(run(source, "\"Hello, \" \"world\""), "Hello, wor");
+    }
+
+    #[test]
+    fn test_in_refills_buffer() {
+        let source = "MODULE M; IMPORT In, Out; BEGIN Out.Int(In.Int()) END M.";
+        let input = format!("{}2147483647", " ".repeat(IN_BUFFER_SIZE as usize - 3));
+        assert_eq!(run(source, &input), "2147483647");
+    }
+
+    #[test]
+    fn test_in_overflow() {
+        assert_eq!(
+            read(
+                "99999999999 2147483647 2147483648 -2147483648 -2147483649",
+                &["Int"; 5]
+            ),
+            vec![
+                (0, false),
+                (i32::MAX, true),
+                (0, false),
+                (i32::MIN, true),
+                (0, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_out_negative() {
+        let mut module = module("Out").unwrap();
+        module.exports.push(Export {
+            name: "Int".to_owned(),
+            desc: ExportDesc::Func("Int".to_owned()),
+        });
+
+        let mut output = Vec::new();
+        let mut instance =
+            Instance::with_io(&module, io::empty(), &mut output, io::sink()).unwrap();
+        for x in [-123, i32::MIN] {
+            instance.call("Int", vec![Value::I32(x)]).unwrap();
+        }
+        drop(instance);
+        assert_eq!(String::from_utf8(output).unwrap(), "-123-2147483648");
+    }
+
+    /// Calls a `Math` function.
+    fn math(name: &str, x: f64) -> f64 {
+        let mut module = module("Math").unwrap();
+        module.exports.push(Export {
+            name: name.to_owned(),
+            desc: ExportDesc::Func(name.to_owned()),
+        });
+        let mut instance = Instance::with_io(&module, io::empty(), io::sink(), io::sink()).unwrap();
+        match instance.call(name, vec![Value::F64(x)]) {
+            Ok(Some(Value::F64(value))) => value,
+            result => panic!("{name}({x:?}) returned {result:?}"),
+        }
+    }
+
+    /// Checks a `Math` function is within the given number of ulps of Rust's
+    /// at each argument, or is the same NaN or infinity.
+    fn assert_math(name: &str, f: fn(f64) -> f64, xs: impl Iterator<Item = f64>, ulps: u64) {
+        for x in xs {
+            let (actual, expected) = (math(name, x), f(x));
+            if expected.is_nan() || expected.is_infinite() || expected == 0.0 {
+                assert!(
+                    actual.to_bits() == expected.to_bits() || actual.is_nan() && expected.is_nan(),
+                    "{name}({x:?}) is {actual:?}, not {expected:?}"
+                );
+                continue;
+            }
+            let distance = (actual.to_bits() as i64 - expected.to_bits() as i64).unsigned_abs();
+            assert!(
+                actual.signum() == expected.signum() && distance <= ulps,
+                "{name}({x:?}) is {actual:?}, not {expected:?}"
+            );
+        }
+    }
+
+    /// Arguments spread over the given range, with some irregular spacing.
+    fn spread(min: f64, max: f64, n: i32) -> impl Iterator<Item = f64> {
+        (0..=n).map(move |i| min + (max - min) * (f64::from(i) / f64::from(n)).powf(1.1))
+    }
+
+    #[test]
+    fn test_math_sqrt() {
+        let xs = [
+            0.0,
+            -0.0,
+            1.0,
+            2.0,
+            0.25,
+            1e300,
+            5e-324,
+            -1.0,
+            f64::INFINITY,
+            f64::NAN,
+        ];
+        assert_math(
+            "Sqrt",
+            f64::sqrt,
+            xs.into_iter().chain(spread(0.0, 1e6, 1000)),
+            0,
+        );
+    }
+
+    #[test]
+    fn test_math_exp() {
+        let xs = [
+            0.0,
+            -0.0,
+            1.0,
+            709.78,
+            709.79,
+            -708.0,
+            -746.0,
+            f64::INFINITY,
+        ];
+        let special = [f64::NEG_INFINITY, f64::NAN];
+        assert_math(
+            "Exp",
+            f64::exp,
+            xs.into_iter().chain(spread(-700.0, 700.0, 2000)),
+            1,
+        );
+        assert_math("Exp", f64::exp, spread(-1.0, 1.0, 1000).chain(special), 1);
+    }
+
+    #[test]
+    fn test_math_ln() {
+        let xs = [
+            1.0,
+            2.0,
+            0.5,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            5e-324,
+            1.0 + 1e-15,
+        ];
+        let special = [0.0, -0.0, -1.0, f64::INFINITY, f64::NEG_INFINITY, f64::NAN];
+        assert_math("Ln", f64::ln, xs.into_iter().chain(special), 1);
+        assert_math("Ln", f64::ln, spread(0.5, 2.0, 1000), 1);
+        assert_math("Ln", f64::ln, (-300..300).map(|e| 1.7 * 10f64.powi(e)), 1);
+    }
+
+    #[test]
+    fn test_math_sin_cos() {
+        let xs = [
+            0.0,
+            -0.0,
+            1e-300,
+            1.0,
+            std::f64::consts::FRAC_PI_4,
+            3.0,
+            100.0,
+            1e15,
+            1e17,
+            -1e17,
+            1e20,
+            3e300,
+            1e300,
+            f64::MAX,
+            -f64::MAX,
+        ];
+        let special = [f64::INFINITY, f64::NEG_INFINITY, f64::NAN];
+        for (name, f) in [("Sin", f64::sin as fn(f64) -> f64), ("Cos", f64::cos)] {
+            assert_math(name, f, xs.into_iter().chain(special), 1);
+            assert_math(name, f, spread(-1000.0, 1000.0, 5000), 1);
+            assert_math(name, f, spread(-1e6, 1e6, 5000), 2);
+            assert_math(name, f, spread(1e6, 1e7, 1000), 1);
+            assert_math(name, f, (6..308).map(|e| -1.7 * 10f64.powi(e)), 1);
+        }
+        // The double nearest a multiple of pi/2, which is 4.687e-19 more than
+        // an odd multiple, where the host's cos may be less accurate.
+        let x = 6381956970095103.0 * 2f64.powi(797);
+        assert_math("Sin", |_| 1.0, [x].into_iter(), 0);
+        assert_math("Cos", |_| -4.687165924254628e-19, [x].into_iter(), 1);
+    }
+
+    #[test]
+    fn test_math_program() {
+        let source = "MODULE M;
+IMPORT Math, Out;
+BEGIN
+  Out.Int(FLOOR(Math.Sqrt(16.0))); Out.Int(FLOOR(Math.Exp(1.0))); Out.Int(FLOOR(Math.Cos(0.0)))
+END M.";
+        assert_eq!(run(source, ""), "421");
+    }
+
+    #[test]
+    fn test_link_program_math() {
+        // Math uses no WASI, so a program using only it needs no memory and
+        // runs by its start function.
+        let source = "MODULE M; IMPORT Math; VAR x*: REAL; BEGIN x := Math.Sin(1.0) END M.";
+        let module = Parser::new(source).module().unwrap();
+        let mut table = Table::new();
+        table.push("Math", symbols("Math").unwrap());
+        let (mut module, _) = crate::compile(&module, &table, &Default::default()).unwrap();
+        module.exports[0].name = MEMORY_ID.to_owned();
+        let program = link_program(vec![module]).unwrap();
+        assert!(program.memories.is_empty() && program.start.is_some());
+        assert!(program.exports.iter().all(|e| e.name != START_EXPORT));
+    }
+
+    /// Calls `Out.Real` on each value, returning what it writes for each.
+    fn write_reals(xs: &[f64]) -> Vec<String> {
+        let mut module = module("Out").unwrap();
+        for name in ["Ln", "Real"] {
+            module.exports.push(Export {
+                name: name.to_owned(),
+                desc: ExportDesc::Func(name.to_owned()),
+            });
+        }
+
+        let mut output = Vec::new();
+        let mut instance =
+            Instance::with_io(&module, io::empty(), &mut output, io::sink()).unwrap();
+        for &x in xs {
+            instance.call("Real", vec![Value::F64(x)]).unwrap();
+            instance.call("Ln", Vec::new()).unwrap();
+        }
+        drop(instance);
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn test_out_real() {
+        let xs = [
+            1.5,
+            -2.5e-3,
+            0.0,
+            -0.0,
+            1e100,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NAN,
+        ];
+        let text = [
+            "1.5E0", "-2.5E-3", "0.0E0", "-0.0E0", "1.0E100", "inf", "-inf", "nan",
+        ];
+        assert_eq!(write_reals(&xs), text);
+    }
+
+    #[test]
+    fn test_out_real_digits() {
+        // The 15 digits are those of the nearest decimal, except that the
+        // last may be one off when the value is near halfway, so reading
+        // them back is within about one part in 10^14.
+        let mut xs = vec![
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            5e-324,
+            0.1,
+            1.0 / 3.0,
+            999999999999999.9,
+        ];
+        xs.extend((-300..308).map(|e| -std::f64::consts::PI * 10f64.powi(e)));
+        xs.extend((1..1000).map(|i| f64::from(i) * 1.234567e-5));
+        for (x, text) in xs.iter().zip(write_reals(&xs)) {
+            let (mantissa, _) = text.split_once('E').unwrap();
+            let digits = mantissa.trim_start_matches('-').replace('.', "");
+            assert!(
+                digits.len() <= 15 && mantissa.contains('.'),
+                "{x:?} as {text}"
+            );
+            // The largest value's digits round up past it, read back as inf.
+            let value: f64 = text.parse().unwrap();
+            assert!(
+                value.is_infinite() || ((value - x) / x).abs() < 1e-14,
+                "{x:?} as {text}"
+            );
+        }
+        assert_eq!(
+            write_reals(&[f64::MAX, 5e-324]),
+            ["1.79769313486232E308", "4.94065645841247E-324"]
+        );
+    }
+
+    #[test]
+    fn test_out_string() {
+        let source = "MODULE M;
+IMPORT Out;
+PROCEDURE Greet;
+BEGIN
+  Out.String(\"Hello, \"); Out.String(\"\"); Out.String(\"Größe\")
+END;
+BEGIN
+  Greet; Out.Ln; Out.String(\"Hello, \"); Out.Real(0.25)
+END M.";
+        assert_eq!(run(source, ""), "Hello, Größe\nHello, 2.5E-1");
+    }
+
+    #[test]
+    fn test_link_program() {
+        let source = "MODULE M; VAR x*: INTEGER; BEGIN x := 1 END M.";
+        let module = Parser::new(source).module().unwrap();
+        let (module, _) = crate::compile(&module, &Table::new(), &Default::default()).unwrap();
+        let program = link_program(vec![module]).unwrap();
+        assert_eq!(program.start, Some("_init".to_owned()));
+        assert!(program.imports.is_empty());
+    }
+
+    #[test]
+    fn test_link_program_reserved_export() {
+        let source = "MODULE M; IMPORT Out; BEGIN Out.Ln END M.";
+        let module = Parser::new(source).module().unwrap();
+        let mut table = Table::new();
+        table.push("Out", symbols("Out").unwrap());
+        let (mut module, _) = crate::compile(&module, &table, &Default::default()).unwrap();
+        // The compiler rejects this export, but a module it's linked with
+        // might not know the program uses the library.
+        module.exports.push(Export {
+            name: START_EXPORT.to_owned(),
+            desc: ExportDesc::Func(crate::compiler::INIT_ID.to_owned()),
+        });
+        assert_eq!(
+            link_program(vec![module]).err(),
+            Some(LinkError::ReservedExport(START_EXPORT.to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_link_program_reserved_memory() {
+        let source = "MODULE M; IMPORT Out; VAR x*: INTEGER; BEGIN Out.Ln END M.";
+        let module = Parser::new(source).module().unwrap();
+        let mut table = Table::new();
+        table.push("Out", symbols("Out").unwrap());
+        let (mut module, _) = crate::compile(&module, &table, &Default::default()).unwrap();
+        module.exports[0].name = MEMORY_ID.to_owned();
+        assert_eq!(
+            link_program(vec![module]).err(),
+            Some(LinkError::ReservedExport(MEMORY_ID.to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_strings_insert_append() {
+        let source = "MODULE M;
+IMPORT In, Out, Strings;
+VAR s: ARRAY 8 OF CHAR; t: ARRAY 1 OF CHAR; m: INTEGER;
+BEGIN
+  m := In.Int();
+  Strings.Append(\"bc\", s); Strings.Insert(\"a\", m, s); Strings.Insert(\"XY\", 2, s);
+  Out.String(s); Out.Char(20X); Out.Int(Strings.Length(s)); Out.Ln;
+  Strings.Append(s, s); Out.String(s); Out.Ln;
+  Strings.Insert(\"123\", 1, s); Out.String(s); Out.Ln;
+  Strings.Insert(\"z\", 99, s); Out.String(s); Out.Ln;
+  Strings.Append(\"abc\", t); Out.Int(Strings.Length(t))
+END M.";
+        assert_eq!(run(source, "-1"), "abXYc 5\nabXYcab\na123bXY\na123bXY\n0");
+    }
+
+    #[test]
+    fn test_strings_delete_extract() {
+        let source = "MODULE M;
+IMPORT In, Out, Strings;
+VAR s: ARRAY 8 OF CHAR; t: ARRAY 4 OF CHAR; m: INTEGER;
+BEGIN
+  m := In.Int();
+  Strings.Append(\"abcdefg\", s);
+  Strings.Delete(s, 1, 2); Out.String(s); Out.Ln;
+  Strings.Delete(s, 3, 99); Out.String(s); Out.Ln;
+  Strings.Delete(s, m, 1); Out.String(s); Out.Ln;
+  Strings.Delete(s, 5, 1); Strings.Delete(s, 0, m); Out.String(s); Out.Ln;
+  Strings.Extract(\"hello world\", 6, 99, s); Out.String(s); Out.Ln;
+  Strings.Extract(\"hello world\", 0, 99, t); Out.String(t); Out.Ln;
+  Strings.Extract(\"hello\", 9, 2, t); Out.Int(Strings.Length(t)); Out.Ln;
+  Strings.Extract(\"hello\", m, 2, t); Out.String(t); Out.Ln;
+  Strings.Extract(s, 1, 3, s); Out.String(s)
+END M.";
+        assert_eq!(
+            run(source, "-2"),
+            "adefg\nade\nde\nde\nworld\nhel\n0\nhe\norl"
+        );
+    }
+
+    #[test]
+    fn test_strings_pos() {
+        let source = "MODULE M;
+IMPORT In, Out, Strings;
+VAR m: INTEGER;
+BEGIN
+  m := In.Int();
+  Out.Int(Strings.Pos(\"lo\", \"hello, lo\", 0)); Out.Char(20X);
+  Out.Int(Strings.Pos(\"lo\", \"hello, lo\", 4)); Out.Char(20X);
+  Out.Int(Strings.Pos(\"lo\", \"hello, lo\", 8)); Out.Char(20X);
+  Out.Int(Strings.Pos(\"\", \"ab\", 2)); Out.Char(20X);
+  Out.Int(Strings.Pos(\"\", \"ab\", 3)); Out.Char(20X);
+  Out.Int(Strings.Pos(\"abc\", \"ab\", m)); Out.Char(20X);
+  Out.Int(Strings.Pos(\"ab\", \"ab\", m))
+END M.";
+        assert_eq!(run(source, "-5"), "3 7 -1 2 -1 -1 0");
+    }
+
+    #[test]
+    fn test_strings_program_without_wasi() {
+        let source = "MODULE M;
+IMPORT Strings;
+VAR s: ARRAY 4 OF CHAR; n*: INTEGER;
+BEGIN
+  Strings.Append(\"abcdef\", s); n := Strings.Length(s)
+END M.";
+        let module = Parser::new(source).module().unwrap();
+        let mut table = Table::new();
+        table.push("Strings", symbols("Strings").unwrap());
+        let (module, _) = crate::compile(&module, &table, &Default::default()).unwrap();
+        let program = link_program(vec![module]).unwrap();
+        assert!(program.imports.is_empty());
+        let instance = Instance::new(&program).unwrap();
+        assert_eq!(instance.global("n"), Some(Value::I32(3)));
+    }
+
+    #[test]
+    fn test_symbols() {
+        let text = "MODULE Out\nPROCEDURE Char(CHAR)\nPROCEDURE Int(INTEGER)\nPROCEDURE Ln\n\
+            PROCEDURE Real(REAL)\nPROCEDURE String(ARRAY OF CHAR)\n";
+        assert_eq!(symbols("Out").unwrap().to_string(), text);
+        let text = "MODULE In\nPROCEDURE Char: CHAR\nPROCEDURE Done: BOOLEAN\nPROCEDURE Int: INTEGER\n\
+            PROCEDURE String(ARRAY OF CHAR)\n";
+        assert_eq!(symbols("In").unwrap().to_string(), text);
+        let text = "MODULE Math\nPROCEDURE Cos(REAL): REAL\nPROCEDURE Exp(REAL): REAL\n\
+            PROCEDURE Ln(REAL): REAL\nPROCEDURE Sin(REAL): REAL\nPROCEDURE Sqrt(REAL): REAL\n";
+        assert_eq!(symbols("Math").unwrap().to_string(), text);
+        let text = "MODULE Strings\nPROCEDURE Append(ARRAY OF CHAR, ARRAY OF CHAR)\n\
+            PROCEDURE Delete(ARRAY OF CHAR, INTEGER, INTEGER)\n\
+            PROCEDURE Extract(ARRAY OF CHAR, INTEGER, INTEGER, ARRAY OF CHAR)\n\
+            PROCEDURE Insert(ARRAY OF CHAR, INTEGER, ARRAY OF CHAR)\n\
+            PROCEDURE Length(ARRAY OF CHAR): INTEGER\n\
+            PROCEDURE Pos(ARRAY OF CHAR, ARRAY OF CHAR, INTEGER): INTEGER\n";
+        assert_eq!(symbols("Strings").unwrap().to_string(), text);
+        assert!(symbols("Maths").is_none());
+        assert!(module("Maths").is_none());
+        assert!(is_library("In") && is_library("Strings") && !is_library("Maths"));
+    }
+}
@@ -0,0 +1,759 @@
+//! Linking separately compiled modules into a single WAT module.
+//!
+//! Calls to procedures imported from a module being linked are resolved to
+//! that module's function. Functions, globals and tables with the same
+//! identifier in more than one module are renamed by qualifying them with
+//! their module's name, e.g. `A.P`, but an export name may only be used by one
+//! module. The modules' memories are merged into one memory, large enough for
+//! each of them. A module whose data is addressed from a `_data` global, as
+//! the compiler's string literals are, has its data moved to follow that of
+//! the modules before it, and the global set to match. Imports from modules
+//! that aren't being linked, e.g. host functions, are kept and merged.
+//! Modules are placed in import order, so a module's functions, and its
+//! initialisation, come after those of the modules it imports.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::wat::*;
+
+/// The identifier of the function that runs each module's start function.
+const START_ID: &str = "_start";
+
+/// The identifier of the global holding the address of a module's data, in
+/// a module whose data can be moved.
+pub const DATA_ID: &str = "_data";
+
+/// Enumerates all possible link errors.
+#[derive(Debug, PartialEq)]
+pub enum LinkError {
+    /// More than one module has the same name.
+    DuplicateModule(String),
+
+    /// Two modules export different things under the same name.
+    DuplicateExport {
+        name: String,
+        first: String,
+        second: String,
+    },
+
+    /// Modules import each other. Holds the modules on the cycle, starting
+    /// and ending with the same module.
+    ImportCycle(Vec<String>),
+
+    /// An export has a name the linked module needs for its own export.
+    ReservedExport(String),
+
+    /// A function was imported from a linked module which doesn't define it.
+    UndefinedImport { module: String, name: String },
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use LinkError::*;
+
+        match self {
+            DuplicateModule(name) => write!(f, "module `{name}` is linked more than once"),
+            DuplicateExport {
+                name,
+                first,
+                second,
+            } => write!(f, "modules `{first}` and `{second}` both export `{name}`"),
+            ImportCycle(cycle) => write!(f, "import cycle `{}`", cycle.join(" -> ")),
+            ReservedExport(name) => write!(f, "export name `{name}` is reserved"),
+            UndefinedImport { module, name } => {
+                write!(f, "module `{module}` doesn't define `{name}`")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// Links modules into one module with the given name.
+pub fn link(name: &str, modules: Vec<Module>) -> Result<Module, LinkError> {
+    let mut index = HashMap::new();
+    for (i, module) in modules.iter().enumerate() {
+        if index.insert(module.name.as_str(), i).is_some() {
+            return Err(LinkError::DuplicateModule(module.name.clone()));
+        }
+    }
+
+    let order = import_order(&modules, &index)?;
+
+    // Count how many modules define each identifier.
+    let count_funcs = count(&modules, |module| module.funcs.iter().map(|x| &x.name));
+    let count_tables = count(&modules, |module| module.tables.iter().map(|x| &x.name));
+    let count_globals = count(&modules, |module| module.globals.iter().map(|x| &x.name));
+
+    // An export name may only be used by one module, except that exports of
+    // the merged memory are the same export.
+    let mut export_owners: HashMap<&str, &str> = HashMap::new();
+    for module in modules.iter() {
+        for export in module.exports.iter() {
+            if matches!(export.desc, ExportDesc::Memory(_)) {
+                continue;
+            }
+            let owner = export_owners.entry(&export.name).or_insert(&module.name);
+            if *owner != module.name {
+                return Err(LinkError::DuplicateExport {
+                    name: export.name.clone(),
+                    first: owner.to_string(),
+                    second: module.name.clone(),
+                });
+            }
+        }
+    }
+
+    let mut memory = merge_memories(&modules);
+    let func_id = |module: &Module, name: &str| qualify(&count_funcs, module, name);
+
+    // Find the linked identifier of a function a module defines or imports.
+    let resolve = |i: usize, name: &str| {
+        let mut module = &modules[i];
+        let mut name = name;
+        loop {
+            if module.funcs.iter().any(|func| func.name == name) {
+                return Ok(func_id(module, name));
+            }
+            let Some(import) = module.imports.iter().find(|import| import.id == name) else {
+                return Err(LinkError::UndefinedImport {
+                    module: module.name.clone(),
+                    name: name.to_owned(),
+                });
+            };
+            match index.get(import.module.as_str()) {
+                Some(&j) => {
+                    module = &modules[j];
+                    name = &import.name;
+                }
+                None => return Ok(external_id(import)),
+            }
+        }
+    };
+
+    // Map each module's identifiers to the linked module's identifiers.
+    let mut imports: Vec<Import> = Vec::new();
+    let mut renames = Vec::new();
+    for module in modules.iter() {
+        let mut rename = Renames::default();
+        for func in module.funcs.iter() {
+            rename
+                .funcs
+                .insert(func.name.clone(), func_id(module, &func.name));
+        }
+        for table in module.tables.iter() {
+            let id = qualify(&count_tables, module, &table.name);
+            rename.tables.insert(table.name.clone(), id);
+        }
+        if let Some(memory) = &memory {
+            for memory_module in module.memories.iter() {
+                let id = memory.name.clone();
+                rename.memories.insert(memory_module.name.clone(), id);
+            }
+        }
+        for global in module.globals.iter() {
+            let id = qualify(&count_globals, module, &global.name);
+            rename.globals.insert(global.name.clone(), id);
+        }
+        for import in module.imports.iter() {
+            let id = match index.get(import.module.as_str()) {
+                Some(&i) => resolve(i, &import.name)?,
+                None => {
+                    let id = external_id(import);
+                    if !imports.iter().any(|kept| kept.id == id) {
+                        imports.push(Import {
+                            module: import.module.clone(),
+                            name: import.name.clone(),
+                            id: id.clone(),
+                            params: import.params.clone(),
+                            result: import.result.clone(),
+                        });
+                    }
+                    id
+                }
+            };
+            rename.funcs.insert(import.id.clone(), id);
+        }
+        renames.push(rename);
+    }
+
+    let mut slots: Vec<Option<Module>> = modules.into_iter().map(Some).collect();
+    let mut tables = Vec::new();
+    let mut globals = Vec::new();
+    let mut funcs = Vec::new();
+    let mut exports = Vec::new();
+    let mut starts = Vec::new();
+    let mut data = Vec::new();
+    let mut data_end = None;
+    for i in order {
+        let mut module = slots[i].take().expect("each module is placed once");
+        relocate_data(&mut module, &mut data_end);
+        let rename = &renames[i];
+
+        for mut table in module.tables {
+            table.name = rename.tables[&table.name].clone();
+            tables.push(table);
+        }
+
+        for mut global in module.globals {
+            global.name = rename.globals[&global.name].clone();
+            rename.instr(&mut global.init);
+            globals.push(global);
+        }
+
+        for mut func in module.funcs {
+            func.name = rename.funcs[&func.name].clone();
+            for instr in func.body.iter_mut() {
+                rename.instr(instr);
+            }
+            funcs.push(func);
+        }
+
+        for export in module.exports {
+            let name = export.name;
+            let desc = match export.desc {
+                ExportDesc::Func(id) => ExportDesc::Func(rename.funcs[&id].clone()),
+                ExportDesc::Table(id) => ExportDesc::Table(rename.tables[&id].clone()),
+                ExportDesc::Memory(id) => ExportDesc::Memory(rename.memories[&id].clone()),
+                ExportDesc::Global(id) => ExportDesc::Global(rename.globals[&id].clone()),
+            };
+            let export = Export { name, desc };
+            if !exports.contains(&export) {
+                exports.push(export);
+            }
+        }
+
+        data.extend(module.data);
+
+        if let Some(start) = module.start {
+            starts.push(Instr::Call(rename.funcs[&start].clone()));
+        }
+    }
+
+    if let (Some(memory), Some(end)) = (&mut memory, data_end) {
+        memory.min = memory.min.max(end.div_ceil(PAGE_SIZE));
+    }
+
+    let start = if starts.is_empty() {
+        None
+    } else {
+        funcs.push(Func {
+            name: START_ID.to_owned(),
+            params: Vec::new(),
+            result: None,
+            locals: Vec::new(),
+            body: starts,
+        });
+        Some(START_ID.to_owned())
+    };
+
+    Ok(Module {
+        name: name.to_owned(),
+        imports,
+        tables,
+        memories: memory.into_iter().collect(),
+        globals,
+        funcs,
+        exports,
+        start,
+        data,
+    })
+}
+
+/// Moves a module's data, if it's addressed from a `_data` global, to start
+/// at `end`, the end of the data moved before it, if any, updating `end`.
+fn relocate_data(module: &mut Module, end: &mut Option<u32>) {
+    let Some(global) = module
+        .globals
+        .iter_mut()
+        .find(|global| global.name == DATA_ID)
+    else {
+        return;
+    };
+    let Instr::I32Const(base) = global.init else {
+        return;
+    };
+    let start = end.unwrap_or(base as u32);
+    global.init = Instr::I32Const(start as i32);
+    let mut next = start;
+    for data in module.data.iter_mut() {
+        data.offset = data.offset - base as u32 + start;
+        next = next.max(data.offset + data.bytes.len() as u32);
+    }
+    // Keep each module's data aligned, as it was at its base.
+    *end = Some(next.next_multiple_of(8));
+}
+
+/// Merges the modules' memories into one memory, named after the first.
+fn merge_memories(modules: &[Module]) -> Option<Memory> {
+    let mut memories = modules.iter().flat_map(|module| module.memories.iter());
+    let first = memories.next()?;
+    let mut merged = Memory {
+        name: first.name.clone(),
+        min: first.min,
+        max: first.max,
+    };
+    for memory in memories {
+        merged.min = merged.min.max(memory.min);
+        merged.max = merged.max.zip(memory.max).map(|(a, b)| a.max(b));
+    }
+    Some(merged)
+}
+
+/// Maps a module's identifiers, by kind, to the linked module's identifiers.
+#[derive(Default)]
+struct Renames {
+    funcs: HashMap<String, String>,
+    tables: HashMap<String, String>,
+    memories: HashMap<String, String>,
+    globals: HashMap<String, String>,
+}
+
+impl Renames {
+    /// Renames the identifiers an instruction refers to, including those in
+    /// a block.
+    fn instr(&self, instr: &mut Instr) {
+        let (rename, id) = match instr {
+            Instr::Call(id) => (&self.funcs, id),
+            Instr::GlobalGet(id) | Instr::GlobalSet(id) => (&self.globals, id),
+            Instr::If(body) | Instr::Loop(body) => {
+                body.iter_mut().for_each(|instr| self.instr(instr));
+                return;
+            }
+            _ => return,
+        };
+        if let Some(renamed) = rename.get(id.as_str()) {
+            *id = renamed.clone();
+        }
+    }
+}
+
+/// Counts how many modules use each name.
+fn count<'a, F, I>(modules: &'a [Module], names: F) -> HashMap<&'a str, usize>
+where
+    F: Fn(&'a Module) -> I,
+    I: Iterator<Item = &'a String>,
+{
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for module in modules {
+        for name in names(module) {
+            *counts.entry(name.as_str()).or_default() += 1;
+        }
+    }
+    counts
+}
+
+/// The linked identifier of a module's definition, qualified by the module's
+/// name if more than one module uses the identifier.
+fn qualify(counts: &HashMap<&str, usize>, module: &Module, name: &str) -> String {
+    if counts[name] > 1 {
+        format!("{}.{}", module.name, name)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// The linked identifier of a function imported from outside the linked
+/// modules. The names can have any characters; those that can't be in an
+/// identifier are mangled when it's emitted.
+fn external_id(import: &Import) -> String {
+    format!("{}.{}", import.module, import.name)
+}
+
+/// Visiting state of a module while ordering modules.
+#[derive(Clone, PartialEq)]
+enum Visit {
+    New,
+    Active,
+    Done,
+}
+
+/// Orders modules so that each module comes after the modules it imports.
+fn import_order(modules: &[Module], index: &HashMap<&str, usize>) -> Result<Vec<usize>, LinkError> {
+    let mut visits = vec![Visit::New; modules.len()];
+    let mut path = Vec::new();
+    let mut order = Vec::new();
+    for i in 0..modules.len() {
+        visit(modules, index, i, &mut visits, &mut path, &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Places a module after the modules it imports, depth first.
+fn visit(
+    modules: &[Module],
+    index: &HashMap<&str, usize>,
+    i: usize,
+    visits: &mut [Visit],
+    path: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+) -> Result<(), LinkError> {
+    match visits[i] {
+        Visit::Done => return Ok(()),
+        Visit::Active => {
+            let start = path.iter().position(|&j| j == i).unwrap_or(0);
+            let cycle = path[start..]
+                .iter()
+                .chain([&i])
+                .map(|&j| modules[j].name.clone())
+                .collect();
+            return Err(LinkError::ImportCycle(cycle));
+        }
+        Visit::New => (),
+    }
+
+    visits[i] = Visit::Active;
+    path.push(i);
+    for import in modules[i].imports.iter() {
+        if let Some(&j) = index.get(import.module.as_str()) {
+            visit(modules, index, j, visits, path, order)?;
+        }
+    }
+    path.pop();
+    visits[i] = Visit::Done;
+    order.push(i);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::wat::builder::*;
+
+    fn module(name: &str) -> Module {
+        Module {
+            name: name.to_owned(),
+            imports: Vec::new(),
+            tables: Vec::new(),
+            memories: Vec::new(),
+            globals: Vec::new(),
+            funcs: Vec::new(),
+            exports: Vec::new(),
+            start: None,
+            data: Vec::new(),
+        }
+    }
+
+    fn import(module: &str, name: &str) -> Import {
+        Import {
+            module: module.to_owned(),
+            name: name.to_owned(),
+            id: format!("{module}.{name}"),
+            params: Vec::new(),
+            result: None,
+        }
+    }
+
+    fn func(name: &str, body: Vec<Instr>) -> Func {
+        BuilderFunc::new().set_name(name).set_body(body).build()
+    }
+
+    fn global(name: &str) -> Global {
+        Global {
+            name: name.to_owned(),
+            t: Type::I32,
+            mutable: true,
+            init: Instr::I32Const(0),
+        }
+    }
+
+    fn memory(name: &str) -> Memory {
+        Memory {
+            name: name.to_owned(),
+            min: 1,
+            max: None,
+        }
+    }
+
+    fn export_func(name: &str) -> Export {
+        Export {
+            name: name.to_owned(),
+            desc: ExportDesc::Func(name.to_owned()),
+        }
+    }
+
+    fn call(id: &str) -> Instr {
+        Instr::Call(id.to_owned())
+    }
+
+    fn func_names(module: &Module) -> Vec<&str> {
+        module.funcs.iter().map(|func| func.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_link_resolves_imports() -> Result<(), LinkError> {
+        let mut a = module("A");
+        a.funcs.push(func("F", vec![]));
+        a.exports.push(export_func("F"));
+        let mut b = module("B");
+        b.imports.push(import("A", "F"));
+        b.funcs.push(func("G", vec![call("A.F")]));
+
+        let linked = link("B", vec![a, b])?;
+        assert!(linked.imports.is_empty());
+        assert_eq!(func_names(&linked), vec!["F", "G"]);
+        assert_eq!(linked.funcs[1].body, vec![call("F")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_external_id() -> Result<(), LinkError> {
+        let mut a = module("A");
+        a.imports.push(import("my env", "log it"));
+        a.funcs.push(func("F", vec![call("my env.log it")]));
+        a.exports.push(export_func("F"));
+        let mut b = module("B");
+        b.imports.push(import("A", "F"));
+
+        let linked = link("B", vec![a, b])?;
+        assert_eq!(linked.imports[0].id, "my env.log it");
+        let code = crate::emit_module(&linked);
+        assert!(code.contains(r#"(import "my env" "log it" (func $my_u20_env.log_u20_it))"#));
+        assert!(code.contains("call $my_u20_env.log_u20_it"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_renames_clashes() -> Result<(), LinkError> {
+        let mut a = module("A");
+        a.funcs.push(func("P", vec![]));
+        a.funcs.push(func("Q", vec![call("P")]));
+        a.exports.push(export_func("P"));
+        let mut b = module("B");
+        b.imports.push(import("A", "P"));
+        b.funcs.push(func("P", vec![call("A.P"), call("P")]));
+
+        let linked = link("B", vec![a, b])?;
+        assert_eq!(func_names(&linked), vec!["A.P", "Q", "B.P"]);
+        assert_eq!(linked.funcs[1].body, vec![call("A.P")]);
+        assert_eq!(linked.funcs[2].body, vec![call("A.P"), call("B.P")]);
+        assert_eq!(linked.exports[0].name, "P");
+        assert_eq!(linked.exports[0].desc, ExportDesc::Func("A.P".to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_orders_by_imports() -> Result<(), LinkError> {
+        let mut a = module("A");
+        a.funcs.push(func("InitA", vec![]));
+        a.start = Some("InitA".to_owned());
+        let mut b = module("B");
+        b.imports.push(import("A", "InitA"));
+        b.funcs.push(func("InitB", vec![]));
+        b.start = Some("InitB".to_owned());
+        let mut c = module("C");
+        c.imports.push(import("B", "InitB"));
+
+        let linked = link("C", vec![c, b, a])?;
+        assert_eq!(func_names(&linked), vec!["InitA", "InitB", START_ID]);
+        assert_eq!(linked.funcs[2].body, vec![call("InitA"), call("InitB")]);
+        assert_eq!(linked.start, Some(START_ID.to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_merges_external_imports() -> Result<(), LinkError> {
+        let mut a = module("A");
+        a.imports.push(import("env", "log"));
+        a.funcs.push(func("P", vec![call("env.log")]));
+        let mut b = module("B");
+        b.imports.push(import("env", "log"));
+
+        let linked = link("B", vec![a, b])?;
+        assert_eq!(linked.imports, vec![import("env", "log")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_resolves_reexported_imports() -> Result<(), LinkError> {
+        let mut a = module("A");
+        a.imports.push(Import {
+            id: "Log".to_owned(),
+            ..import("env", "log")
+        });
+        let mut b = module("B");
+        b.imports.push(import("A", "Log"));
+        b.funcs.push(func("P", vec![call("A.Log")]));
+
+        let linked = link("B", vec![a, b])?;
+        assert_eq!(linked.imports, vec![import("env", "log")]);
+        assert_eq!(linked.funcs[0].body, vec![call("env.log")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_import_cycle() {
+        let mut a = module("A");
+        a.imports.push(import("B", "P"));
+        let mut b = module("B");
+        b.imports.push(import("C", "P"));
+        let mut c = module("C");
+        c.imports.push(import("A", "P"));
+
+        let cycle = ["A", "B", "C", "A"].map(str::to_owned).to_vec();
+        assert_eq!(
+            link("A", vec![a, b, c]).err(),
+            Some(LinkError::ImportCycle(cycle))
+        );
+    }
+
+    #[test]
+    fn test_link_undefined_import() {
+        let a = module("A");
+        let mut b = module("B");
+        b.imports.push(import("A", "P"));
+        assert_eq!(
+            link("B", vec![a, b]).err(),
+            Some(LinkError::UndefinedImport {
+                module: "A".to_owned(),
+                name: "P".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_link_renames_globals() -> Result<(), LinkError> {
+        let mut a = module("A");
+        a.globals.push(global("x"));
+        a.funcs
+            .push(func("F", vec![Instr::GlobalGet("x".to_owned())]));
+        a.exports.push(Export {
+            name: "x".to_owned(),
+            desc: ExportDesc::Global("x".to_owned()),
+        });
+        let mut b = module("B");
+        b.globals.push(global("x"));
+        b.funcs
+            .push(func("G", vec![Instr::GlobalSet("x".to_owned())]));
+        b.exports.push(Export {
+            name: "G".to_owned(),
+            desc: ExportDesc::Func("G".to_owned()),
+        });
+
+        let linked = link("B", vec![a, b])?;
+        assert_eq!(linked.globals, vec![global("A.x"), global("B.x")]);
+        assert_eq!(
+            linked.funcs[0].body,
+            vec![Instr::GlobalGet("A.x".to_owned())]
+        );
+        assert_eq!(
+            linked.funcs[1].body,
+            vec![Instr::GlobalSet("B.x".to_owned())]
+        );
+        assert_eq!(
+            linked.exports,
+            vec![
+                Export {
+                    name: "x".to_owned(),
+                    desc: ExportDesc::Global("A.x".to_owned()),
+                },
+                Export {
+                    name: "G".to_owned(),
+                    desc: ExportDesc::Func("G".to_owned()),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_duplicate_export() {
+        let mut a = module("A");
+        a.funcs.push(func("F", Vec::new()));
+        a.exports.push(Export {
+            name: "init".to_owned(),
+            desc: ExportDesc::Func("F".to_owned()),
+        });
+        let mut b = module("B");
+        b.globals.push(global("x"));
+        b.exports.push(Export {
+            name: "init".to_owned(),
+            desc: ExportDesc::Global("x".to_owned()),
+        });
+        assert_eq!(
+            link("B", vec![a, b]).err(),
+            Some(LinkError::DuplicateExport {
+                name: "init".to_owned(),
+                first: "A".to_owned(),
+                second: "B".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_link_merges_memories() -> Result<(), LinkError> {
+        let export_memory = Export {
+            name: "memory".to_owned(),
+            desc: ExportDesc::Memory("memory".to_owned()),
+        };
+        let mut a = module("A");
+        a.memories.push(memory("memory"));
+        a.exports.push(export_memory.clone());
+        let mut b = module("B");
+        b.memories.push(Memory {
+            min: 2,
+            ..memory("memory")
+        });
+        b.exports.push(export_memory.clone());
+
+        let linked = link("B", vec![a, b])?;
+        assert_eq!(
+            linked.memories,
+            vec![Memory {
+                min: 2,
+                ..memory("memory")
+            }]
+        );
+        assert_eq!(linked.exports, vec![export_memory]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_relocates_data() -> Result<(), LinkError> {
+        let data_module = |name, bytes: &[u8]| {
+            let mut m = module(name);
+            m.memories.push(memory("memory"));
+            m.globals.push(Global {
+                mutable: false,
+                init: Instr::I32Const(1024),
+                ..global(DATA_ID)
+            });
+            m.data.push(Data {
+                offset: 1024,
+                bytes: bytes.to_vec(),
+            });
+            m
+        };
+        let a = data_module("A", b"abc\0");
+        let b = data_module("B", &[7; PAGE_SIZE as usize]);
+
+        let linked = link("B", vec![a, b])?;
+        let inits: Vec<_> = linked
+            .globals
+            .iter()
+            .map(|g| (g.name.as_str(), &g.init))
+            .collect();
+        assert_eq!(
+            inits,
+            vec![
+                ("A._data", &Instr::I32Const(1024)),
+                ("B._data", &Instr::I32Const(1032))
+            ]
+        );
+        let offsets: Vec<_> = linked.data.iter().map(|d| d.offset).collect();
+        assert_eq!(offsets, vec![1024, 1032]);
+        assert_eq!(linked.memories[0].min, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_duplicate_module() {
+        assert_eq!(
+            link("A", vec![module("A"), module("A")]).err(),
+            Some(LinkError::DuplicateModule("A".to_owned()))
+        );
+    }
+}
@@ -1,55 +1,651 @@
-mod ast;
-mod compiler;
-mod emission;
-mod error;
-mod parser;
-mod scanner;
-mod table;
-mod types;
-
 use std::env;
+use std::fmt;
 use std::fs;
-use std::io::Write;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
 
-use crate::compiler::compile;
-use crate::emission::emit_module;
-use crate::parser::Parser;
+use titania::ast::{src, wat};
+use titania::compiler::{Options, compile};
+use titania::emission::emit_module;
+use titania::encoding::encode_module;
+use titania::error::Diagnostics;
+use titania::interpreter::{Instance, Trap, Value, with_call_stack};
+use titania::json;
+use titania::library::{self, START_EXPORT};
+use titania::parser::Parser;
+use titania::repl;
+use titania::scanner::{Scanner, TokenTag, decode_source};
+use titania::symbols::Symbols;
+use titania::table::Table;
+use titania::warnings::{CODES, Config, check};
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// The compilation stage whose output is written.
+#[derive(Clone, Copy, PartialEq)]
+enum Emit {
+    /// Each file's tokens, one per line.
+    Tokens,
+
+    /// Each file's syntax tree.
+    Ast,
+
+    /// The compiled module's syntax tree.
+    WatAst,
+
+    /// The compiled module in WebAssembly text format.
+    Wat,
+
+    /// The compiled module in WebAssembly binary format.
+    Wasm,
+
+    /// The compiled module in both formats, to `<Module>.wat` and
+    /// `<Module>.wasm`.
+    Both,
+}
+
+/// How errors and warnings are printed.
+#[derive(Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    /// Text with the source line, for people.
+    Human,
+
+    /// A JSON object per line, for tools, see `json`.
+    Json,
+}
+
+/// Errors or warnings already formatted for printing.
+#[derive(Debug)]
+struct Reported(String);
+
+impl fmt::Display for Reported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Reported {}
+
+/// A file that couldn't be read or written.
+#[derive(Debug)]
+struct FileError {
+    path: String,
+    error: io::Error,
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.path, self.error)
+    }
+}
+
+impl std::error::Error for FileError {}
+
+/// What the compiler is asked to do.
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// Compile the source files, writing the output of a stage.
+    Compile,
+
+    /// Compile the source files and run the module with the interpreter,
+    /// calling an exported function with the given arguments if asked.
+    Run {
+        call: Option<String>,
+        args: Vec<String>,
+    },
+
+    /// Read, evaluate and print entries interactively.
+    Repl,
+}
+
+/// The settings given on the command line.
+struct Settings {
+    command: Command,
+    options: Options,
+    emit: Emit,
+    warnings: Config,
+    format: ErrorFormat,
+
+    /// Where the output goes, `-` for standard output. By default the `wat`
+    /// and `wasm` stages are written to `<Module>.wat` and `<Module>.wasm`,
+    /// and the others to standard output.
+    output: Option<String>,
+}
+
+const USAGE: &str = "Usage: titania [options] path...
+       titania run [options] path... [--call NAME [ARG...]]
+       titania repl
 
-    if args.len() != 2 {
-        println!("Usage: titania path");
+Options:
+  -o PATH                        write the output to PATH, or standard output for -
+  --emit=tokens|ast|wat-ast|wat|wasm|both
+                                 the stage to output, wat by default
+  --export-init                  export the initialisation body as _init
+  --export-memory                define and export a memory
+  --deny-warnings                fail if there are any warnings
+  --allow=CODE,...               don't report the given warnings
+  --error-format=human|json      how errors and warnings are printed
+  --unicode-identifiers          allow Unicode letters in identifiers
+  -h, --help                     print this message
+
+Run options:
+  --call NAME [ARG...]           call the exported function NAME with the
+                                 arguments ARG, integers or reals such as
+                                 1.5, and print its result";
+
+/// The exit code for errors in the source files.
+const EXIT_ERROR: i32 = 1;
+
+/// The exit code for a bad command line.
+const EXIT_USAGE: i32 = 2;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
         return;
     }
 
-    match compile_file(&args[1]) {
-        Ok(_) => (),
-        Err(e) => eprintln!("error: {e}"),
+    let (settings, paths) = match parse_args(args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            process::exit(EXIT_USAGE);
+        }
+    };
+
+    let result = match settings.command {
+        Command::Compile => compile_files(&paths, &settings),
+        Command::Run { .. } => run_files(&paths, &settings),
+        Command::Repl => repl::run(io::stdin().lock(), &mut io::stdout()).map_err(Into::into),
+    };
+    if let Err(e) = result {
+        match (e.downcast_ref::<Reported>(), settings.format) {
+            (Some(reported), _) => eprintln!("{reported}"),
+            (None, ErrorFormat::Human) => eprintln!("error: {e}"),
+            (None, ErrorFormat::Json) => match e.downcast_ref::<FileError>() {
+                Some(file) => eprintln!(
+                    "{}",
+                    json::file_message("error", &e.to_string(), &file.path)
+                ),
+                None => eprintln!("{}", json::message("error", &e.to_string())),
+            },
+        }
+        process::exit(EXIT_ERROR);
+    }
+}
+
+/// Reads the settings and source file paths from the command line
+/// arguments, or describes what's wrong with them.
+fn parse_args(args: Vec<String>) -> Result<(Settings, Vec<String>), String> {
+    let mut settings = Settings {
+        command: Command::Compile,
+        options: Options::default(),
+        emit: Emit::Wat,
+        warnings: Config::default(),
+        format: ErrorFormat::Human,
+        output: None,
+    };
+    let mut paths = Vec::new();
+
+    let mut args = args.into_iter().peekable();
+    if args.next_if(|arg| arg == "run").is_some() {
+        settings.command = Command::Run {
+            call: None,
+            args: Vec::new(),
+        };
+    } else if args.next_if(|arg| arg == "repl").is_some() {
+        settings.command = Command::Repl;
+        if let Some(arg) = args.next() {
+            return Err(format!("repl doesn't take `{arg}`"));
+        }
+        return Ok((settings, paths));
+    }
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--call" => match (&mut settings.command, args.next()) {
+                (
+                    Command::Run {
+                        call,
+                        args: call_args,
+                    },
+                    Some(name),
+                ) => {
+                    *call = Some(name);
+                    // Everything after the name is an argument, even if it
+                    // looks like an option, e.g. `-1`.
+                    call_args.extend(args.by_ref());
+                }
+                (Command::Run { .. }, None) => return Err("--call needs a name".to_owned()),
+                (Command::Compile | Command::Repl, _) => {
+                    return Err("--call is only used with run".to_owned());
+                }
+            },
+            "-o" => match args.next() {
+                Some(output) => settings.output = Some(output),
+                None => return Err("-o needs a path".to_owned()),
+            },
+            "--emit=tokens" => settings.emit = Emit::Tokens,
+            "--emit=ast" => settings.emit = Emit::Ast,
+            "--emit=wat-ast" => settings.emit = Emit::WatAst,
+            "--emit=wat" => settings.emit = Emit::Wat,
+            "--emit=wasm" => settings.emit = Emit::Wasm,
+            "--emit=both" => settings.emit = Emit::Both,
+            "--export-init" => settings.options.export_init = true,
+            "--export-memory" => settings.options.export_memory = true,
+            "--deny-warnings" => settings.warnings.deny = true,
+            "--error-format=human" => settings.format = ErrorFormat::Human,
+            "--error-format=json" => settings.format = ErrorFormat::Json,
+            "--unicode-identifiers" => settings.options.unicode_identifiers = true,
+            _ if arg.starts_with("--allow=") => {
+                for code in arg["--allow=".len()..].split(',') {
+                    if !CODES.contains(&code) {
+                        return Err(format!("unknown warning code `{code}`"));
+                    }
+                    settings.warnings.allow.push(code.to_owned());
+                }
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option `{arg}`"));
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.is_empty() {
+        return Err("no source files given".to_owned());
+    }
+    if settings.emit == Emit::Both && settings.output.is_some() {
+        return Err("-o can't be used with --emit=both".to_owned());
+    }
+    Ok((settings, paths))
+}
+
+/// Compiles each file in order, so a module must come after the modules it
+/// imports, and writes the output of the stage asked for.
+fn compile_files(paths: &[String], settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    // The stages before compiling work on each file separately.
+    if let Emit::Tokens | Emit::Ast = settings.emit {
+        let mut text = String::new();
+        for path in paths {
+            let source = read_source(path, settings)?;
+            if settings.emit == Emit::Tokens {
+                text.push_str(&scan_tokens(path, &source, settings)?);
+            } else {
+                let module = parse(path, &source, settings)?;
+                text.push_str(&format!("{module:#?}\n"));
+            }
+        }
+        return write_output(settings, None, text.as_bytes());
+    }
+
+    let module = compile_and_link(paths, settings)?;
+    match settings.emit {
+        Emit::WatAst => write_output(settings, None, format!("{module:#?}\n").as_bytes()),
+        Emit::Wasm => {
+            let path = format!("{}.wasm", module.name);
+            write_output(settings, Some(path), &encode_module(&module))
+        }
+        Emit::Both => {
+            write_file(
+                &format!("{}.wat", module.name),
+                emit_module(&module).as_bytes(),
+            )?;
+            write_file(&format!("{}.wasm", module.name), &encode_module(&module))?;
+            Ok(())
+        }
+        _ => {
+            let path = format!("{}.wat", module.name);
+            write_output(settings, Some(path), emit_module(&module).as_bytes())
+        }
+    }
+}
+
+/// Compiles the files and runs the module, printing the result of the
+/// function called, if any.
+fn run_files(paths: &[String], settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    let Command::Run { call, args } = &settings.command else {
+        unreachable!("run_files is only used with run");
+    };
+    let args = args
+        .iter()
+        .map(|arg| match (arg.parse(), arg.parse()) {
+            (Ok(value), _) => Ok(Value::I32(value)),
+            (_, Ok(value)) if arg.contains('.') => Ok(Value::F64(value)),
+            _ => Err(format!("argument `{arg}` isn't a number")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let module = compile_and_link(paths, settings)?;
+    let result = with_call_stack(|| {
+        let mut instance = Instance::new(&module)?;
+        if module
+            .exports
+            .iter()
+            .any(|export| export.name == START_EXPORT)
+        {
+            instance.call(START_EXPORT, Vec::new())?;
+        }
+        match call {
+            Some(name) => instance.call(name, args),
+            None => Ok(None),
+        }
+    });
+    if let Some(result) = result.map_err(|trap: Trap| format!("trap: {trap}"))? {
+        println!("{result}");
+    }
+    Ok(())
+}
+
+/// Compiles each file in order. The modules are linked, with the library
+/// modules they import, into a single module named after the last module.
+fn compile_and_link(
+    paths: &[String],
+    settings: &Settings,
+) -> Result<wat::Module, Box<dyn std::error::Error>> {
+    let mut symbols = Table::new();
+    let mut modules = Vec::new();
+    for path in paths {
+        modules.push(compile_file(path, &mut symbols, settings)?);
+    }
+
+    Ok(library::link_program(modules)?)
+}
+
+/// Compiles a file, writing its symbol file unless it's being run, and
+/// adding its symbols to `symbols`. Imported modules not in `symbols` are
+/// library modules or are read from their symbol files.
+fn compile_file(
+    path: &str,
+    symbols: &mut Table<Symbols>,
+    settings: &Settings,
+) -> Result<wat::Module, Box<dyn std::error::Error>> {
+    let source = read_source(path, settings)?;
+    let module = parse(path, &source, settings)?;
+    report_warnings(path, &source, &module, settings)?;
+
+    let dir = symbols_dir(path, settings);
+    for import in module.imports.iter() {
+        if symbols.lookup(&import.name).is_some() {
+            continue;
+        }
+        let symbols_import = match library::symbols(&import.name) {
+            Some(symbols_import) => symbols_import,
+            None => {
+                let path = symbols_path(dir, &import.name);
+                let text = String::from_utf8_lossy(&read_file(&path)?).into_owned();
+                Symbols::parse(&text, &import.name)
+                    .map_err(|e| report(&path, &text, e.into(), settings))?
+            }
+        };
+        symbols.push(&import.name, symbols_import);
+    }
+
+    let (module, symbols_export) = compile(&module, symbols, &settings.options)
+        .map_err(|diagnostics| report(path, &source, diagnostics, settings))?;
+
+    // Running leaves nothing behind, so only compiling writes symbol files.
+    if settings.command == Command::Compile {
+        write_file(
+            &symbols_path(dir, &module.name),
+            symbols_export.to_string().as_bytes(),
+        )?;
     }
+    symbols.push(&module.name, symbols_export);
+
+    Ok(module)
 }
 
-fn compile_file(path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    println!("\n# SOURCE");
-    let source = fs::read_to_string(path)?;
-    println!("{source}");
+/// Reads a source file, which must be UTF-8.
+fn read_source(path: &str, settings: &Settings) -> Result<String, Box<dyn std::error::Error>> {
+    let bytes = read_file(path)?;
+    match decode_source(&bytes) {
+        Ok(source) => Ok(source.to_owned()),
+        Err(e) => {
+            let source = String::from_utf8_lossy(&bytes);
+            Err(report(path, &source, e.into(), settings).into())
+        }
+    }
+}
 
-    println!("\n# PARSED");
-    let mut parser = Parser::new(&source)?;
-    let module = parser.module()?;
-    println!("{:?}", module);
+/// Lists a source file's tokens, one per line with its position.
+fn scan_tokens(
+    path: &str,
+    source: &str,
+    settings: &Settings,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut scanner =
+        Scanner::new(source).unicode_identifiers(settings.options.unicode_identifiers);
+    let mut diagnostics = Diagnostics::new();
+    let mut text = String::new();
+    loop {
+        match scanner.next_token() {
+            Ok(token) => {
+                let (line, column) = (token.span.line, token.span.column);
+                text.push_str(&format!("{path}:{line}:{column} {}\n", token.tag));
+                if token.tag == TokenTag::Eof {
+                    break;
+                }
+            }
+            Err(e) => diagnostics.push(e),
+        }
+    }
 
-    println!("\n# COMPILED");
-    let module = compile(&module)?;
-    println!("{:?}", module);
+    if diagnostics.is_empty() {
+        Ok(text)
+    } else {
+        Err(report(path, source, diagnostics, settings).into())
+    }
+}
+
+/// Parses a source file.
+fn parse(
+    path: &str,
+    source: &str,
+    settings: &Settings,
+) -> Result<src::Module, Box<dyn std::error::Error>> {
+    let scanner = Scanner::new(source).unicode_identifiers(settings.options.unicode_identifiers);
+    Parser::with_scanner(scanner)
+        .module()
+        .map_err(|diagnostics| report(path, source, diagnostics, settings).into())
+}
 
-    println!("\n# EMISSION");
-    let code = emit_module(&module);
-    println!("{code}");
+/// Prints the warnings about a module that aren't allowed, and fails if
+/// warnings are denied.
+fn report_warnings(
+    path: &str,
+    source: &str,
+    module: &src::Module,
+    settings: &Settings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let warnings: Vec<_> = check(module)
+        .into_iter()
+        .filter(|warning| settings.warnings.is_reported(warning))
+        .collect();
+    for warning in warnings.iter() {
+        match settings.format {
+            ErrorFormat::Human => eprintln!("{}", warning.render(source)),
+            ErrorFormat::Json => eprintln!("{}", warning.to_json(path, source)),
+        }
+    }
+    if settings.warnings.deny && !warnings.is_empty() {
+        return Err(format!("{} warning(s) denied by --deny-warnings", warnings.len()).into());
+    }
+    Ok(())
+}
 
-    let wat_path = format!("{}.wat", module.name);
-    let mut file = fs::File::create(wat_path)?;
-    file.write_all(code.as_bytes())?;
+/// Formats a file's errors, showing where in the source they are.
+fn report(path: &str, source: &str, diagnostics: Diagnostics, settings: &Settings) -> Reported {
+    match settings.format {
+        ErrorFormat::Human => Reported(diagnostics.render(source)),
+        ErrorFormat::Json => Reported(diagnostics.to_json(path, source)),
+    }
+}
 
+/// Writes output to the path given with `-o`, or else the default path, or
+/// else standard output.
+fn write_output(
+    settings: &Settings,
+    default: Option<String>,
+    bytes: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    match settings.output.clone().or(default) {
+        Some(path) if path != "-" => write_file(&path, bytes)?,
+        _ => io::stdout().write_all(bytes)?,
+    }
     Ok(())
 }
+
+/// Reads a file, with its path in any error.
+fn read_file(path: &str) -> Result<Vec<u8>, FileError> {
+    fs::read(path).map_err(|error| FileError {
+        path: path.to_owned(),
+        error,
+    })
+}
+
+/// Writes a file, with its path in any error.
+fn write_file(path: &str, bytes: &[u8]) -> Result<(), FileError> {
+    fs::write(path, bytes).map_err(|error| FileError {
+        path: path.to_owned(),
+        error,
+    })
+}
+
+/// The directory of the symbol files for a source file: that of the output
+/// if it's given with `-o`, or else that of the source file.
+fn symbols_dir<'a>(path: &'a str, settings: &'a Settings) -> &'a Path {
+    let beside = match settings.output.as_deref() {
+        Some(output) if output != "-" => output,
+        _ => path,
+    };
+    Path::new(beside).parent().unwrap_or(Path::new(""))
+}
+
+/// The path of a module's symbol file in `dir`.
+fn symbols_path(dir: &Path, module_name: &str) -> String {
+    dir.join(format!("{module_name}.sym"))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let (settings, paths) =
+            parse_args(args(&["--emit=wat-ast", "-o", "-", "A.ta", "B.ta"])).unwrap();
+        assert!(settings.emit == Emit::WatAst);
+        assert_eq!(settings.output.as_deref(), Some("-"));
+        assert_eq!(paths, vec!["A.ta", "B.ta"]);
+    }
+
+    #[test]
+    fn test_parse_args_default() {
+        let (settings, _) = parse_args(args(&["A.ta"])).unwrap();
+        assert!(settings.emit == Emit::Wat);
+        assert_eq!(settings.output, None);
+    }
+
+    #[test]
+    fn test_parse_args_emit_both() {
+        let (settings, _) = parse_args(args(&["--emit=both", "A.ta"])).unwrap();
+        assert!(settings.emit == Emit::Both);
+    }
+
+    #[test]
+    fn test_parse_args_run() {
+        let (settings, paths) =
+            parse_args(args(&["run", "A.ta", "--call", "P", "-1", "-o"])).unwrap();
+        assert_eq!(
+            settings.command,
+            Command::Run {
+                call: Some("P".to_string()),
+                args: args(&["-1", "-o"]),
+            }
+        );
+        assert_eq!(paths, vec!["A.ta"]);
+    }
+
+    #[test]
+    fn test_symbols_path() {
+        let (settings, _) = parse_args(args(&["src/A.ta"])).unwrap();
+        let dir = symbols_dir("src/A.ta", &settings);
+        assert_eq!(symbols_path(dir, "A"), "src/A.sym");
+        assert_eq!(symbols_path(symbols_dir("A.ta", &settings), "A"), "A.sym");
+
+        let (settings, _) = parse_args(args(&["-o", "out/A.wasm", "src/A.ta"])).unwrap();
+        let dir = symbols_dir("src/A.ta", &settings);
+        assert_eq!(symbols_path(dir, "A"), "out/A.sym");
+
+        let (settings, _) = parse_args(args(&["-o", "-", "src/A.ta"])).unwrap();
+        let dir = symbols_dir("src/A.ta", &settings);
+        assert_eq!(symbols_path(dir, "A"), "src/A.sym");
+    }
+
+    #[test]
+    fn test_read_source_missing() {
+        let (settings, _) = parse_args(args(&["missing.ta"])).unwrap();
+        let e = read_source("missing.ta", &settings).unwrap_err();
+        assert_eq!(e.downcast_ref::<FileError>().unwrap().path, "missing.ta");
+        assert!(e.to_string().starts_with("`missing.ta`: "));
+    }
+
+    /// Creates an empty directory for a test's files.
+    fn test_dir(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("titania-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_compile_file_symbols_error() {
+        let dir = test_dir("symbols-error");
+        let path = format!("{dir}/M.ta");
+        fs::write(&path, "MODULE M; IMPORT A; END M.").unwrap();
+        let sym = format!("{dir}/A.sym");
+        fs::write(&sym, "MODULE B\n").unwrap();
+
+        let (settings, _) = parse_args(args(&["--error-format=json", &path])).unwrap();
+        let e = compile_file(&path, &mut Table::new(), &settings).unwrap_err();
+        let reported = e.downcast_ref::<Reported>().unwrap().to_string();
+        assert!(reported.contains("\"code\":\"E031\""), "{reported}");
+        assert!(reported.contains(&format!("\"file\":\"{sym}\"")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_compile_file_run_writes_no_symbols() {
+        let dir = test_dir("run-symbols");
+        let path = format!("{dir}/M.ta");
+        fs::write(&path, "MODULE M; PROCEDURE P*; END; END M.").unwrap();
+
+        let (settings, _) = parse_args(args(&["run", &path])).unwrap();
+        compile_file(&path, &mut Table::new(), &settings).unwrap();
+        assert!(!Path::new(&format!("{dir}/M.sym")).exists());
+
+        let (settings, _) = parse_args(args(&[&path])).unwrap();
+        compile_file(&path, &mut Table::new(), &settings).unwrap();
+        assert!(Path::new(&format!("{dir}/M.sym")).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert!(parse_args(args(&[])).is_err());
+        assert!(parse_args(args(&["A.ta", "-o"])).is_err());
+        assert!(parse_args(args(&["--emit=all", "A.ta"])).is_err());
+        assert!(parse_args(args(&["--emit=both", "-o", "A", "A.ta"])).is_err());
+        assert!(parse_args(args(&["--allow=W999", "A.ta"])).is_err());
+        assert!(parse_args(args(&["--call", "P", "A.ta"])).is_err());
+        assert!(parse_args(args(&["run", "A.ta", "--call"])).is_err());
+        assert!(parse_args(args(&["repl", "A.ta"])).is_err());
+    }
+}
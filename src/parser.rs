@@ -4,6 +4,7 @@ use crate::ast::src::builder::*;
 use crate::ast::src::*;
 use crate::error::*;
 use crate::scanner::*;
+use crate::span::Span;
 
 // Result type for parsing functions.
 pub type ResultParse<T> = Result<T, Error>;
@@ -15,18 +16,60 @@ pub struct Parser<'a> {
 
     /// Current token in the source code.
     current: Token,
+
+    /// The errors found so far.
+    diagnostics: Diagnostics,
+
+    /// The pragmas found so far.
+    pragmas: Vec<Pragma>,
 }
 
 impl<'a> Parser<'a> {
     /// Constructs a Parser for a source text.
-    pub fn new(source: &'a str) -> Result<Parser<'a>, Error> {
-        let mut scanner = Scanner::new(source);
-        let current = scanner.next_token()?;
-        Ok(Parser { scanner, current })
+    pub fn new(source: &'a str) -> Parser<'a> {
+        Self::with_scanner(Scanner::new(source))
+    }
+
+    /// Constructs a Parser reading tokens from a scanner, for scanners with
+    /// options set.
+    pub fn with_scanner(scanner: Scanner<'a>) -> Parser<'a> {
+        let mut parser = Parser {
+            scanner,
+            current: Token::new(TokenTag::Eof, Span::default()),
+            diagnostics: Diagnostics::new(),
+            pragmas: Vec::new(),
+        };
+        parser.advance();
+        parser
+    }
+
+    /// Parses a module, recovering from errors to report as many as
+    /// possible.
+    pub fn module(&mut self) -> Result<Module, Diagnostics> {
+        self.whole(Self::module_items)
+    }
+
+    /// Parses the whole source text with a parsing function, e.g.
+    /// `Parser::expr` for a lone expression, recovering from errors to report
+    /// as many as possible.
+    pub fn whole<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> ResultParse<T>,
+    ) -> Result<T, Diagnostics> {
+        let result = parse(self).and_then(|item| self.expect(TokenTag::Eof).map(|_| item));
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        match result {
+            Ok(item) if diagnostics.is_empty() => Ok(item),
+            Ok(_) => Err(diagnostics),
+            Err(e) => {
+                diagnostics.push(e);
+                Err(diagnostics)
+            }
+        }
     }
 
-    /// Parses a module.
-    pub fn module(&mut self) -> ResultParse<Module> {
+    /// Parses the parts of a module.
+    fn module_items(&mut self) -> ResultParse<Module> {
         let mut builder_module = BuilderModule::new();
 
         // "module"
@@ -39,26 +82,163 @@ impl<'a> Parser<'a> {
         // ";"
         self.expect(TokenTag::Semicolon)?;
 
-        // { Decl }
-        while let Some(decl) = self.decl()? {
+        // [ ImportList ]
+        if self.is_match(TokenTag::Import)
+            && let Err(e) = self.import_list(&mut builder_module)
+        {
+            self.recover(e)?;
+            self.is_match(TokenTag::Semicolon);
+        }
+
+        // DeclSeq
+        for decl in self.decl_seq()? {
             builder_module.add_decl(decl);
         }
 
+        // ["begin" StmtSeq]
+        if self.is_match(TokenTag::Begin) {
+            for stmt in self.stmt_seq()? {
+                builder_module.add_stmt(stmt);
+            }
+        }
+
         // "end"
         self.expect(TokenTag::End)?;
 
+        // [Id]
+        if let TokenTag::Identifier(_) = self.current.tag {
+            let (name_end, span) = self.expect_identifier()?;
+            if name_end != name {
+                let tag = ErrorTag::ModuleNameMismatch {
+                    expected: name,
+                    got: name_end,
+                };
+                return Err(Error::new(tag, span));
+            }
+        }
+
         // "."
         self.expect(TokenTag::Dot)?;
 
-        // EOF
-        self.expect(TokenTag::Eof)?;
+        for pragma in std::mem::take(&mut self.pragmas) {
+            builder_module.add_pragma(pragma);
+        }
 
         Ok(builder_module.build())
     }
 
+    /// Parses an import list.
+    fn import_list(&mut self, builder_module: &mut BuilderModule) -> ResultParse<()> {
+        // "import" was previous token.
+
+        loop {
+            // Id [":=" Id]
+            let (alias, span) = self.expect_identifier()?;
+            let name = if self.is_match(TokenTag::Becomes) {
+                let (name, _) = self.expect_identifier()?;
+                name
+            } else {
+                alias.clone()
+            };
+            builder_module.add_import(&alias, &name, span);
+
+            // { "," Import }
+            if !self.is_match(TokenTag::Comma) {
+                break;
+            }
+        }
+
+        // ";"
+        self.expect(TokenTag::Semicolon)
+    }
+
+    /// Parses a declaration sequence.
+    pub fn decl_seq(&mut self) -> ResultParse<Vec<Decl>> {
+        let mut decls = Vec::new();
+
+        loop {
+            if self.is_match(TokenTag::Var) {
+                // "var" { VarDecl ";" }
+                decls.extend(self.var_section()?.into_iter().map(Decl::Var));
+            } else if self.current.tag == TokenTag::Procedure {
+                // Decl
+                match self.decl() {
+                    Ok(Some(decl)) => decls.push(decl),
+                    Ok(None) => (),
+                    Err(e) => {
+                        self.recover(e)?;
+                        self.is_match(TokenTag::Semicolon);
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+
+        Ok(decls)
+    }
+
+    /// Parses the variable declarations following `VAR`.
+    fn var_section(&mut self) -> ResultParse<Vec<VarDecl>> {
+        // "var" was previous token.
+
+        // { VarDecl ";" }
+        let mut vars = Vec::new();
+        while let TokenTag::Identifier(_) = self.current.tag {
+            let result = self
+                .var_decl(&mut vars)
+                .and_then(|_| self.expect(TokenTag::Semicolon));
+            if let Err(e) = result {
+                self.recover(e)?;
+                self.is_match(TokenTag::Semicolon);
+            }
+        }
+
+        Ok(vars)
+    }
+
+    /// Parses a variable declaration, which may declare several variables.
+    fn var_decl(&mut self, vars: &mut Vec<VarDecl>) -> ResultParse<()> {
+        // IdentDef { "," IdentDef }
+        let mut names = Vec::new();
+        loop {
+            let (name, span) = self.expect_identifier()?;
+            let export = self.is_match(TokenTag::Star);
+            names.push((name, span, export));
+            if !self.is_match(TokenTag::Comma) {
+                break;
+            }
+        }
+
+        // ":" Type
+        self.expect(TokenTag::Colon)?;
+        let len = if self.is_match(TokenTag::Array) {
+            // "ARRAY" Integer "OF"
+            let len = self.expect_integer()?;
+            self.expect(TokenTag::Of)?;
+            Some(len)
+        } else {
+            None
+        };
+        let (tid, _) = self.expect_identifier()?;
+
+        for (name, span, export) in names {
+            let tid = tid.clone();
+            vars.push(VarDecl {
+                name,
+                span,
+                export,
+                tid,
+                len,
+            });
+        }
+
+        Ok(())
+    }
+
     /// Parses a declaration.
     pub fn decl(&mut self) -> ResultParse<Option<Decl>> {
-        let decl = if self.is_match(TokenTag::Procedure)? {
+        let decl = if self.is_match(TokenTag::Procedure) {
             // Proc
             let proc = self.proc()?;
 
@@ -79,18 +259,30 @@ impl<'a> Parser<'a> {
 
         // "procedure" was previous token.
 
-        // Id ["*"]
-        let (name, line) = self.expect_identifier()?;
-        let export = self.is_match(TokenTag::Star)?;
-        builder.set_name(&name, line).set_export(export);
+        // ProcHeading
+        match self.proc_heading(&mut builder) {
+            // External procedures have no body.
+            Ok(true) => return Ok(builder.build()),
+            Ok(false) => self.expect(TokenTag::Semicolon)?,
+            Err(e) => {
+                self.recover(e)?;
+                self.is_match(TokenTag::Semicolon);
+            }
+        }
 
-        if self.is_match(TokenTag::Colon)? {
-            let (name, _) = self.expect_identifier()?;
-            builder.set_tid_return(&name);
+        // { "var" { VarDecl ";" } }
+        while self.is_match(TokenTag::Var) {
+            for local in self.var_section()? {
+                builder.add_local(local);
+            }
         }
 
-        // ";"
-        self.expect(TokenTag::Semicolon)?;
+        // ["begin" StmtSeq]
+        if self.is_match(TokenTag::Begin) {
+            for stmt in self.stmt_seq()? {
+                builder.add_stmt(stmt);
+            }
+        }
 
         // "end"
         self.expect(TokenTag::End)?;
@@ -98,10 +290,265 @@ impl<'a> Parser<'a> {
         Ok(builder.build())
     }
 
+    /// Parses a procedure's attributes and heading.
+    /// Returns whether the procedure is external.
+    fn proc_heading(&mut self, builder: &mut BuilderProc) -> ResultParse<bool> {
+        // ["[" Attribute { ";" Attribute } "]"]
+        let mut is_external = false;
+        if self.is_match(TokenTag::LBracket) {
+            loop {
+                is_external |= self.attribute(builder)?;
+                if !self.is_match(TokenTag::Semicolon) {
+                    break;
+                }
+            }
+            self.expect(TokenTag::RBracket)?;
+        }
+
+        // Id ["*"]
+        let (name, span) = self.expect_identifier()?;
+        let export = self.is_match(TokenTag::Star);
+        builder.set_name(&name, span).set_export(export);
+
+        // [FormalParams]
+        if self.is_match(TokenTag::LParen) {
+            self.formal_params(builder)?;
+        }
+
+        // [":" Id]
+        if self.is_match(TokenTag::Colon) {
+            let (name, _) = self.expect_identifier()?;
+            builder.set_tid_return(&name);
+        }
+
+        Ok(is_external)
+    }
+
+    /// Parses a procedure attribute.
+    /// Returns whether the attribute makes the procedure external.
+    fn attribute(&mut self, builder: &mut BuilderProc) -> ResultParse<bool> {
+        let (name, span) = self.expect_identifier()?;
+        match name.as_str() {
+            "EXPORT" => {
+                // "export" String
+                let export_name = self.expect_string()?;
+                builder.set_export_name(&export_name);
+                Ok(false)
+            }
+            "EXTERNAL" => {
+                // "external" String "," String
+                let module = self.expect_string()?;
+                self.expect(TokenTag::Comma)?;
+                let name = self.expect_string()?;
+                builder.set_external(&module, &name);
+                Ok(true)
+            }
+            _ => Err(Error::new(ErrorTag::UnknownAttribute(name), span)),
+        }
+    }
+
+    /// Parses formal parameters.
+    fn formal_params(&mut self, builder: &mut BuilderProc) -> ResultParse<()> {
+        // "(" was previous token.
+
+        // [FPSection { ";" FPSection }]
+        if let TokenTag::Identifier(_) = self.current.tag {
+            loop {
+                self.fp_section(builder)?;
+                if !self.is_match(TokenTag::Semicolon) {
+                    break;
+                }
+            }
+        }
+
+        // ")"
+        self.expect(TokenTag::RParen)
+    }
+
+    /// Parses a section of formal parameters sharing a type.
+    fn fp_section(&mut self, builder: &mut BuilderProc) -> ResultParse<()> {
+        // Id { "," Id }
+        let mut names = vec![self.expect_identifier()?];
+        while self.is_match(TokenTag::Comma) {
+            names.push(self.expect_identifier()?);
+        }
+
+        // ":" Id
+        self.expect(TokenTag::Colon)?;
+        let (tid, _) = self.expect_identifier()?;
+
+        for (name, span) in names {
+            builder.add_param(&name, &tid, span);
+        }
+
+        Ok(())
+    }
+
+    /// Parses a statement sequence.
+    pub fn stmt_seq(&mut self) -> ResultParse<Vec<Stmt>> {
+        let mut stmts = Vec::new();
+
+        // Stmt { ";" Stmt }
+        loop {
+            match self.stmt() {
+                Ok(Some(stmt)) => stmts.push(stmt),
+                Ok(None) => (),
+                Err(e) => self.recover(e)?,
+            }
+            if self.is_match(TokenTag::Semicolon) {
+                continue;
+            }
+            if let TokenTag::End | TokenTag::Procedure | TokenTag::Eof = self.current.tag {
+                break;
+            }
+
+            // The statement didn't end where expected, e.g. a missing ";".
+            let tag = ErrorTag::ExpectedToken {
+                expected: TokenTag::Semicolon,
+                got: self.current.tag.clone(),
+            };
+            self.recover(self.error_current(tag))?;
+            if !self.is_match(TokenTag::Semicolon) {
+                break;
+            }
+        }
+
+        Ok(stmts)
+    }
+
+    /// Parses a statement, which may be empty.
+    pub fn stmt(&mut self) -> ResultParse<Option<Stmt>> {
+        let span = self.current.span;
+        let stmt = if self.is_match(TokenTag::Return) {
+            // "return" [Expr]
+            let expr = if self.is_expr_start() {
+                Some(self.expr()?)
+            } else {
+                None
+            };
+            Some(Stmt::Return(Return { expr, span }))
+        } else if let TokenTag::Identifier(_) = self.current.tag {
+            let (name, span) = self.qualident()?;
+            if self.is_match(TokenTag::Becomes) {
+                // QualIdent ":=" Expr
+                let var = Var { name, span };
+                let expr = self.expr()?;
+                Some(Stmt::Assign(Assign { var, expr }))
+            } else {
+                // QualIdent [ActualParams]
+                let args = if self.is_match(TokenTag::LParen) {
+                    self.actual_params()?
+                } else {
+                    Vec::new()
+                };
+                Some(Stmt::Call(Call {
+                    proc: name,
+                    args,
+                    span,
+                }))
+            }
+        } else {
+            None
+        };
+
+        Ok(stmt)
+    }
+
+    /// Parses an expression.
+    pub fn expr(&mut self) -> ResultParse<Expr> {
+        let span = self.current.span;
+        match self.current.tag {
+            TokenTag::Integer(value) => {
+                // Integer
+                self.advance();
+                return Ok(Expr::Integer(Integer { value, span }));
+            }
+            TokenTag::Real(value) => {
+                // Real
+                self.advance();
+                return Ok(Expr::Real(Real { value, span }));
+            }
+            TokenTag::Char(value) => {
+                // Character
+                self.advance();
+                return Ok(Expr::Char(Char { value, span }));
+            }
+            TokenTag::Str(_) => {
+                // String
+                let value = self.expect_string()?;
+                return Ok(Expr::Str(Str { value, span }));
+            }
+            _ => (),
+        }
+
+        // QualIdent [ActualParams]
+        let (name, span) = self.qualident()?;
+        let expr = if self.is_match(TokenTag::LParen) {
+            let args = self.actual_params()?;
+            Expr::Call(Call {
+                proc: name,
+                args,
+                span,
+            })
+        } else {
+            Expr::Var(Var { name, span })
+        };
+        Ok(expr)
+    }
+
+    /// Parses actual parameters.
+    fn actual_params(&mut self) -> ResultParse<Vec<Expr>> {
+        // "(" was previous token.
+
+        // [Expr { "," Expr }]
+        let mut args = Vec::new();
+        if self.is_expr_start() {
+            loop {
+                args.push(self.expr()?);
+                if !self.is_match(TokenTag::Comma) {
+                    break;
+                }
+            }
+        }
+
+        // ")"
+        self.expect(TokenTag::RParen)?;
+
+        Ok(args)
+    }
+
+    /// Parses a possibly qualified identifier, returning it and its span.
+    fn qualident(&mut self) -> ResultParse<(QualIdent, Span)> {
+        // [Id "."] Id
+        let (name, span) = self.expect_identifier()?;
+        if self.is_match(TokenTag::Dot) {
+            let (member, span_member) = self.expect_identifier()?;
+            let qualident = QualIdent {
+                module: Some(name),
+                name: member,
+            };
+            Ok((qualident, span.join(span_member)))
+        } else {
+            Ok((QualIdent { module: None, name }, span))
+        }
+    }
+
+    /// Determines if the current token can start an expression.
+    fn is_expr_start(&self) -> bool {
+        matches!(
+            self.current.tag,
+            TokenTag::Integer(_)
+                | TokenTag::Real(_)
+                | TokenTag::Char(_)
+                | TokenTag::Str(_)
+                | TokenTag::Identifier(_)
+        )
+    }
+
     /// Make sure the current token has the given tag, or else generate an error.
     fn expect(&mut self, expected: TokenTag) -> ResultParse<()> {
         if self.current.tag == expected {
-            self.advance()?;
+            self.advance();
             Ok(())
         } else {
             self.err_current(ErrorTag::ExpectedToken {
@@ -112,17 +559,17 @@ impl<'a> Parser<'a> {
     }
 
     /// If the current token is an identifier, return the identifier name and
-    /// line number. Otherwise, return an error.
-    fn expect_identifier(&mut self) -> ResultParse<(String, usize)> {
+    /// span. Otherwise, return an error.
+    fn expect_identifier(&mut self) -> ResultParse<(String, Span)> {
         match &self.current {
             Token {
                 tag: TokenTag::Identifier(name),
-                line,
+                span,
             } => {
                 let name = name.clone();
-                let line = *line;
-                self.advance()?;
-                Ok((name, line))
+                let span = *span;
+                self.advance();
+                Ok((name, span))
             }
             _ => self.err_current(ErrorTag::ExpectedIdentifier {
                 got: self.current.tag.clone(),
@@ -130,26 +577,116 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// If the current token is a string, return the string. Otherwise,
+    /// return an error.
+    fn expect_string(&mut self) -> ResultParse<String> {
+        match &self.current.tag {
+            TokenTag::Str(s) => {
+                let s = s.clone();
+                self.advance();
+                Ok(s)
+            }
+            _ => self.err_current(ErrorTag::ExpectedToken {
+                expected: TokenTag::Str(String::new()),
+                got: self.current.tag.clone(),
+            }),
+        }
+    }
+
+    /// If the current token is an integer, return its value. Otherwise,
+    /// return an error.
+    fn expect_integer(&mut self) -> ResultParse<i64> {
+        match self.current.tag {
+            TokenTag::Integer(value) => {
+                self.advance();
+                Ok(value)
+            }
+            _ => self.err_current(ErrorTag::ExpectedToken {
+                expected: TokenTag::Integer(0),
+                got: self.current.tag.clone(),
+            }),
+        }
+    }
+
     /// If the current token matches the given tag, advance and return true.
     /// Otherwise, do nothing and return false.
-    fn is_match(&mut self, tag: TokenTag) -> ResultParse<bool> {
+    fn is_match(&mut self, tag: TokenTag) -> bool {
         if self.current.tag == tag {
-            self.advance()?;
-            Ok(true)
+            self.advance();
+            true
         } else {
-            Ok(false)
+            false
         }
     }
 
-    /// Sets current token to the next token in the source text.
-    fn advance(&mut self) -> ResultParse<()> {
-        self.current = self.scanner.next_token()?;
-        Ok(())
+    /// Sets current token to the next token in the source text, recording
+    /// and skipping any invalid text and pragmas, which can be anywhere.
+    fn advance(&mut self) {
+        loop {
+            match self.scanner.next_token() {
+                Ok(Token {
+                    tag: TokenTag::Pragma(text),
+                    span,
+                }) => self.pragmas.push(Pragma { text, span }),
+                Ok(token) => {
+                    self.current = token;
+                    return;
+                }
+                Err(e) => {
+                    // A bad literal still stands for a value, so it doesn't
+                    // also cause a syntax error.
+                    let literal = matches!(
+                        e.tag,
+                        ErrorTag::CharacterOutOfRange(_)
+                            | ErrorTag::IntegerOutOfRange(_)
+                            | ErrorTag::InvalidNumber(_)
+                            | ErrorTag::MissingScaleFactor(_)
+                            | ErrorTag::RealOutOfRange(_)
+                    );
+                    let span = e.span;
+                    self.diagnostics.push(e);
+                    if literal {
+                        self.current = Token::new(TokenTag::Integer(0), span);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records an error and skips to a token that can follow a statement or
+    /// declaration: `;`, `END`, `PROCEDURE` or the end of the source text.
+    /// Gives up, returning the error, once there are too many errors.
+    fn recover(&mut self, error: Error) -> ResultParse<()> {
+        if self.diagnostics.is_full() {
+            return Err(error);
+        }
+        self.diagnostics.push(error);
+
+        loop {
+            match self.current.tag {
+                TokenTag::Semicolon | TokenTag::End | TokenTag::Procedure | TokenTag::Eof => {
+                    return Ok(());
+                }
+                _ => self.advance(),
+            }
+        }
     }
 
     /// Creates an error result for the current token.
     fn err_current<T>(&self, tag: ErrorTag) -> ResultParse<T> {
-        Err(Error::new(tag, self.current.line))
+        Err(self.error_current(tag))
+    }
+
+    /// Creates an error for the current token, suggesting the keyword it
+    /// spells if it's an identifier that only differs from one by case.
+    fn error_current(&self, tag: ErrorTag) -> Error {
+        let help = match &self.current.tag {
+            TokenTag::Identifier(name) => keyword_ignoring_case(name)
+                .map(|keyword| format!("keywords are upper case, did you mean `{keyword}`?")),
+            _ => None,
+        };
+        Error::new(tag, self.current.span).with_help(help)
     }
 }
 
@@ -159,7 +696,7 @@ mod tests {
 
     #[test]
     fn test_empty_module() -> ResultParse<()> {
-        let mut parser = Parser::new("MODULE M; END.")?;
+        let mut parser = Parser::new("MODULE M; END.");
         let module = parser.module()?;
         assert_eq!(module.name, "M");
         assert_eq!(module.decls.len(), 0);
@@ -169,19 +706,41 @@ mod tests {
 
     #[test]
     fn test_module_procedure() -> ResultParse<()> {
-        let mut parser = Parser::new("MODULE M; PROCEDURE P; END; END.")?;
+        let mut parser = Parser::new("MODULE M; PROCEDURE P; END; END.");
         let module = parser.module()?;
         assert_eq!(module.decls.len(), 1);
         assert!(is_at_eof(&parser));
         Ok(())
     }
 
+    #[test]
+    fn test_module_body() -> ResultParse<()> {
+        let mut parser = Parser::new("MODULE M; BEGIN P; Q END M.");
+        let module = parser.module()?;
+        assert_eq!(module.body.len(), 2);
+        assert!(is_at_eof(&parser));
+        Ok(())
+    }
+
+    #[test]
+    fn test_module_name_mismatch() -> ResultParse<()> {
+        let mut parser = Parser::new("MODULE M;\nEND N.");
+        match parser.module().map_err(Error::from) {
+            Err(Error {
+                tag: ErrorTag::ModuleNameMismatch { expected, got },
+                span: Span { line: 2, .. },
+                ..
+            }) if expected == "M" && got == "N" => Ok(()),
+            _ => panic!("Expected a module name mismatch error."),
+        }
+    }
+
     #[test]
     fn test_procedure_empty() -> ResultParse<()> {
-        let mut parser = Parser::new("P; END")?;
+        let mut parser = Parser::new("P; END");
         let decl_proc = parser.proc()?;
         assert_eq!(decl_proc.name, "P");
-        assert_eq!(decl_proc.export, false);
+        assert!(!decl_proc.export);
         assert_eq!(decl_proc.tid_return, None);
         assert!(is_at_eof(&parser));
         Ok(())
@@ -189,10 +748,10 @@ mod tests {
 
     #[test]
     fn test_procedure_export() -> ResultParse<()> {
-        let mut parser = Parser::new("P*; END")?;
+        let mut parser = Parser::new("P*; END");
         let decl_proc = parser.proc()?;
         assert_eq!(decl_proc.name, "P");
-        assert_eq!(decl_proc.export, true);
+        assert!(decl_proc.export);
         assert_eq!(decl_proc.tid_return, None);
         assert!(is_at_eof(&parser));
         Ok(())
@@ -200,15 +759,301 @@ mod tests {
 
     #[test]
     fn test_procedure_integer_return() -> ResultParse<()> {
-        let mut parser = Parser::new("P*: INTEGER; END")?;
+        let mut parser = Parser::new("P*: INTEGER; END");
         let decl_proc = parser.proc()?;
         assert_eq!(decl_proc.name, "P");
-        assert_eq!(decl_proc.export, true);
+        assert!(decl_proc.export);
+        assert_eq!(decl_proc.tid_return, Some("INTEGER".to_owned()));
+        assert!(is_at_eof(&parser));
+        Ok(())
+    }
+
+    #[test]
+    fn test_module_imports() -> ResultParse<()> {
+        let mut parser = Parser::new("MODULE M; IMPORT Out, A := Maths; END.");
+        let module = parser.module()?;
+        assert_eq!(module.imports.len(), 2);
+        assert_eq!(module.imports[0].alias, "Out");
+        assert_eq!(module.imports[0].name, "Out");
+        assert_eq!(module.imports[1].alias, "A");
+        assert_eq!(module.imports[1].name, "Maths");
+        assert!(is_at_eof(&parser));
+        Ok(())
+    }
+
+    #[test]
+    fn test_procedure_body() -> ResultParse<()> {
+        let mut parser = Parser::new("P: INTEGER; BEGIN Q; M.R(); RETURN M.S() END");
+        let decl_proc = parser.proc()?;
+        assert_eq!(decl_proc.body.len(), 3);
+        match &decl_proc.body[1] {
+            Stmt::Call(call) => {
+                assert_eq!(call.proc.module, Some("M".to_owned()));
+                assert_eq!(call.proc.name, "R");
+            }
+            _ => panic!("Expected a call statement."),
+        }
+        match &decl_proc.body[2] {
+            Stmt::Return(Return {
+                expr: Some(Expr::Call(call)),
+                ..
+            }) => assert_eq!(call.proc.name, "S"),
+            _ => panic!("Expected a return statement."),
+        }
+        assert!(is_at_eof(&parser));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stmt_seq_empty_stmts() -> ResultParse<()> {
+        let mut parser = Parser::new("; RETURN 1;");
+        let stmts = parser.stmt_seq()?;
+        assert_eq!(stmts.len(), 1);
+        assert!(is_at_eof(&parser));
+        Ok(())
+    }
+
+    #[test]
+    fn test_procedure_params() -> ResultParse<()> {
+        let mut parser = Parser::new("P(x, y: INTEGER; z: INTEGER): INTEGER; END");
+        let decl_proc = parser.proc()?;
+        let names: Vec<&str> = decl_proc.params.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["x", "y", "z"]);
+        assert!(decl_proc.params.iter().all(|p| p.tid == "INTEGER"));
         assert_eq!(decl_proc.tid_return, Some("INTEGER".to_owned()));
         assert!(is_at_eof(&parser));
         Ok(())
     }
 
+    #[test]
+    fn test_procedure_external() -> ResultParse<()> {
+        let mut parser = Parser::new("[EXTERNAL \"env\", \"log\"] Log(x: INTEGER);");
+        let decl_proc = parser.proc()?;
+        assert_eq!(decl_proc.name, "Log");
+        assert_eq!(
+            decl_proc.external,
+            Some(External {
+                module: "env".to_owned(),
+                name: "log".to_owned(),
+            })
+        );
+        assert_eq!(decl_proc.params.len(), 1);
+        assert_eq!(parser.current.tag, TokenTag::Semicolon);
+        Ok(())
+    }
+
+    #[test]
+    fn test_procedure_unknown_attribute() -> ResultParse<()> {
+        let mut parser =
+            Parser::new("MODULE M; PROCEDURE [INTERNAL \"env\", \"log\"] Log; END; END M.");
+        assert_eq!(
+            error_tag(parser.module()),
+            Some(ErrorTag::UnknownAttribute("INTERNAL".to_owned()))
+        );
+        Ok(())
+    }
+
+    /// The tags and lines of a module's errors.
+    fn module_errors(source: &str) -> Vec<(ErrorTag, usize)> {
+        match Parser::new(source).module() {
+            Ok(_) => Vec::new(),
+            Err(diagnostics) => diagnostics
+                .errors()
+                .iter()
+                .map(|e| (e.tag.clone(), e.span.line))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_keyword_case_help() {
+        let Err(diagnostics) = Parser::new("MODULE M;\nprocedure P;\nEND M.").module() else {
+            panic!("Expected an error.");
+        };
+        let error = &diagnostics.errors()[0];
+        assert_eq!(error.span.line, 2);
+        assert_eq!(
+            error.help.as_deref(),
+            Some("keywords are upper case, did you mean `PROCEDURE`?")
+        );
+    }
+
+    #[test]
+    fn test_pragmas() -> ResultParse<()> {
+        let mut parser =
+            Parser::new("(*$A*) MODULE M;\nBEGIN (*$B+*)\n  P (* (*$C*) *)\nEND M. (*$D*)");
+        let module = parser.module()?;
+        let pragmas: Vec<_> = module
+            .pragmas
+            .iter()
+            .map(|p| (p.text.as_str(), p.span.line))
+            .collect();
+        assert_eq!(pragmas, vec![("A", 1), ("B+", 2), ("D", 4)]);
+        assert_eq!(module.body.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_literal() {
+        let source = "MODULE M;\nBEGIN\n  x := 1AB;\n  y := 100X;\n  z := 1.0E\nEND M.";
+        assert_eq!(
+            module_errors(source),
+            vec![
+                (ErrorTag::InvalidNumber("1AB".to_owned()), 3),
+                (ErrorTag::CharacterOutOfRange("100X".to_owned()), 4),
+                (ErrorTag::MissingScaleFactor("1.0E".to_owned()), 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recover_stmts() {
+        let source = "MODULE M;\nPROCEDURE P;\nBEGIN\n  x := ;\n  Q;\n  y 1;\n  R\nEND;\nEND M.";
+        let errors = module_errors(source);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            errors[0],
+            (ErrorTag::ExpectedIdentifier { .. }, 4)
+        ));
+        assert!(matches!(errors[1], (ErrorTag::ExpectedToken { .. }, 6)));
+    }
+
+    #[test]
+    fn test_recover_decls() {
+        let source = "MODULE M;\nVAR x INTEGER;\nPROCEDURE P(a INTEGER);\nBEGIN\n  Q := \nEND;\nPROCEDURE R#;\nEND;\nEND M.";
+        let errors = module_errors(source);
+        let lines: Vec<usize> = errors.iter().map(|(_, line)| *line).collect();
+        assert_eq!(lines, vec![2, 3, 6, 7]);
+        assert_eq!(errors[3].0, ErrorTag::UnexpectedCharacter('#'));
+    }
+
+    #[test]
+    fn test_recover_cap() {
+        let mut source = String::from("MODULE M;\nBEGIN\n");
+        for _ in 0..MAX_ERRORS * 2 {
+            source.push_str("  x := ;\n");
+        }
+        source.push_str("END M.");
+        assert_eq!(module_errors(&source).len(), MAX_ERRORS);
+    }
+
+    #[test]
+    fn test_procedure_attributes() -> ResultParse<()> {
+        let mut parser =
+            Parser::new("[EXTERNAL \"env\", \"log\"; EXPORT \"log\"] Log*(x: INTEGER);");
+        let decl_proc = parser.proc()?;
+        assert!(decl_proc.external.is_some());
+        assert_eq!(decl_proc.export_name, Some("log".to_owned()));
+        assert_eq!(parser.current.tag, TokenTag::Semicolon);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decl_seq_vars() -> ResultParse<()> {
+        let mut parser = Parser::new("VAR x*, y: INTEGER; z: INTEGER; PROCEDURE P; END;");
+        let decls = parser.decl_seq()?;
+        assert_eq!(decls.len(), 4);
+        match &decls[0] {
+            Decl::Var(var) => {
+                assert_eq!(var.name, "x");
+                assert!(var.export);
+                assert_eq!(var.tid, "INTEGER");
+            }
+            _ => panic!("Expected a variable declaration."),
+        }
+        assert!(matches!(&decls[1], Decl::Var(var) if !var.export));
+        assert!(matches!(&decls[3], Decl::Proc(_)));
+        assert!(is_at_eof(&parser));
+        Ok(())
+    }
+
+    #[test]
+    fn test_decl_seq_array() -> ResultParse<()> {
+        let mut parser = Parser::new("VAR s: ARRAY 8 OF CHAR; c: CHAR;");
+        let decls = parser.decl_seq()?;
+        assert!(matches!(&decls[0], Decl::Var(var) if var.tid == "CHAR" && var.len == Some(8)));
+        assert!(matches!(&decls[1], Decl::Var(var) if var.tid == "CHAR" && var.len.is_none()));
+        assert!(is_at_eof(&parser));
+
+        let errors = module_errors("MODULE M;\nVAR s: ARRAY OF CHAR;\nEND M.");
+        assert!(matches!(errors[..], [(ErrorTag::ExpectedToken { .. }, 2)]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_procedure_locals() -> ResultParse<()> {
+        let mut parser = Parser::new("P; VAR x, y: INTEGER; VAR z: INTEGER; BEGIN x := 1 END");
+        let decl_proc = parser.proc()?;
+        let names: Vec<_> = decl_proc.locals.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["x", "y", "z"]);
+        assert_eq!(decl_proc.body.len(), 1);
+        assert!(is_at_eof(&parser));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stmt_assign() -> ResultParse<()> {
+        let mut parser = Parser::new("x := 1");
+        match parser.stmt()? {
+            Some(Stmt::Assign(assign)) => assert_eq!(assign.var.name.name, "x"),
+            _ => panic!("Expected an assignment."),
+        }
+        assert!(is_at_eof(&parser));
+        Ok(())
+    }
+
+    #[test]
+    fn test_call_args() -> ResultParse<()> {
+        let mut parser = Parser::new("P(1, x, Q(), 2.5, \"ab\")");
+        match parser.stmt()? {
+            Some(Stmt::Call(call)) => {
+                assert_eq!(call.args.len(), 5);
+                assert!(matches!(call.args[1], Expr::Var(_)));
+                assert!(matches!(call.args[2], Expr::Call(_)));
+                assert!(matches!(call.args[3], Expr::Real(Real { value: 2.5, .. })));
+                assert!(matches!(&call.args[4], Expr::Str(Str { value, .. }) if value == "ab"));
+            }
+            _ => panic!("Expected a call statement."),
+        }
+        assert!(is_at_eof(&parser));
+        Ok(())
+    }
+
+    #[test]
+    fn test_spans() -> ResultParse<()> {
+        let mut parser = Parser::new("MODULE M;\nPROCEDURE P;\nBEGIN  Maths.Q(7)\nEND;\nEND M.");
+        let module = parser.module()?;
+        let Decl::Proc(proc) = &module.decls[0] else {
+            panic!("Expected a procedure.");
+        };
+        assert_eq!(
+            proc.span,
+            Span {
+                offset: 20,
+                len: 1,
+                line: 2,
+                column: 11,
+            }
+        );
+        let Stmt::Call(call) = &proc.body[0] else {
+            panic!("Expected a call statement.");
+        };
+        assert_eq!(
+            call.span,
+            Span {
+                offset: 30,
+                len: 7,
+                line: 3,
+                column: 8,
+            }
+        );
+        let Expr::Integer(integer) = &call.args[0] else {
+            panic!("Expected an integer.");
+        };
+        assert_eq!(integer.span.column, 16);
+        Ok(())
+    }
+
     fn is_at_eof(parser: &Parser) -> bool {
         parser.current.tag == TokenTag::Eof
     }
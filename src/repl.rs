@@ -0,0 +1,259 @@
+//! An interactive read-eval-print loop.
+//!
+//! Each entry is either declarations, a statement sequence or an expression.
+//! It's checked against the declarations entered before it and run with the
+//! `evaluator`, printing an expression's value. An entry continues over
+//! several lines until it's complete, e.g. until a procedure's `END`, or until
+//! an empty line.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::mem;
+
+use crate::ast::src::{Decl, Expr, Module, Stmt};
+use crate::compiler::Checker;
+use crate::error::{Diagnostics, ErrorTag};
+use crate::evaluator::{Evaluator, Value};
+use crate::interpreter::{Trap, with_call_stack};
+use crate::parser::Parser;
+use crate::scanner::{Scanner, Token, TokenTag};
+
+const BANNER: &str = "Titania REPL. Enter declarations, statements or expressions, \
+                      an empty line to end an entry, and end of file to quit.";
+
+/// The prompt for a new entry.
+const PROMPT: &str = "> ";
+
+/// The prompt for the next line of an incomplete entry.
+const PROMPT_CONTINUE: &str = ". ";
+
+/// What an entry runs.
+enum Entry<'a> {
+    Expr(&'a Expr),
+    Stmts(&'a [Stmt]),
+}
+
+/// The declarations and variables entered so far.
+#[derive(Default)]
+pub struct Repl {
+    checker: Checker,
+    decls: Vec<Decl>,
+    globals: HashMap<String, Value>,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles the text of an entry, returning what to print, or `None` if
+    /// the entry is incomplete and `end` isn't set.
+    pub fn entry(&mut self, text: &str, end: bool) -> Option<String> {
+        let first = Scanner::new(text).next_token();
+        let result = match first {
+            Ok(Token {
+                tag: TokenTag::Var | TokenTag::Procedure,
+                ..
+            }) => self.decls(text),
+            _ => self.stmts_or_expr(text),
+        };
+        match result {
+            Ok(printed) => Some(printed),
+            Err(diagnostics) if !end && is_incomplete(text, &diagnostics) => None,
+            Err(diagnostics) => Some(diagnostics.render(text)),
+        }
+    }
+
+    /// Checks and adds declarations.
+    fn decls(&mut self, text: &str) -> Result<String, Diagnostics> {
+        let decls = Parser::new(text).whole(Parser::decl_seq)?;
+        self.checker.decls(&decls)?;
+        self.decls.extend(decls);
+        Ok(String::new())
+    }
+
+    /// Checks and runs a lone expression, or else a statement sequence.
+    fn stmts_or_expr(&mut self, text: &str) -> Result<String, Diagnostics> {
+        // A call of a proper procedure also parses as an expression, but
+        // isn't one.
+        if let Ok(expr) = Parser::new(text).whole(Parser::expr)
+            && self.checker.expr(&expr).is_ok()
+        {
+            return Ok(self.run(Entry::Expr(&expr)));
+        }
+
+        let stmts = Parser::new(text).whole(Parser::stmt_seq)?;
+        self.checker.stmts(&stmts)?;
+        Ok(self.run(Entry::Stmts(&stmts)))
+    }
+
+    /// Runs an entry with the variables entered so far, returning what to
+    /// print. A trap keeps the variables assigned before it.
+    fn run(&mut self, entry: Entry) -> String {
+        let module = Module {
+            name: "Repl".to_owned(),
+            imports: Vec::new(),
+            decls: mem::take(&mut self.decls),
+            body: Vec::new(),
+            pragmas: Vec::new(),
+        };
+        let globals = mem::take(&mut self.globals);
+        let (result, globals) = with_call_stack(|| {
+            let mut evaluator = Evaluator::with_globals(&module, globals);
+            let result = match entry {
+                Entry::Expr(expr) => evaluator.eval(expr).map(|value| value.to_string()),
+                Entry::Stmts(stmts) => evaluator.run(stmts).map(|_| String::new()),
+            };
+            (result, evaluator.into_globals())
+        });
+        self.globals = globals;
+        self.decls = module.decls;
+        result.unwrap_or_else(|trap: Trap| format!("trap: {trap}"))
+    }
+}
+
+/// Determines if an entry's errors are because it stops early, so it may
+/// continue on the next line.
+fn is_incomplete(text: &str, diagnostics: &Diagnostics) -> bool {
+    diagnostics.errors().first().is_some_and(|e| {
+        matches!(
+            e.tag,
+            ErrorTag::UnterminatedComment | ErrorTag::UnterminatedString
+        ) || e.span.offset >= text.trim_end().len()
+    })
+}
+
+/// Reads entries from `input` until its end, writing prompts and results to
+/// `output`.
+pub fn run(input: impl BufRead, output: &mut impl Write) -> io::Result<()> {
+    let mut repl = Repl::new();
+    let mut text = String::new();
+    write!(output, "{BANNER}\n{PROMPT}")?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+        let end = line.trim().is_empty();
+        if text.is_empty() && end {
+            write!(output, "{PROMPT}")?;
+            output.flush()?;
+            continue;
+        }
+        text.push_str(&line);
+        text.push('\n');
+
+        let prompt = match repl.entry(&text, end) {
+            Some(printed) => {
+                if !printed.is_empty() {
+                    writeln!(output, "{printed}")?;
+                }
+                text.clear();
+                PROMPT
+            }
+            None => PROMPT_CONTINUE,
+        };
+        write!(output, "{prompt}")?;
+        output.flush()?;
+    }
+
+    writeln!(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the REPL with the given lines of input, returning what it prints
+    /// after the banner, without prompts.
+    fn repl(input: &str) -> String {
+        let mut output = Vec::new();
+        run(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        output[BANNER.len() + 1..]
+            .replace(PROMPT, "")
+            .replace(PROMPT_CONTINUE, "")
+    }
+
+    #[test]
+    fn test_repl() {
+        let input = "VAR x: INTEGER; c: CHAR;
+x := 5; c := 41X
+x
+c
+PROCEDURE Id(a: INTEGER): INTEGER;
+BEGIN
+  RETURN a
+END;
+Id(x)
+INC(x, 2)
+ODD(x)
+";
+        assert_eq!(repl(input), "5\n041X\n5\nTRUE\n\n");
+    }
+
+    #[test]
+    fn test_repl_errors() {
+        let input = "VAR x: INTEGER;
+y := 1
+
+x := 41X
+VAR x: INTEGER;
+x
+";
+        let output = repl(input);
+        assert!(output.contains("name `y` is not defined"), "{output}");
+        assert!(
+            output.contains("expected type `INTEGER` but got `CHAR`"),
+            "{output}"
+        );
+        assert!(
+            output.contains("name `x` was previously defined"),
+            "{output}"
+        );
+        assert!(output.ends_with("0\n\n"), "{output}");
+    }
+
+    #[test]
+    fn test_repl_undefined_type() {
+        let input = "VAR x: Foo;
+VAR x: INTEGER;
+x
+";
+        let output = repl(input);
+        assert!(output.contains("name `Foo` is not defined"), "{output}");
+        assert!(output.ends_with("0\n\n"), "{output}");
+    }
+
+    #[test]
+    fn test_repl_incomplete() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.entry("PROCEDURE P*;\n", false), None);
+        assert_eq!(repl.entry("PROCEDURE P*;\nBEGIN\n", false), None);
+        let text = "PROCEDURE P*;\nBEGIN\nEND;\n";
+        assert_eq!(repl.entry(text, false), Some(String::new()));
+        assert_eq!(repl.entry("P(\n", false), None);
+        assert!(repl.entry("P(\n", true).is_some());
+    }
+
+    #[test]
+    fn test_repl_call_stack_exhausted() {
+        let input = "PROCEDURE P;
+BEGIN
+  P
+END;
+P
+";
+        assert_eq!(repl(input), "trap: call stack exhausted\n\n");
+    }
+
+    #[test]
+    fn test_repl_trap() {
+        let input = "PROCEDURE [EXTERNAL \"env\", \"log\"] Log(a: INTEGER);
+Log(1)
+";
+        assert_eq!(
+            repl(input),
+            "trap: imported function `env.log` isn't available\n\n"
+        );
+    }
+}
@@ -4,16 +4,30 @@ use std::fmt;
 use std::str::Chars;
 
 use crate::error::*;
+use crate::span::Span;
+use crate::unicode;
 
 /// Represents a token's type in a source text.
 #[derive(Clone, Debug, PartialEq)]
 pub enum TokenTag {
+    /// The `ARRAY` keyword.
+    Array,
+
+    /// A `:=`.
+    Becomes,
+
     /// The `BEGIN` keyword.
     Begin,
 
+    /// A character literal, e.g. `41X`.
+    Char(u8),
+
     /// A `:`.
     Colon,
 
+    /// A `,`.
+    Comma,
+
     /// A `.`
     Dot,
 
@@ -26,23 +40,54 @@ pub enum TokenTag {
     /// A sequence of letters or digits that is not a keyword.
     Identifier(String),
 
-    /// A sequence of digits.
-    Integer(String),
+    /// The `IMPORT` keyword.
+    Import,
+
+    /// A decimal integer literal, e.g. `255`, or a hexadecimal one, e.g.
+    /// `0FFH`.
+    Integer(i64),
+
+    /// A `[`.
+    LBracket,
+
+    /// A `(`.
+    LParen,
 
     /// The `MODULE` keyword.
     Module,
 
+    /// The `OF` keyword.
+    Of,
+
+    /// A compiler pragma, `(*$ text *)`, holding its text.
+    Pragma(String),
+
     /// The `PROCEDURE` keyword.
     Procedure,
 
     /// The `RETURN` keyword.
     Return,
 
+    /// A `]`.
+    RBracket,
+
+    /// A real literal, e.g. `1.5` or `2.0E-3`.
+    Real(f64),
+
+    /// A `)`.
+    RParen,
+
     /// A `;`.
     Semicolon,
 
     /// A '*'.
     Star,
+
+    /// The `VAR` keyword.
+    Var,
+
+    /// A sequence of characters enclosed in double quotes.
+    Str(String),
 }
 
 impl fmt::Display for TokenTag {
@@ -50,22 +95,44 @@ impl fmt::Display for TokenTag {
         use TokenTag::*;
 
         let token_str = match self {
+            Array => "ARRAY",
+            Becomes => ":=",
             Begin => "BEGIN",
+            Char(c) => {
+                return write!(f, "char({c:02X}X)");
+            }
             Colon => ":",
+            Comma => ",",
             Dot => ".",
             Eof => "EOF",
             End => "END",
             Identifier(id) => {
                 return write!(f, "identifier({id})");
             }
+            Import => "IMPORT",
             Integer(n) => {
                 return write!(f, "integer({n})");
             }
+            LBracket => "[",
+            LParen => "(",
             Module => "MODULE",
+            Of => "OF",
+            Pragma(text) => {
+                return write!(f, "pragma({text})");
+            }
             Procedure => "PROCEDURE",
             Return => "Return",
+            RBracket => "]",
+            Real(x) => {
+                return write!(f, "real({x:?})");
+            }
+            RParen => ")",
             Semicolon => ";",
             Star => "*",
+            Str(s) => {
+                return write!(f, "string(\"{s}\")");
+            }
+            Var => "VAR",
         };
 
         write!(f, "{token_str}")
@@ -78,14 +145,14 @@ pub struct Token {
     /// The token's type.
     pub tag: TokenTag,
 
-    /// The line in the source text on which the token starts.
-    pub line: usize,
+    /// Where the token is in the source text.
+    pub span: Span,
 }
 
 impl Token {
     /// Constructs a new Token.
-    pub fn new(tag: TokenTag, line: usize) -> Self {
-        Self { tag, line }
+    pub fn new(tag: TokenTag, span: Span) -> Self {
+        Self { tag, span }
     }
 }
 
@@ -100,77 +167,129 @@ pub struct Scanner<'a> {
     /// Next character in the source text.
     next: Option<char>,
 
-    /// The line the current character is on in the source text.
-    line: usize,
+    /// The position of the current character in the source text.
+    position: Span,
+
+    /// Whether identifiers may have Unicode letters, not just ASCII ones.
+    unicode_identifiers: bool,
 }
 
 impl<'a> Scanner<'a> {
     /// Constructs a scanner that is ready to produce tokens from a given
-    /// source text.
+    /// source text, skipping any byte order mark at its start.
     pub fn new(source: &'a str) -> Scanner<'a> {
         let mut scanner = Self {
             chars: source.chars(),
             current: None,
             next: None,
-            line: 1,
+            position: Span::at_line(1),
+            unicode_identifiers: false,
         };
         scanner.advance();
         scanner.advance();
+        if scanner.current == Some(BYTE_ORDER_MARK) {
+            scanner.advance();
+            scanner.position.column = 1;
+        }
         scanner
     }
 
+    /// Sets whether identifiers may have Unicode letters, following
+    /// Unicode's XID identifier syntax outside ASCII, e.g. `Größe`.
+    pub fn unicode_identifiers(mut self, unicode_identifiers: bool) -> Self {
+        self.unicode_identifiers = unicode_identifiers;
+        self
+    }
+
     /// Attempt to get the next token in the source text.
     pub fn next_token(&mut self) -> Result<Token, Error> {
-        self.skip_whitespace_comments()?;
+        if let Some(start) = self.skip_whitespace_comments()? {
+            return self.pragma(start);
+        }
 
         match self.current {
-            Some(c) if is_alpha(c) => self.identifier(),
+            Some(c) if self.is_identifier_start(c) => self.identifier(),
             Some(c) if is_digit(c) => self.number(),
+            Some('"') => self.string(),
             _ => self.symbol(),
         }
     }
 
     /// Skips all white space characters and comments before the next token.
-    fn skip_whitespace_comments(&mut self) -> Result<(), Error> {
-        let mut in_comment = false;
+    /// Comments nest, and a comment starting `(*$` is a pragma, which stops
+    /// skipping and returns where the pragma starts.
+    fn skip_whitespace_comments(&mut self) -> Result<Option<Span>, Error> {
+        // The number of comments the current character is in.
+        let mut depth = 0;
         loop {
             match (self.current, self.next) {
-                (Some('('), Some('*')) if !in_comment => {
-                    in_comment = true;
+                (Some('('), Some('*')) => {
+                    let start = self.position;
                     self.advance();
                     self.advance();
+                    if depth == 0 && self.current == Some('$') {
+                        return Ok(Some(start));
+                    }
+                    depth += 1;
                 }
 
-                (Some('*'), Some(')')) if in_comment => {
-                    in_comment = false;
+                (Some('*'), Some(')')) if depth > 0 => {
+                    depth -= 1;
                     self.advance();
                     self.advance();
                 }
 
-                (Some(c), _) if is_whitespace(c) || in_comment => {
+                (Some(c), _) if is_whitespace(c) || depth > 0 => {
                     self.advance();
                 }
 
-                (None, _) if in_comment => {
-                    return Err(Error::new(ErrorTag::UnterminatedComment, self.line));
+                (None, _) if depth > 0 => {
+                    return Err(Error::new(ErrorTag::UnterminatedComment, self.position));
                 }
 
-                _ => return Ok(()),
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    /// Scans a pragma token assuming that current is the `$` after the `(*`
+    /// at `start`. The pragma's text is trimmed, and ends at the first `*)`.
+    fn pragma(&mut self, start: Span) -> Result<Token, Error> {
+        // "$"
+        self.advance();
+
+        let mut text = String::new();
+        loop {
+            match (self.current, self.next) {
+                (Some('*'), Some(')')) => break,
+                (Some(c), _) => {
+                    text.push(c);
+                    self.advance();
+                }
+                (None, _) => {
+                    let tag = ErrorTag::UnterminatedComment;
+                    return Err(Error::new(tag, self.span_from(start)));
+                }
             }
         }
+
+        // "*)"
+        self.advance();
+        self.advance();
+
+        let tag = TokenTag::Pragma(text.trim().to_owned());
+        Ok(Token::new(tag, self.span_from(start)))
     }
 
     /// Scans an identifier token assuming that current is a valid starting
     /// character for an identifier.
     fn identifier(&mut self) -> Result<Token, Error> {
-        use TokenTag::*;
-
-        let line = self.line;
+        let start = self.position;
 
         let mut lexeme = String::new();
         loop {
             match self.current {
-                Some(c) if is_alpha(c) || is_digit(c) => {
+                Some(c) if self.is_identifier_continue(c) => {
                     lexeme.push(c);
                     self.advance();
                 }
@@ -178,64 +297,210 @@ impl<'a> Scanner<'a> {
             }
         }
 
-        let tag = match lexeme.as_ref() {
-            "BEGIN" => Begin,
-            "END" => End,
-            "MODULE" => Module,
-            "PROCEDURE" => Procedure,
-            "RETURN" => Return,
-            _ => Identifier(lexeme),
-        };
+        let tag = keyword(&lexeme).unwrap_or(TokenTag::Identifier(lexeme));
 
-        Ok(Token::new(tag, line))
+        Ok(Token::new(tag, self.span_from(start)))
     }
 
-    /// Scans a number token assuming that current is digit.
+    /// Scans a number token assuming that current is digit. A number is
+    /// decimal unless it ends in `H`, for hexadecimal, or `X`, for a
+    /// hexadecimal character code, and is real if its digits are followed by
+    /// a `.`.
     fn number(&mut self) -> Result<Token, Error> {
-        let line = self.line;
+        let start = self.position;
+
+        // Hex digits are allowed until the suffix shows the number's base.
+        let mut lexeme = String::new();
+        while let Some(c) = self.current
+            && c.is_ascii_hexdigit()
+            && !c.is_ascii_lowercase()
+        {
+            lexeme.push(c);
+            self.advance();
+        }
+
+        if self.current == Some('.') && lexeme.chars().all(is_digit) {
+            return self.real(start, lexeme);
+        }
+
+        let suffix = match self.current {
+            Some(c @ ('H' | 'X')) => {
+                self.advance();
+                Some(c)
+            }
+            _ => None,
+        };
+        let span = self.span_from(start);
+        let error = |tag| Err(Error::new(tag, span));
+
+        let tag = match suffix {
+            None if !lexeme.chars().all(is_digit) => {
+                return error(ErrorTag::InvalidNumber(lexeme));
+            }
+            None => match lexeme.parse() {
+                Ok(value) => TokenTag::Integer(value),
+                Err(_) => return error(ErrorTag::IntegerOutOfRange(lexeme)),
+            },
+            Some('H') => match i64::from_str_radix(&lexeme, 16) {
+                Ok(value) => TokenTag::Integer(value),
+                Err(_) => return error(ErrorTag::IntegerOutOfRange(lexeme + "H")),
+            },
+            _ => match u8::from_str_radix(&lexeme, 16) {
+                Ok(value) => TokenTag::Char(value),
+                Err(_) => return error(ErrorTag::CharacterOutOfRange(lexeme + "X")),
+            },
+        };
+
+        Ok(Token::new(tag, span))
+    }
+
+    /// Scans the rest of a real number assuming that current is the `.`
+    /// after its integer part, `lexeme`: the fraction's digits and an
+    /// optional scale factor, e.g. `E-3`.
+    fn real(&mut self, start: Span, mut lexeme: String) -> Result<Token, Error> {
+        lexeme.push('.');
+        self.advance();
+        self.digits(&mut lexeme);
+
+        let mut scaled = true;
+        if self.current == Some('E') {
+            lexeme.push('E');
+            self.advance();
+            if let Some(c @ ('+' | '-')) = self.current {
+                lexeme.push(c);
+                self.advance();
+            }
+            scaled = self.digits(&mut lexeme);
+        }
+
+        let span = self.span_from(start);
+        if !scaled {
+            return Err(Error::new(ErrorTag::MissingScaleFactor(lexeme), span));
+        }
+        match lexeme.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Token::new(TokenTag::Real(value), span)),
+            _ => Err(Error::new(ErrorTag::RealOutOfRange(lexeme), span)),
+        }
+    }
+
+    /// Scans decimal digits onto a lexeme, returning whether there were any.
+    fn digits(&mut self, lexeme: &mut String) -> bool {
+        let len = lexeme.len();
+        while let Some(c) = self.current
+            && is_digit(c)
+        {
+            lexeme.push(c);
+            self.advance();
+        }
+        lexeme.len() > len
+    }
+
+    /// Scans a string token assuming that current is a double quote.
+    fn string(&mut self) -> Result<Token, Error> {
+        let start = self.position;
+
+        // Opening quote.
+        self.advance();
 
         let mut lexeme = String::new();
         loop {
             match self.current {
-                Some(c) if is_digit(c) => {
+                Some('"') => break,
+                Some(c) if c != '\n' => {
                     lexeme.push(c);
                     self.advance();
                 }
-                _ => break,
+                _ => {
+                    let tag = ErrorTag::UnterminatedString;
+                    return Err(Error::new(tag, self.span_from(start)));
+                }
             }
         }
 
-        Ok(Token::new(TokenTag::Integer(lexeme), line))
+        // Closing quote.
+        self.advance();
+
+        Ok(Token::new(TokenTag::Str(lexeme), self.span_from(start)))
     }
 
     /// Scans a symbol token and end of file.
     fn symbol(&mut self) -> Result<Token, Error> {
         use TokenTag::*;
 
-        let line = self.line;
+        let start = self.position;
 
-        let tag = match self.current {
-            None => Eof,
-            Some(':') => Colon,
-            Some('.') => Dot,
-            Some(';') => Semicolon,
-            Some('*') => Star,
-            Some(c) => return Err(Error::new(ErrorTag::UnexpectedCharacter(c), self.line)),
+        let tag = match (self.current, self.next) {
+            (None, _) => Eof,
+            (Some(':'), Some('=')) => {
+                self.advance();
+                Becomes
+            }
+            (Some(':'), _) => Colon,
+            (Some(','), _) => Comma,
+            (Some('.'), _) => Dot,
+            (Some('['), _) => LBracket,
+            (Some('('), _) => LParen,
+            (Some(']'), _) => RBracket,
+            (Some(')'), _) => RParen,
+            (Some(';'), _) => Semicolon,
+            (Some('*'), _) => Star,
+            (Some(c), _) => {
+                // Skip the character so scanning can continue after the error.
+                self.advance();
+                let tag = ErrorTag::UnexpectedCharacter(c);
+                let help = c.is_alphabetic().then(|| {
+                    "identifiers only have ASCII letters unless Unicode identifiers are enabled"
+                        .to_owned()
+                });
+                return Err(Error::new(tag, self.span_from(start)).with_help(help));
+            }
         };
 
         self.advance();
 
-        Ok(Token::new(tag, line))
+        Ok(Token::new(tag, self.span_from(start)))
     }
 
     /// Advances current to the next character in the source text.
     fn advance(&mut self) {
-        if let Some('\n') = self.current {
-            self.line += 1;
+        match self.current {
+            Some('\n') => {
+                self.position.line += 1;
+                self.position.column = 1;
+            }
+            Some(_) => self.position.column += 1,
+            None => (),
         }
+        self.position.offset += self.current.map_or(0, char::len_utf8);
         self.current = self.next;
         self.next = self.chars.next();
     }
+
+    /// Determines if a character can start an identifier.
+    fn is_identifier_start(&self, c: char) -> bool {
+        if self.unicode_identifiers && !c.is_ascii() {
+            unicode::is_xid_start(c)
+        } else {
+            is_alpha(c)
+        }
+    }
+
+    /// Determines if a character can be in an identifier after its start.
+    fn is_identifier_continue(&self, c: char) -> bool {
+        if self.unicode_identifiers && !c.is_ascii() {
+            unicode::is_xid_continue(c)
+        } else {
+            is_alpha(c) || is_digit(c)
+        }
+    }
+
+    /// The span from a start position to the current character.
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            len: self.position.offset - start.offset,
+            ..start
+        }
+    }
 }
 
 /// Determines if a given character is alphabetic.
@@ -253,6 +518,49 @@ fn is_whitespace(c: char) -> bool {
     c == ' ' || c == '\t' || c == '\r' || c == '\n'
 }
 
+/// The byte order mark some editors put at the start of UTF-8 files.
+const BYTE_ORDER_MARK: char = '\u{FEFF}';
+
+/// Reads a source text from the bytes of a file, which must be UTF-8.
+pub fn decode_source(bytes: &[u8]) -> Result<&str, Error> {
+    std::str::from_utf8(bytes).map_err(|e| {
+        let offset = e.valid_up_to();
+        let valid = std::str::from_utf8(&bytes[..offset]).unwrap_or_default();
+        let line_start = valid.rfind('\n').map_or(0, |i| i + 1);
+        let span = Span {
+            offset,
+            len: e.error_len().unwrap_or(bytes.len() - offset),
+            line: valid.matches('\n').count() + 1,
+            column: valid[line_start..].chars().count() + 1,
+        };
+        Error::new(ErrorTag::InvalidUtf8(offset), span)
+    })
+}
+
+/// The keyword spelt by a lexeme, if any.
+fn keyword(lexeme: &str) -> Option<TokenTag> {
+    use TokenTag::*;
+
+    match lexeme {
+        "ARRAY" => Some(Array),
+        "BEGIN" => Some(Begin),
+        "END" => Some(End),
+        "IMPORT" => Some(Import),
+        "MODULE" => Some(Module),
+        "OF" => Some(Of),
+        "PROCEDURE" => Some(Procedure),
+        "RETURN" => Some(Return),
+        "VAR" => Some(Var),
+        _ => None,
+    }
+}
+
+/// The keyword an identifier spells if case is ignored, e.g. `Begin`, since
+/// keywords must be upper case.
+pub fn keyword_ignoring_case(identifier: &str) -> Option<TokenTag> {
+    keyword(&identifier.to_ascii_uppercase())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,8 +571,8 @@ mod tests {
         TokenTag::Identifier(String::from(id))
     }
 
-    fn integer_tag(n: &str) -> TokenTag {
-        TokenTag::Integer(String::from(n))
+    fn integer_tag(n: i64) -> TokenTag {
+        TokenTag::Integer(n)
     }
 
     fn next_tag(scanner: &mut Scanner) -> Result<TokenTag, Error> {
@@ -285,6 +593,43 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_skip_nested_comments() -> Result<(), Error> {
+        let mut scanner = Scanner::new("(* outer (* inner *) still comment *) id *");
+        assert_eq!(next_tag(&mut scanner)?, identifier_tag("id"));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Star);
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_token_unclosed_nested_comment() {
+        let mut scanner = Scanner::new("(* (* *)");
+        assert_eq!(
+            error_tag(scanner.next_token()),
+            Some(ErrorTag::UnterminatedComment)
+        );
+    }
+
+    #[test]
+    fn test_next_token_pragma() -> Result<(), Error> {
+        let mut scanner = Scanner::new("x (*$ INDEX- *) (* (*$ignored*) *) y");
+        assert_eq!(next_tag(&mut scanner)?, identifier_tag("x"));
+        let token = scanner.next_token()?;
+        assert_eq!(token.tag, TokenTag::Pragma("INDEX-".to_owned()));
+        assert_eq!((token.span.offset, token.span.len), (2, 13));
+        assert_eq!(next_tag(&mut scanner)?, identifier_tag("y"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_token_unclosed_pragma() {
+        let mut scanner = Scanner::new("(*$INDEX-");
+        assert_eq!(
+            error_tag(scanner.next_token()),
+            Some(ErrorTag::UnterminatedComment)
+        );
+    }
+
     #[test]
     fn test_next_token_unclosed_comment() {
         let mut scanner = Scanner::new("(**");
@@ -306,33 +651,220 @@ mod tests {
     #[test]
     fn test_next_token() -> Result<(), Error> {
         let mut scanner = Scanner::new("1 1234");
-        assert_eq!(next_tag(&mut scanner)?, integer_tag("1"));
-        assert_eq!(next_tag(&mut scanner)?, integer_tag("1234"));
+        assert_eq!(next_tag(&mut scanner)?, integer_tag(1));
+        assert_eq!(next_tag(&mut scanner)?, integer_tag(1234));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Eof);
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_token_hex_and_char() -> Result<(), Error> {
+        let mut scanner = Scanner::new("0FFH 7FFFFFFFFFFFFFFFH 41X 0X 0FFX");
+        assert_eq!(next_tag(&mut scanner)?, integer_tag(255));
+        assert_eq!(next_tag(&mut scanner)?, integer_tag(i64::MAX));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Char(b'A'));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Char(0));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Char(255));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Eof);
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_token_real() -> Result<(), Error> {
+        let mut scanner = Scanner::new("1.5 0. 12.5E3 2.0E-3 1.0E+2 1.8E308 END.");
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Real(1.5));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Real(0.0));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Real(12500.0));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Real(0.002));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Real(100.0));
+        assert_eq!(
+            error_tag(scanner.next_token()),
+            Some(ErrorTag::RealOutOfRange("1.8E308".to_owned()))
+        );
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::End);
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Dot);
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_token_real_errors() -> Result<(), Error> {
+        let mut scanner = Scanner::new("1.0E 2.5E- 0FF.5");
+        assert_eq!(
+            error_tag(scanner.next_token()),
+            Some(ErrorTag::MissingScaleFactor("1.0E".to_owned()))
+        );
+        assert_eq!(
+            error_tag(scanner.next_token()),
+            Some(ErrorTag::MissingScaleFactor("2.5E-".to_owned()))
+        );
+        assert_eq!(
+            error_tag(scanner.next_token()),
+            Some(ErrorTag::InvalidNumber("0FF".to_owned()))
+        );
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Dot);
+        assert_eq!(next_tag(&mut scanner)?, integer_tag(5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_token_number_errors() -> Result<(), Error> {
+        let mut scanner = Scanner::new("0FF 9223372036854775808 8000000000000000H 100X ;");
+        assert_eq!(
+            error_tag(scanner.next_token()),
+            Some(ErrorTag::InvalidNumber("0FF".to_owned()))
+        );
+        assert_eq!(
+            error_tag(scanner.next_token()),
+            Some(ErrorTag::IntegerOutOfRange(
+                "9223372036854775808".to_owned()
+            ))
+        );
+        assert_eq!(
+            error_tag(scanner.next_token()),
+            Some(ErrorTag::IntegerOutOfRange("8000000000000000H".to_owned()))
+        );
+        let Err(error) = scanner.next_token() else {
+            panic!("Expected an error.");
+        };
+        assert_eq!(error.tag, ErrorTag::CharacterOutOfRange("100X".to_owned()));
+        assert_eq!((error.span.column, error.span.len), (43, 4));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Semicolon);
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_token_string() -> Result<(), Error> {
+        let mut scanner = Scanner::new("\"env\" \"\"");
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Str("env".to_owned()));
+        assert_eq!(next_tag(&mut scanner)?, TokenTag::Str(String::new()));
         assert_eq!(next_tag(&mut scanner)?, TokenTag::Eof);
         Ok(())
     }
 
+    #[test]
+    fn test_next_token_unterminated_string() {
+        let mut scanner = Scanner::new("\"env\n\"");
+        assert_eq!(
+            error_tag(scanner.next_token()),
+            Some(ErrorTag::UnterminatedString)
+        );
+    }
+
     #[test]
     fn test_next_token_keywords() -> Result<(), Error> {
         use TokenTag::*;
 
-        let mut scanner = Scanner::new("BEGIN END MODULE PROCEDURE RETURN");
+        let mut scanner = Scanner::new("ARRAY BEGIN END IMPORT MODULE OF PROCEDURE RETURN VAR");
+        assert_eq!(next_tag(&mut scanner)?, Array);
         assert_eq!(next_tag(&mut scanner)?, Begin);
         assert_eq!(next_tag(&mut scanner)?, End);
+        assert_eq!(next_tag(&mut scanner)?, Import);
         assert_eq!(next_tag(&mut scanner)?, Module);
+        assert_eq!(next_tag(&mut scanner)?, Of);
         assert_eq!(next_tag(&mut scanner)?, Procedure);
         assert_eq!(next_tag(&mut scanner)?, Return);
+        assert_eq!(next_tag(&mut scanner)?, Var);
         assert_eq!(next_tag(&mut scanner)?, Eof);
         Ok(())
     }
 
+    #[test]
+    fn test_next_token_span() -> Result<(), Error> {
+        let mut scanner = Scanner::new("(* é *) ab\n  := 12");
+        let span = |offset, len, line, column| Span {
+            offset,
+            len,
+            line,
+            column,
+        };
+        assert_eq!(scanner.next_token()?.span, span(9, 2, 1, 9));
+        assert_eq!(scanner.next_token()?.span, span(14, 2, 2, 3));
+        assert_eq!(scanner.next_token()?.span, span(17, 2, 2, 6));
+        assert_eq!(scanner.next_token()?.span, span(19, 0, 2, 8));
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_token_unicode_identifiers() -> Result<(), Error> {
+        let source = "Größe e\u{301}tat";
+        let mut scanner = Scanner::new(source).unicode_identifiers(true);
+        assert_eq!(next_tag(&mut scanner)?, identifier_tag("Größe"));
+        let token = scanner.next_token()?;
+        assert_eq!(token.tag, identifier_tag("e\u{301}tat"));
+        assert_eq!((token.span.offset, token.span.column), (8, 7));
+
+        let mut scanner = Scanner::new(source);
+        assert_eq!(next_tag(&mut scanner)?, identifier_tag("Gr"));
+        let Err(error) = scanner.next_token() else {
+            panic!("Expected an error.");
+        };
+        assert_eq!(error.tag, ErrorTag::UnexpectedCharacter('ö'));
+        assert!(error.help.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_token_unicode_identifiers_xid() -> Result<(), Error> {
+        use TokenTag::*;
+
+        // The virama and vowel signs are marks, Mn and Mc, the digits are Nd
+        // and the undertie is Pc, none of which can start an identifier.
+        let source = "VAR नमस्ते*: x٣‿y; \u{0966}";
+        let mut scanner = Scanner::new(source).unicode_identifiers(true);
+        assert_eq!(next_tag(&mut scanner)?, Var);
+        assert_eq!(next_tag(&mut scanner)?, identifier_tag("नमस्ते"));
+        assert_eq!(next_tag(&mut scanner)?, Star);
+        assert_eq!(next_tag(&mut scanner)?, Colon);
+        assert_eq!(next_tag(&mut scanner)?, identifier_tag("x٣‿y"));
+        assert_eq!(next_tag(&mut scanner)?, Semicolon);
+        let Err(error) = scanner.next_token() else {
+            panic!("Expected an error.");
+        };
+        assert_eq!(error.tag, ErrorTag::UnexpectedCharacter('\u{0966}'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_token_byte_order_mark() -> Result<(), Error> {
+        let mut scanner = Scanner::new("\u{FEFF}MODULE");
+        let token = scanner.next_token()?;
+        assert_eq!(token.tag, TokenTag::Module);
+        assert_eq!((token.span.offset, token.span.column), (3, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_source() {
+        assert_eq!(decode_source(b"MODULE M;").ok(), Some("MODULE M;"));
+
+        let Err(error) = decode_source(b"MODULE M;\n(* \xC3\xA9\xFF *)") else {
+            panic!("Expected an error.");
+        };
+        assert_eq!(error.tag, ErrorTag::InvalidUtf8(15));
+        assert_eq!(
+            (
+                error.span.offset,
+                error.span.len,
+                error.span.line,
+                error.span.column
+            ),
+            (15, 1, 2, 5)
+        );
+    }
+
     #[test]
     fn test_next_token_symbol() -> Result<(), Error> {
         use TokenTag::*;
 
-        let mut scanner = Scanner::new(": . ; *");
+        let mut scanner = Scanner::new(":= : , . [ ( ] ) ; *");
+        assert_eq!(next_tag(&mut scanner)?, Becomes);
         assert_eq!(next_tag(&mut scanner)?, Colon);
+        assert_eq!(next_tag(&mut scanner)?, Comma);
         assert_eq!(next_tag(&mut scanner)?, Dot);
+        assert_eq!(next_tag(&mut scanner)?, LBracket);
+        assert_eq!(next_tag(&mut scanner)?, LParen);
+        assert_eq!(next_tag(&mut scanner)?, RBracket);
+        assert_eq!(next_tag(&mut scanner)?, RParen);
         assert_eq!(next_tag(&mut scanner)?, Semicolon);
         assert_eq!(next_tag(&mut scanner)?, Star);
         assert_eq!(next_tag(&mut scanner)?, Eof);
@@ -0,0 +1,92 @@
+//! Positions in a source text.
+
+/// A range of characters in a source text.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    /// The byte offset of the first character.
+    pub offset: usize,
+
+    /// The length of the range in bytes.
+    pub len: usize,
+
+    /// The line the range starts on, counting from 1.
+    pub line: usize,
+
+    /// The column, in characters, the range starts at, counting from 1.
+    pub column: usize,
+}
+
+impl Span {
+    /// Constructs an empty span at the start of a line, for positions only
+    /// known by line.
+    pub fn at_line(line: usize) -> Self {
+        Self {
+            offset: 0,
+            len: 0,
+            line,
+            column: 1,
+        }
+    }
+
+    /// The span from the start of this span to the end of a later span.
+    pub fn join(self, end: Span) -> Self {
+        Self {
+            len: end.offset + end.len - self.offset,
+            ..self
+        }
+    }
+
+    /// The line and column just past the end of the span, counting from 1.
+    pub fn end(&self, source: &str) -> (usize, usize) {
+        let text = source
+            .get(self.offset..self.offset + self.len)
+            .unwrap_or_default();
+        text.chars()
+            .fold((self.line, self.column), |(line, column), c| match c {
+                '\n' => (line + 1, 1),
+                _ => (line, column + 1),
+            })
+    }
+
+    /// Formats the source line the span starts on, with carets under the
+    /// span, or `None` if the line isn't in the source, e.g.
+    ///
+    /// ```text
+    ///   2 | PROCEDURE P;
+    ///     |           ^
+    /// ```
+    pub fn snippet(&self, source: &str) -> Option<String> {
+        let line = source.lines().nth(self.line.wrapping_sub(1))?;
+
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let line = line.trim_end_matches('\r');
+        // Columns don't count a byte order mark.
+        let line = match self.line {
+            1 => line.trim_start_matches('\u{FEFF}'),
+            _ => line,
+        };
+
+        let start = line
+            .char_indices()
+            .nth(self.column.saturating_sub(1))
+            .map_or(line.len(), |(i, _)| i);
+        let (before, rest) = line.split_at(start);
+
+        // Keep tabs so the carets line up with the source line.
+        let indent: String = before
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = rest
+            .char_indices()
+            .take_while(|&(i, _)| i < self.len)
+            .count()
+            .max(1);
+
+        Some(format!(
+            "  {number} | {line}\n  {gutter} | {indent}{}",
+            "^".repeat(width)
+        ))
+    }
+}
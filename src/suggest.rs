@@ -0,0 +1,72 @@
+//! Suggestions for misspelt names.
+
+/// Suggests the candidate closest to a name, if one is close enough to be a
+/// likely misspelling of it, e.g. "did you mean `Sum`?".
+pub fn did_you_mean<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<String> {
+    closest(name, candidates).map(|candidate| format!("did you mean `{candidate}`?"))
+}
+
+/// The candidate with the fewest edits from a name, ignoring case, if it's
+/// within a third of the name's length, or one edit for short names.
+fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|&(d, _)| d <= max)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between two names, ignoring case.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+
+    // The distances from a prefix of `a` to each prefix of `b`.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("Count", "count"), 0);
+    }
+
+    #[test]
+    fn test_closest() {
+        let names = ["Sum", "Square", "Sqrt"];
+        assert_eq!(closest("Sqr", names), Some("Sqrt"));
+        assert_eq!(closest("Sun", names), Some("Sum"));
+        assert_eq!(closest("Squar", names), Some("Square"));
+        assert_eq!(closest("sqrt", names), Some("Sqrt"));
+        assert_eq!(closest("Total", names), None);
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        assert_eq!(
+            did_you_mean("Ad", ["Add"]),
+            Some("did you mean `Add`?".to_owned())
+        );
+    }
+}
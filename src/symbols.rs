@@ -0,0 +1,234 @@
+//! Symbol files.
+//!
+//! A symbol file records the names and types a module exports so that
+//! importers can be compiled without the module's source. It is a line
+//! oriented text file, e.g.
+//!
+//! ```text
+//! MODULE Maths
+//! PROCEDURE Init
+//! PROCEDURE Sqrt(INTEGER): INTEGER
+//! ```
+
+use std::fmt;
+
+use crate::compiler::create_default_type_table;
+use crate::error::*;
+use crate::span::Span;
+use crate::table::Table;
+use crate::types::*;
+
+/// The exported symbols of a module.
+pub struct Symbols {
+    /// The module's name.
+    pub module: String,
+
+    /// The module's exported procedures.
+    pub procs: Table<TypeProc>,
+}
+
+impl Symbols {
+    /// Creates an empty symbol set for a module.
+    pub fn new(module: &str) -> Self {
+        let module = module.to_owned();
+        let procs = Table::new();
+        Self { module, procs }
+    }
+
+    /// Reads the symbols of the named module from the text of its symbol
+    /// file.
+    pub fn parse(text: &str, module: &str) -> Result<Self, Error> {
+        let table_type = create_symbol_type_table();
+        let mut symbols: Option<Symbols> = None;
+
+        for (index, line_text) in text.lines().enumerate() {
+            let line = index + 1;
+            let line_text = line_text.trim();
+
+            if line_text.is_empty() {
+                continue;
+            }
+
+            match (&mut symbols, line_text.strip_prefix("PROCEDURE ")) {
+                (None, _) => match line_text.strip_prefix("MODULE ") {
+                    Some(name) if name == module => symbols = Some(Symbols::new(name)),
+                    Some(name) if is_name(name) => {
+                        let tag = ErrorTag::SymbolModuleMismatch {
+                            expected: module.to_owned(),
+                            got: name.to_owned(),
+                        };
+                        return Err(Error::new(tag, Span::at_line(line)));
+                    }
+                    _ => return invalid("expected a module header", line),
+                },
+
+                (Some(symbols), Some(signature)) => {
+                    let (name, t_proc) = parse_proc(&table_type, signature, line)?;
+                    symbols.procs.push(name, t_proc);
+                }
+
+                (Some(_), None) => return invalid("expected a procedure", line),
+            }
+        }
+
+        match symbols {
+            Some(symbols) => Ok(symbols),
+            None => invalid("expected a module header", 1),
+        }
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let table_type = create_symbol_type_table();
+
+        writeln!(f, "MODULE {}", self.module)?;
+        for item in self.procs.items.iter() {
+            write!(f, "PROCEDURE {}", item.name)?;
+            let params = item.value.params();
+            if !params.is_empty() {
+                write!(f, "(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", type_name(&table_type, param))?;
+                }
+                write!(f, ")")?;
+            }
+            if let Some(t_return) = item.value.t_return() {
+                write!(f, ": {}", type_name(&table_type, t_return))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The types a symbol file can name: the built-in types, and the open array
+/// of characters a library procedure may take, which has no identifier.
+fn create_symbol_type_table() -> Table<Type> {
+    let mut table_type = create_default_type_table();
+    table_type.push("ARRAY OF CHAR", Type::new_char_array());
+    table_type
+}
+
+/// Reads a procedure's name and type from its signature, e.g.
+/// `Max(INTEGER, INTEGER): INTEGER`.
+fn parse_proc<'a>(
+    table_type: &Table<Type>,
+    signature: &'a str,
+    line: usize,
+) -> Result<(&'a str, TypeProc), Error> {
+    let (head, tid_return) = match signature.split_once(':') {
+        Some((head, tid_return)) => (head, Some(tid_return.trim())),
+        None => (signature, None),
+    };
+
+    let (name, tids) = match head.split_once('(') {
+        Some((name, tids)) => {
+            let Some(tids) = tids.trim_end().strip_suffix(')') else {
+                return invalid("expected `)` after parameters", line);
+            };
+            (name.trim(), tids.split(',').map(str::trim).collect())
+        }
+        None => (head.trim(), Vec::new()),
+    };
+
+    if !is_name(name) {
+        return invalid("expected a procedure name", line);
+    }
+
+    let lookup = |tid: &str| match table_type.lookup(tid) {
+        Some(t) => Ok(t.clone()),
+        None => invalid(&format!("unknown type `{tid}`"), line),
+    };
+    let params = tids.into_iter().map(lookup).collect::<Result<_, _>>()?;
+    let t_return = tid_return.map(lookup).transpose()?;
+
+    Ok((name, TypeProc::new(params, t_return)))
+}
+
+/// Determines if a string is a valid name.
+fn is_name(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(char::is_alphanumeric)
+}
+
+/// The identifier of a built-in type.
+fn type_name<'a>(table_type: &'a Table<Type>, t: &Type) -> &'a str {
+    table_type
+        .items
+        .iter()
+        .find(|item| item.value == *t)
+        .map(|item| item.name.as_str())
+        .expect("exported types are built-in")
+}
+
+/// Creates an invalid symbol file error result.
+fn invalid<T>(reason: &str, line: usize) -> Result<T, Error> {
+    let tag = ErrorTag::InvalidSymbolFile(reason.to_owned());
+    Err(Error::new(tag, Span::at_line(line)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbols_round_trip() -> Result<(), Error> {
+        let t_init = TypeProc::new(Vec::new(), None);
+        let t_max = TypeProc::new(
+            vec![Type::new_int(), Type::new_int()],
+            Some(Type::new_int()),
+        );
+        let mut symbols = Symbols::new("Maths");
+        symbols.procs.push("Init", t_init.clone());
+        symbols.procs.push("Max", t_max.clone());
+
+        let text = symbols.to_string();
+        assert_eq!(
+            text,
+            "MODULE Maths\nPROCEDURE Init\nPROCEDURE Max(INTEGER, INTEGER): INTEGER\n"
+        );
+
+        let symbols = Symbols::parse(&text, "Maths")?;
+        assert_eq!(symbols.module, "Maths");
+        assert_eq!(symbols.procs.lookup("Init"), Some(&t_init));
+        assert_eq!(symbols.procs.lookup("Max"), Some(&t_max));
+        Ok(())
+    }
+
+    #[test]
+    fn test_symbols_parse_unknown_type() {
+        let result = Symbols::parse("MODULE M\nPROCEDURE P: SET\n", "M");
+        match result {
+            Err(Error {
+                tag: ErrorTag::InvalidSymbolFile(_),
+                span: Span { line: 2, .. },
+                ..
+            }) => (),
+            _ => panic!("Expected an invalid symbol file error."),
+        }
+    }
+
+    #[test]
+    fn test_symbols_parse_missing_header() {
+        let result = Symbols::parse("PROCEDURE P\n", "M");
+        assert!(matches!(
+            error_tag(result),
+            Some(ErrorTag::InvalidSymbolFile(_))
+        ));
+    }
+
+    #[test]
+    fn test_symbols_parse_module_mismatch() {
+        let result = Symbols::parse("MODULE Maths2\nPROCEDURE P\n", "Maths");
+        assert_eq!(
+            error_tag(result),
+            Some(ErrorTag::SymbolModuleMismatch {
+                expected: "Maths".to_owned(),
+                got: "Maths2".to_owned(),
+            })
+        );
+    }
+}
@@ -16,6 +16,12 @@ pub struct Table<T> {
     pub items: Vec<Item<T>>,
 }
 
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Table<T> {
     pub fn new() -> Self {
         Self { items: Vec::new() }
@@ -33,4 +39,9 @@ impl<T> Table<T> {
             .find(|item| item.name == name)
             .map(|item| &item.value)
     }
+
+    /// The names in the table, oldest first.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().map(|item| item.name.as_str())
+    }
 }
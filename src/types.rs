@@ -7,12 +7,24 @@ use std::rc::Rc;
 
 #[derive(Debug)]
 pub enum TypeTag {
+    Bool,
+    Char,
+    /// An open array of characters, the type of a string literal and of a
+    /// parameter it's passed to, e.g. `Out.String`'s.
+    CharArray,
+    /// An array of the given number of characters, the only arrays there
+    /// are.
+    FixedCharArray(u32),
     Int,
+    Real,
 }
 
 /// Represents a procedure type.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct TypeProc {
+    /// The procedure's parameter types.
+    params: Vec<Type>,
+
     /// The procedure's return type.
     t_return: Option<Type>,
 }
@@ -24,8 +36,18 @@ pub struct Type {
 
 impl TypeProc {
     /// Creates a procedure type.
-    pub fn new(t_return: Option<Type>) -> Self {
-        Self { t_return }
+    pub fn new(params: Vec<Type>, t_return: Option<Type>) -> Self {
+        Self { params, t_return }
+    }
+
+    /// The procedure's parameter types.
+    pub fn params(&self) -> &[Type] {
+        &self.params
+    }
+
+    /// The procedure's return type, if it's a function procedure.
+    pub fn t_return(&self) -> Option<&Type> {
+        self.t_return.as_ref()
     }
 }
 
@@ -35,6 +57,31 @@ impl Type {
         Self { tag }
     }
 
+    pub fn new_bool() -> Self {
+        let tag = Rc::new(TypeTag::Bool);
+        Self { tag }
+    }
+
+    pub fn new_char() -> Self {
+        let tag = Rc::new(TypeTag::Char);
+        Self { tag }
+    }
+
+    pub fn new_real() -> Self {
+        let tag = Rc::new(TypeTag::Real);
+        Self { tag }
+    }
+
+    pub fn new_char_array() -> Self {
+        let tag = Rc::new(TypeTag::CharArray);
+        Self { tag }
+    }
+
+    pub fn new_fixed_char_array(len: u32) -> Self {
+        let tag = Rc::new(TypeTag::FixedCharArray(len));
+        Self { tag }
+    }
+
     pub fn tag(&self) -> &TypeTag {
         self.tag.as_ref()
     }
@@ -50,14 +97,26 @@ impl Clone for Type {
 impl fmt::Display for TypeTag {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            TypeTag::Int => write!(f, "int"),
+            TypeTag::Bool => write!(f, "BOOLEAN"),
+            TypeTag::Char => write!(f, "CHAR"),
+            TypeTag::CharArray => write!(f, "ARRAY OF CHAR"),
+            TypeTag::FixedCharArray(len) => write!(f, "ARRAY {len} OF CHAR"),
+            TypeTag::Int => write!(f, "INTEGER"),
+            TypeTag::Real => write!(f, "REAL"),
         }
     }
 }
 
 impl fmt::Display for TypeProc {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "procedure;")?;
+        write!(f, "procedure(")?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{param}")?;
+        }
+        write!(f, ");")?;
         if let Some(t_return) = &self.t_return {
             write!(f, " {t_return}")?;
         }
@@ -76,14 +135,18 @@ impl PartialEq for TypeTag {
         use TypeTag::*;
 
         match (self, other) {
-            (Int, Int) => true,
+            (FixedCharArray(a), FixedCharArray(b)) => a == b,
+            _ => matches!(
+                (self, other),
+                (Bool, Bool) | (Char, Char) | (CharArray, CharArray) | (Int, Int) | (Real, Real)
+            ),
         }
     }
 }
 
 impl PartialEq for TypeProc {
     fn eq(&self, other: &Self) -> bool {
-        self.t_return.eq(&other.t_return)
+        self.params.eq(&other.params) && self.t_return.eq(&other.t_return)
     }
 }
 
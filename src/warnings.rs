@@ -0,0 +1,407 @@
+//! Warnings about code that compiles but is probably a mistake.
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::ast::src::{Decl, Expr, Module, QualIdent, Stmt};
+use crate::compiler::terminates;
+use crate::json;
+use crate::span::Span;
+
+/// Enumerates all possible warnings.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WarningTag {
+    /// A procedure that isn't exported is never called.
+    UnusedProcedure(String),
+
+    /// A variable that isn't exported is never used.
+    UnusedVariable(String),
+
+    /// An imported module is never used.
+    UnusedImport(String),
+
+    /// A statement follows a `RETURN` statement or a `HALT`.
+    UnreachableCode,
+
+    /// A pragma isn't known, so has no effect.
+    UnknownPragma(String),
+}
+
+/// The codes of all possible warnings, in the order they're listed in
+/// [`WarningTag`].
+pub const CODES: [&str; 5] = ["W001", "W002", "W003", "W004", "W005"];
+
+impl WarningTag {
+    /// The warning's stable code, used to suppress it.
+    pub fn code(&self) -> &'static str {
+        use WarningTag::*;
+
+        match self {
+            UnusedProcedure(_) => CODES[0],
+            UnusedVariable(_) => CODES[1],
+            UnusedImport(_) => CODES[2],
+            UnreachableCode => CODES[3],
+            UnknownPragma(_) => CODES[4],
+        }
+    }
+}
+
+impl fmt::Display for WarningTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use WarningTag::*;
+
+        match self {
+            UnusedProcedure(name) => write!(f, "procedure `{name}` is never called"),
+            UnusedVariable(name) => write!(f, "variable `{name}` is never used"),
+            UnusedImport(alias) => write!(f, "imported module `{alias}` is never used"),
+            UnreachableCode => write!(f, "statement is unreachable"),
+            UnknownPragma(text) => write!(f, "unknown pragma `{text}` is ignored"),
+        }
+    }
+}
+
+/// A warning, and where in the source it is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub tag: WarningTag,
+    pub span: Span,
+}
+
+impl Warning {
+    pub fn new(tag: WarningTag, span: Span) -> Self {
+        Self { tag, span }
+    }
+
+    /// Formats the warning followed by the source line it's on, with carets
+    /// under the warning's span.
+    pub fn render(&self, source: &str) -> String {
+        let mut text = self.to_string();
+        if let Some(snippet) = self.span.snippet(source) {
+            text.push('\n');
+            text.push_str(&snippet);
+        }
+        text
+    }
+
+    /// Formats the warning as a JSON object, see `json::diagnostic`.
+    pub fn to_json(&self, file: &str, source: &str) -> String {
+        let message = self.tag.to_string();
+        json::diagnostic(
+            "warning",
+            self.tag.code(),
+            &message,
+            None,
+            file,
+            self.span,
+            source,
+        )
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "warning[{}] at line {}, column {}: {}",
+            self.tag.code(),
+            self.span.line,
+            self.span.column,
+            self.tag
+        )
+    }
+}
+
+/// Which warnings are reported, and whether they fail compilation.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Whether any warning fails compilation.
+    pub deny: bool,
+
+    /// The codes of warnings that aren't reported.
+    pub allow: Vec<String>,
+}
+
+impl Config {
+    /// Whether a warning is reported.
+    pub fn is_reported(&self, warning: &Warning) -> bool {
+        !self.allow.iter().any(|code| code == warning.tag.code())
+    }
+}
+
+/// Checks a module for warnings, in source order.
+pub fn check(module: &Module) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut uses = Uses::default();
+    let procs: HashSet<&str> = module
+        .decls
+        .iter()
+        .filter_map(|decl| match decl {
+            Decl::Proc(proc) => Some(proc.name.as_str()),
+            Decl::Var(_) => None,
+        })
+        .collect();
+
+    for decl in module.decls.iter() {
+        let Decl::Proc(proc) = decl else {
+            continue;
+        };
+
+        let mut locals: HashSet<&str> = proc.params.iter().map(|p| p.name.as_str()).collect();
+        locals.extend(proc.locals.iter().map(|l| l.name.as_str()));
+        uses.scope = Scope {
+            proc: Some(&proc.name),
+            locals,
+            used: HashSet::new(),
+        };
+        uses.stmts(&proc.body);
+        check_unreachable(&proc.body, &procs, &mut warnings);
+
+        for local in proc.locals.iter() {
+            if !uses.scope.used.contains(local.name.as_str()) {
+                let tag = WarningTag::UnusedVariable(local.name.clone());
+                warnings.push(Warning::new(tag, local.span));
+            }
+        }
+    }
+
+    uses.scope = Scope::default();
+    uses.stmts(&module.body);
+    check_unreachable(&module.body, &procs, &mut warnings);
+
+    for decl in module.decls.iter() {
+        let (name, export, tag) = match decl {
+            Decl::Proc(proc) => (
+                &proc.name,
+                proc.export,
+                WarningTag::UnusedProcedure(proc.name.clone()),
+            ),
+            Decl::Var(var) => (
+                &var.name,
+                var.export,
+                WarningTag::UnusedVariable(var.name.clone()),
+            ),
+        };
+        if !export && !uses.globals.contains(name.as_str()) {
+            warnings.push(Warning::new(tag, decl.span()));
+        }
+    }
+
+    for import in module.imports.iter() {
+        if !uses.modules.contains(import.alias.as_str()) {
+            let tag = WarningTag::UnusedImport(import.alias.clone());
+            warnings.push(Warning::new(tag, import.span));
+        }
+    }
+
+    for pragma in module.pragmas.iter().filter(|p| p.setting().is_none()) {
+        let tag = WarningTag::UnknownPragma(pragma.text.clone());
+        warnings.push(Warning::new(tag, pragma.span));
+    }
+
+    warnings.sort_by_key(|w| (w.span.line, w.span.column));
+    warnings
+}
+
+/// Warns about the first statement after a `RETURN` statement or a call of
+/// the predeclared `HALT`, unless `procs`, the module's procedures, hides it.
+fn check_unreachable(stmts: &[Stmt], procs: &HashSet<&str>, warnings: &mut Vec<Warning>) {
+    let returned = stmts
+        .iter()
+        .position(|s| terminates(s, |name| procs.contains(name)));
+    if let Some(stmt) = returned.and_then(|i| stmts.get(i + 1)) {
+        warnings.push(Warning::new(WarningTag::UnreachableCode, stmt.span()));
+    }
+}
+
+/// The names a statement sequence is checked in.
+#[derive(Default)]
+struct Scope<'a> {
+    /// The procedure being checked, whose calls to itself aren't uses.
+    proc: Option<&'a str>,
+
+    /// The procedure's parameters and local variables.
+    locals: HashSet<&'a str>,
+
+    /// The locals used so far.
+    used: HashSet<&'a str>,
+}
+
+/// The names used in a module.
+#[derive(Default)]
+struct Uses<'a> {
+    scope: Scope<'a>,
+
+    /// The module-level names used outside their own declaration.
+    globals: HashSet<&'a str>,
+
+    /// The import aliases used.
+    modules: HashSet<&'a str>,
+}
+
+impl<'a> Uses<'a> {
+    fn stmts(&mut self, stmts: &'a [Stmt]) {
+        for stmt in stmts.iter() {
+            match stmt {
+                Stmt::Assign(assign) => {
+                    self.name(&assign.var.name);
+                    self.expr(&assign.expr);
+                }
+                Stmt::Call(call) => {
+                    self.name(&call.proc);
+                    call.args.iter().for_each(|arg| self.expr(arg));
+                }
+                Stmt::Return(stmt_return) => {
+                    if let Some(expr) = &stmt_return.expr {
+                        self.expr(expr);
+                    }
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        match expr {
+            Expr::Call(call) => {
+                self.name(&call.proc);
+                call.args.iter().for_each(|arg| self.expr(arg));
+            }
+            Expr::Char(_) | Expr::Integer(_) | Expr::Real(_) | Expr::Str(_) => (),
+            Expr::Var(var) => self.name(&var.name),
+        }
+    }
+
+    fn name(&mut self, name: &'a QualIdent) {
+        let ident = name.name.as_str();
+        if let Some(module) = &name.module {
+            self.modules.insert(module);
+        } else if self.scope.locals.contains(ident) {
+            self.scope.used.insert(ident);
+        } else if self.scope.proc != Some(ident) {
+            self.globals.insert(ident);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn warnings(source: &str) -> Vec<(WarningTag, usize)> {
+        let module = Parser::new(source).module().unwrap();
+        check(&module)
+            .into_iter()
+            .map(|w| (w.tag, w.span.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_clean() {
+        let source = "MODULE M;
+VAR x*: INTEGER;
+PROCEDURE P(a: INTEGER): INTEGER;
+  VAR b: INTEGER;
+BEGIN
+  b := a;
+  RETURN b
+END;
+BEGIN
+  x := P(1)
+END M.";
+        assert_eq!(warnings(source), vec![]);
+    }
+
+    #[test]
+    fn test_check_unused() {
+        let source = "MODULE M;
+IMPORT A, B := C;
+VAR x, y*: INTEGER;
+PROCEDURE P;
+  VAR a, b: INTEGER;
+BEGIN
+  a := 1;
+  P
+END;
+PROCEDURE Q*;
+BEGIN
+  B.R
+END;
+END M.";
+        use WarningTag::*;
+        assert_eq!(
+            warnings(source),
+            vec![
+                (UnusedImport("A".to_string()), 2),
+                (UnusedVariable("x".to_string()), 3),
+                (UnusedProcedure("P".to_string()), 4),
+                (UnusedVariable("b".to_string()), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_local_shadows_global() {
+        let source = "MODULE M;
+VAR x: INTEGER;
+PROCEDURE P*;
+  VAR x: INTEGER;
+BEGIN
+  x := 1
+END;
+END M.";
+        assert_eq!(
+            warnings(source),
+            vec![(WarningTag::UnusedVariable("x".to_string()), 2)]
+        );
+    }
+
+    #[test]
+    fn test_check_unreachable() {
+        let source = "MODULE M;
+PROCEDURE P*(): INTEGER;
+BEGIN
+  RETURN 1;
+  P;
+  P
+END;
+END M.";
+        assert_eq!(warnings(source), vec![(WarningTag::UnreachableCode, 5)]);
+    }
+
+    #[test]
+    fn test_check_unreachable_after_halt() {
+        let source = "MODULE M;
+VAR x: INTEGER;
+BEGIN
+  HALT(1);
+  x := 1
+END M.";
+        assert_eq!(warnings(source), vec![(WarningTag::UnreachableCode, 5)]);
+        let source = "MODULE M;
+VAR x: INTEGER;
+PROCEDURE HALT(n: INTEGER);
+END;
+BEGIN
+  HALT(1);
+  x := 1
+END M.";
+        assert!(warnings(source).is_empty());
+    }
+
+    #[test]
+    fn test_check_unknown_pragma() {
+        let source = "MODULE M;\n(*$INDEX-*)\n(*$ASSERT-*)\nEND M.";
+        assert_eq!(
+            warnings(source),
+            vec![(WarningTag::UnknownPragma("INDEX-".to_string()), 2)]
+        );
+    }
+
+    #[test]
+    fn test_config_allow() {
+        let warning = Warning::new(WarningTag::UnreachableCode, Span::at_line(1));
+        let mut config = Config::default();
+        assert!(config.is_reported(&warning));
+        config.allow.push("W004".to_string());
+        assert!(!config.is_reported(&warning));
+    }
+}